@@ -0,0 +1,277 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::em8080::{BusCycle, Em8080, IOState, MEMORY_SIZE};
+
+mod acr;
+pub use acr::Cassette;
+pub mod cli;
+pub mod console;
+pub mod dcdd;
+pub mod panel;
+mod paper_tape;
+pub use paper_tape::PaperTape;
+mod sio;
+pub use sio::{SerialCard, SerialPort};
+pub mod tape;
+#[cfg(test)]
+mod tests;
+
+use panel::Lights;
+use tape::Tape;
+
+// The MITS Altair 8800: an 8080 on an S-100 bus with whatever memory and
+// I/O cards were plugged in. Cards are `Device`s answering on their own
+// ports; reads of ports nothing answers float high.
+
+/// An I/O card on the bus
+pub trait Device {
+    /// Value for an IN from `port`, `None` if the card doesn't decode it
+    fn read(&mut self, port: u8) -> Option<u8>;
+    /// Handles an OUT to `port`, returns false if the card doesn't decode it
+    fn write(&mut self, port: u8, value: u8) -> bool;
+    /// Called after every instruction with the CPU cycles it took
+    fn tick(&mut self, _cycles: u64) {}
+}
+
+/// Whatever is at the other end of a serial port: a terminal, a file...
+pub trait SerialLine {
+    /// Next byte that has arrived, if any
+    fn receive(&mut self) -> Option<u8>;
+    fn send(&mut self, byte: u8);
+    /// Passing of time, for lines that run at a set speed
+    fn tick(&mut self, _cycles: u64) {}
+}
+
+/// Serial lines are shared between the card and the front end
+pub type SharedLine = Rc<RefCell<dyn SerialLine>>;
+
+struct AltairIO {
+    devices: Vec<Box<dyn Device>>,
+    /// The front panel's address switches
+    switches: u16,
+}
+
+impl AltairIO {
+    /// Port the sense switches (A8-A15) are read on. Programs such as BASIC
+    /// read them at start up to choose their terminal port.
+    const SENSE_PORT: u8 = 0xFF;
+}
+
+impl IOState for AltairIO {
+    fn input(&mut self, _cpu: &Em8080, port: u8) -> u8 {
+        if port == Self::SENSE_PORT {
+            return (self.switches >> 8) as u8;
+        }
+
+        self.devices
+            .iter_mut()
+            .find_map(|device| device.read(port))
+            .unwrap_or(0xFF)
+    }
+
+    fn output(&mut self, _cpu: &Em8080, port: u8, value: u8) {
+        for device in self.devices.iter_mut() {
+            if device.write(port, value) {
+                return;
+            }
+        }
+    }
+}
+
+pub struct Altair {
+    cpu: Em8080,
+    io_state: AltairIO,
+    ram_size: usize,
+    cycles: u64,
+    /// False while stopped from the front panel
+    running: bool,
+    lights: Lights,
+}
+
+impl Altair {
+    pub const CLOCK_HZ: u64 = 2_000_000;
+
+    /// Machine with `ram_size` bytes of memory from address 0 and no cards
+    pub fn new(ram_size: usize) -> Self {
+        let mut cpu = Em8080::new();
+        cpu.set_memory_limit(ram_size);
+        cpu.set_bus_monitor(true);
+        // Powers on with interrupts off
+        cpu.reset();
+
+        Self {
+            cpu,
+            io_state: AltairIO {
+                devices: Vec::new(),
+                switches: 0,
+            },
+            ram_size: ram_size.min(MEMORY_SIZE),
+            cycles: 0,
+            running: true,
+            lights: Lights::default(),
+        }
+    }
+
+    pub fn add_device(&mut self, device: Box<dyn Device>) {
+        self.io_state.devices.push(device);
+    }
+
+    /// Copies `data` into memory at `address`
+    pub fn load(&mut self, address: u16, data: &[u8]) -> Result<(), String> {
+        let end = address as usize + data.len();
+        if end > self.ram_size {
+            return Err(format!(
+                "{} bytes at {:04X} don't fit in {}K of memory",
+                data.len(),
+                address,
+                self.ram_size / 1024
+            ));
+        }
+
+        self.cpu.memory[address as usize..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Loads every record of a tape and jumps to its start address
+    pub fn load_tape(&mut self, tape: &Tape) -> Result<(), String> {
+        for (address, data) in &tape.records {
+            self.load(*address, data)?;
+        }
+        if let Some(start) = tape.start {
+            self.jump(start);
+        }
+        Ok(())
+    }
+
+    /// Continues execution at `address`
+    pub fn jump(&mut self, address: u16) {
+        self.cpu.set_pc(address);
+    }
+
+    /// Runs for at least `cycles` CPU cycles, or does nothing while stopped
+    pub fn run(&mut self, cycles: u64) {
+        if !self.running {
+            return;
+        }
+
+        let end = self.cycles + cycles;
+        while self.cycles < end {
+            self.step();
+        }
+
+        self.lights = Lights::from_cycles(&self.cpu.take_bus_cycles());
+        self.lights.inte = self.cpu.interrupts_enabled();
+    }
+
+    /// CPU cycles since power on
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // Front panel. Like the real one it works by jamming instructions onto
+    // the bus, so EXAMINE moves the program counter and DEPOSIT writes at it.
+
+    pub fn switches(&self) -> u16 {
+        self.io_state.switches
+    }
+
+    pub fn set_switches(&mut self, switches: u16) {
+        self.io_state.switches = switches;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// RUN
+    pub fn start(&mut self) {
+        self.running = true;
+        self.lights.wait = false;
+    }
+
+    /// STOP, at the end of the current instruction
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.show_fetch();
+    }
+
+    /// Steps one whole instruction. The real SINGLE STEP switch stops after
+    /// every machine cycle, but `emulate` runs an instruction at a time.
+    pub fn single_step(&mut self) {
+        self.step();
+        let cycles = self.cpu.take_bus_cycles();
+        self.running = false;
+        match cycles.last() {
+            Some(&cycle) => self.latch(cycle),
+            None => self.show_fetch(),
+        }
+    }
+
+    /// EXAMINE: jumps to the address on the switches and shows what is there
+    pub fn examine(&mut self) {
+        self.jump(self.io_state.switches);
+        self.show_fetch();
+    }
+
+    /// EXAMINE NEXT
+    pub fn examine_next(&mut self) {
+        self.jump(self.cpu.pc().wrapping_add(1));
+        self.show_fetch();
+    }
+
+    /// DEPOSIT: writes the low eight switches at the current address.
+    /// Writes above the installed memory are lost.
+    pub fn deposit(&mut self) {
+        let address = self.cpu.pc() as usize;
+        if address < self.ram_size {
+            self.cpu.memory[address] = self.io_state.switches as u8;
+        }
+        self.show_fetch();
+    }
+
+    /// DEPOSIT NEXT
+    pub fn deposit_next(&mut self) {
+        self.jump(self.cpu.pc().wrapping_add(1));
+        self.deposit();
+    }
+
+    /// RESET: the CPU starts again from address 0, memory is untouched
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.cpu.take_bus_cycles();
+        if !self.running {
+            self.show_fetch();
+        }
+    }
+
+    pub fn lights(&self) -> &Lights {
+        &self.lights
+    }
+
+    /// Runs one instruction and lets the cards know how long it took
+    fn step(&mut self) {
+        let cycles = self.cpu.emulate(&mut self.io_state);
+        self.cycles += cycles;
+        for device in self.io_state.devices.iter_mut() {
+            device.tick(cycles);
+        }
+    }
+
+    /// Shows the opcode fetch about to happen at the program counter
+    fn show_fetch(&mut self) {
+        let address = self.cpu.pc();
+        let data = if (address as usize) < self.ram_size { self.cpu.memory[address as usize] } else { 0xFF };
+        self.latch(BusCycle {
+            status: BusCycle::MEMR | BusCycle::M1 | BusCycle::WO,
+            address,
+            data,
+        });
+    }
+
+    fn latch(&mut self, cycle: BusCycle) {
+        self.lights = Lights::latched(cycle);
+        self.lights.inte = self.cpu.interrupts_enabled();
+        self.lights.wait = !self.running;
+    }
+}
@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use super::{Altair, PaperTape, SerialLine};
+use crate::kcs::{self, Encoder};
+use crate::wav::Wav;
+
+/// The 88-ACR audio cassette interface: an 88-SIO (at port 6 as shipped)
+/// wired to a Kansas City Standard modem. Playback decodes a recording up
+/// front and feeds it to the port at 300 baud; whatever the program sends
+/// is recorded as it would have been on tape, gaps and all.
+pub struct Cassette {
+    playback: PaperTape,
+    recording: Encoder,
+    /// Whether anything has been sent, the recorder starts with the first byte
+    recorded: bool,
+    /// CPU cycles since the last byte sent
+    since_send: u64,
+}
+
+impl Cassette {
+    pub const PORT: u8 = 0x06;
+    pub const SAMPLE_RATE: u32 = 44100;
+
+    /// Longest silence recorded between bytes, so a program that sits
+    /// waiting doesn't fill the disk with mark tone
+    const MAX_GAP_SECONDS: f64 = 1.0;
+
+    /// Cassette with `data` already on it
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            playback: PaperTape::with_frame(data, kcs::BAUD, kcs::BITS_PER_BYTE),
+            recording: Encoder::new(Self::SAMPLE_RATE),
+            recorded: false,
+            since_send: 0,
+        }
+    }
+
+    /// Cassette holding the KCS recording in a WAV file
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let wav = Wav::open(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let data = kcs::decode(&wav.to_mono(), wav.sample_rate);
+        if data.is_empty() {
+            return Err(format!("{}: no Kansas City Standard data found", path.display()));
+        }
+        Ok(Self::new(data))
+    }
+
+    /// Whether playback has reached the end of the tape
+    pub fn is_finished(&self) -> bool {
+        self.playback.is_finished()
+    }
+
+    /// What has been sent so far, as audio
+    pub fn recording(&self) -> Wav {
+        Wav {
+            sample_rate: self.recording.sample_rate(),
+            channels: 1,
+            samples: self.recording.samples().to_vec(),
+        }
+    }
+
+    /// Saves the recording as a WAV file, returning false if nothing was sent
+    pub fn save(&self, path: &Path) -> Result<bool, String> {
+        if !self.recorded {
+            return Ok(false);
+        }
+        self.recording()
+            .save(path)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        Ok(true)
+    }
+}
+
+impl SerialLine for Cassette {
+    fn receive(&mut self) -> Option<u8> {
+        self.playback.receive()
+    }
+
+    fn send(&mut self, byte: u8) {
+        let frame = Altair::CLOCK_HZ * kcs::BITS_PER_BYTE as u64 / kcs::BAUD as u64;
+
+        if !self.recorded {
+            self.recording.idle(kcs::LEADER_SECONDS);
+            self.recorded = true;
+        } else if self.since_send > frame {
+            // Bytes sent faster than 300 baud simply follow one another
+            let gap = (self.since_send - frame) as f64 / Altair::CLOCK_HZ as f64;
+            self.recording.idle(gap.min(Self::MAX_GAP_SECONDS));
+        }
+
+        self.recording.byte(byte);
+        self.since_send = 0;
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.playback.tick(cycles);
+        self.since_send = self.since_send.saturating_add(cycles);
+    }
+}
@@ -0,0 +1,430 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::console::HostConsole;
+use super::tape::Tape;
+use super::dcdd::Dcdd;
+use super::{panel, Altair, Cassette, PaperTape, SerialCard, SerialPort, SharedLine};
+use crate::config::{self, Settings};
+use crate::disk::DiskImage;
+use crate::kcs;
+use crate::pacing::{Pacer, Speed};
+use crate::session::QuietTimer;
+use crate::wav::Wav;
+
+/// Command line options of `emulator-8080 altair`. As with the arcade
+/// machines, every option can also be set from a `--config` file.
+struct Options {
+    /// Bytes of RAM from address 0
+    ram: usize,
+    /// Binary images and the address to load them at
+    load: Vec<(PathBuf, u16)>,
+    /// MITS checksum loader tape image
+    tape: Option<PathBuf>,
+    /// Where to start running, overrides the tape's start address
+    start: Option<u16>,
+    sense: u8,
+    uncapped: bool,
+    /// Power on stopped, at the front panel
+    panel: bool,
+    /// Paper tape to put in the reader, and file to punch to
+    reader: Option<PathBuf>,
+    punch: Option<PathBuf>,
+    /// Serial port the paper tape reader and punch are on
+    reader_card: SerialCard,
+    reader_port: u8,
+    reader_baud: u32,
+    /// Kansas City Standard recordings to play into and record from the 88-ACR
+    cassette: Option<PathBuf>,
+    record: Option<PathBuf>,
+    /// Disk images for the 88-DCDD's drives in order, and whether each is
+    /// write protected. Nothing boots from them by itself: load the disk
+    /// boot loader PROM too, with `--load dbl.bin@0xFF00 --start 0xFF00`.
+    disks: Vec<(PathBuf, bool)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            ram: 64 * 1024,
+            load: Vec::new(),
+            tape: None,
+            start: None,
+            sense: 0,
+            uncapped: false,
+            panel: false,
+            reader: None,
+            punch: None,
+            reader_card: SerialCard::Sio2,
+            reader_port: SerialPort::SIO2_SECOND_PORT,
+            reader_baud: 110,
+            cassette: None,
+            record: None,
+            disks: Vec::new(),
+        }
+    }
+}
+
+impl Settings for Options {
+    const FLAGS: &'static [&'static str] = &["uncapped", "panel"];
+
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        let flag = || value.map_or(Ok(true), config::parse_bool);
+        let value = || value.ok_or(format!("{} needs a value", name));
+        let address = |text: &str| -> Result<u16, String> {
+            parse_number(text)
+                .filter(|&address| address <= 0xFFFF)
+                .map(|address| address as u16)
+                .ok_or(format!("Invalid address: {}", text))
+        };
+
+        match name {
+            "ram" => self.ram = parse_size(value()?)?,
+            "load" => {
+                let value = value()?;
+                let (path, at) = match value.rsplit_once('@') {
+                    Some((path, at)) => (path, address(at)?),
+                    None => (value, 0),
+                };
+                self.load.push((path.into(), at));
+            }
+            "tape" => self.tape = Some(value()?.into()),
+            "start" => self.start = Some(address(value()?)?),
+            "sense" => {
+                let value = value()?;
+                self.sense = parse_number(value)
+                    .filter(|&sense| sense <= 0xFF)
+                    .ok_or(format!("Invalid sense switch setting: {}", value))? as u8;
+            }
+            "uncapped" => self.uncapped = flag()?,
+            "panel" => self.panel = flag()?,
+            "cassette" => self.cassette = Some(value()?.into()),
+            "record" => self.record = Some(value()?.into()),
+            "disk" | "disk-ro" => {
+                if self.disks.len() == Dcdd::DRIVES {
+                    return Err(format!("The 88-DCDD only has {} drives", Dcdd::DRIVES));
+                }
+                self.disks.push((value()?.into(), name == "disk-ro"));
+            }
+            "reader" => self.reader = Some(value()?.into()),
+            "punch" => self.punch = Some(value()?.into()),
+            "reader-card" => {
+                self.reader_card = match value()? {
+                    "sio" => SerialCard::Sio,
+                    "2sio" => SerialCard::Sio2,
+                    card => return Err(format!("Unknown serial card: {}, expected sio or 2sio", card)),
+                }
+            }
+            "reader-port" => {
+                let value = value()?;
+                self.reader_port = parse_number(value)
+                    .filter(|&port| port <= 0xFE)
+                    .ok_or(format!("Invalid port: {}", value))? as u8;
+            }
+            "reader-baud" => {
+                let value = value()?;
+                self.reader_baud = parse_number(value)
+                    .filter(|&baud| baud > 0 && baud <= 1_000_000)
+                    .ok_or(format!("Invalid baud rate: {}", value))? as u32;
+            }
+            _ => return Err(format!("Unknown option: {}", name)),
+        }
+
+        Ok(())
+    }
+
+    fn usage() {
+        println!("Usage: emulator-8080 altair [--config FILE] [--ram SIZE] [--load FILE[@ADDR]]... [--tape FILE]");
+        println!("                            [--start ADDR] [--sense SWITCHES] [--uncapped] [--panel]");
+        println!("                            [--reader FILE] [--punch FILE] [--reader-card sio|2sio] [--reader-port PORT]");
+        println!("                            [--reader-baud BAUD] [--cassette FILE.wav] [--record FILE.wav]");
+        println!("                            [--disk FILE]... [--disk-ro FILE]...");
+        println!("       To boot from disk, add --load dbl.bin@0xFF00 --start 0xFF00 with the disk boot loader PROM");
+        println!("       emulator-8080 altair kcs-encode FILE OUT.wav | kcs-decode FILE.wav OUT");
+    }
+}
+
+/// Decimal, `0x` hex or `0o` octal (as on the front panel)
+fn parse_number(text: &str) -> Option<u64> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(octal) = text.strip_prefix("0o") {
+        u64::from_str_radix(octal, 8).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Memory size in bytes, or in kilobytes with a `K` suffix
+fn parse_size(text: &str) -> Result<usize, String> {
+    let size = match text.strip_suffix(['K', 'k']) {
+        Some(kilobytes) => parse_number(kilobytes).map(|k| k * 1024),
+        None => parse_number(text),
+    };
+
+    size.filter(|&size| size > 0 && size <= 64 * 1024)
+        .map(|size| size as usize)
+        .ok_or(format!("RAM must be 1 byte to 64K, got {}", text))
+}
+
+/// Cycles run between checks of the console and the wall clock, 10 ms
+const SLICE: u64 = Altair::CLOCK_HZ / 100;
+
+pub fn main(args: impl Iterator<Item = String>) {
+    let mut args = args.peekable();
+    if let Some(command) = args.next_if(|arg| arg.starts_with("kcs-")) {
+        let args: Vec<String> = args.collect();
+        let result = match (command.as_str(), args.as_slice()) {
+            ("kcs-encode", [input, output]) => kcs_encode(input.as_ref(), output.as_ref()),
+            ("kcs-decode", [input, output]) => kcs_decode(input.as_ref(), output.as_ref()),
+            _ => Err("Usage: emulator-8080 altair kcs-encode FILE OUT.wav | kcs-decode FILE.wav OUT".to_string()),
+        };
+        if let Err(e) = result {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let options: Options = config::parse_args(args).unwrap_or_else(|e| {
+        println!("{}", e);
+        Options::usage();
+        std::process::exit(1);
+    });
+
+    if let Err(e) = run(&options) {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Records a file onto a Kansas City Standard cassette
+fn kcs_encode(input: &Path, output: &Path) -> Result<(), String> {
+    let data = std::fs::read(input).map_err(|e| format!("Could not read {}: {}", input.display(), e))?;
+    let wav = Wav {
+        sample_rate: Cassette::SAMPLE_RATE,
+        channels: 1,
+        samples: kcs::encode(&data, Cassette::SAMPLE_RATE),
+    };
+    wav.save(output).map_err(|e| format!("Could not write {}: {}", output.display(), e))?;
+    println!("{} bytes, {:.1} seconds of tape", data.len(), wav.samples.len() as f64 / wav.sample_rate as f64);
+    Ok(())
+}
+
+/// Reads the data back off a cassette recording
+fn kcs_decode(input: &Path, output: &Path) -> Result<(), String> {
+    let wav = Wav::open(input).map_err(|e| format!("Could not read {}: {}", input.display(), e))?;
+    let data = kcs::decode(&wav.to_mono(), wav.sample_rate);
+    std::fs::write(output, &data).map_err(|e| format!("Could not write {}: {}", output.display(), e))?;
+    println!("{} bytes", data.len());
+    Ok(())
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut altair = Altair::new(options.ram);
+
+    for (path, address) in &options.load {
+        let data = std::fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        altair.load(*address, &data)?;
+    }
+    if let Some(path) = &options.tape {
+        let data = std::fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let tape = Tape::parse(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+        altair.load_tape(&tape)?;
+        println!("Loaded {} bytes from {}", tape.loaded_bytes(), path.display());
+    }
+    if let Some(start) = options.start {
+        altair.jump(start);
+    }
+
+    println!("Altair 8800 with {}K of RAM. Ctrl-] for the front panel.", options.ram / 1024);
+
+    // The console is on both the 88-SIO and the first 2SIO channel, so
+    // programs find it whichever they were configured for
+    let console = Rc::new(RefCell::new(HostConsole::open().map_err(|e| e.to_string())?));
+    let line: SharedLine = console.clone();
+    altair.add_device(Box::new(SerialPort::new(SerialCard::Sio, SerialPort::SIO_PORT, line.clone())));
+    altair.add_device(Box::new(SerialPort::new(SerialCard::Sio2, SerialPort::SIO2_PORT, line)));
+    if !options.disks.is_empty() {
+        let mut controller = Dcdd::new(Dcdd::PORT);
+        for (drive, (path, write_protected)) in options.disks.iter().enumerate() {
+            let disk = DiskImage::open(path, *write_protected)?;
+            let geometry = disk.geometry();
+            println!(
+                "Drive {}: {} ({} tracks of {} x {} byte sectors{})",
+                drive,
+                path.display(),
+                geometry.tracks,
+                geometry.sectors,
+                geometry.sector_size,
+                if disk.is_write_protected() { ", write protected" } else { "" }
+            );
+            controller.insert(drive, disk);
+        }
+        altair.add_device(Box::new(controller));
+    }
+
+    let mut paper_tape = None;
+    if options.reader.is_some() || options.punch.is_some() {
+        let mut tape = match &options.reader {
+            Some(path) => PaperTape::open(path, options.reader_baud)
+                .map_err(|e| format!("Could not read {}: {}", path.display(), e))?,
+            None => PaperTape::new(Vec::new(), options.reader_baud),
+        };
+        if let Some(path) = &options.punch {
+            tape.punch_to_file(path)
+                .map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+        }
+        let tape = Rc::new(RefCell::new(tape));
+        let line: SharedLine = tape.clone();
+        altair.add_device(Box::new(SerialPort::new(options.reader_card, options.reader_port, line)));
+        paper_tape = Some(tape);
+    }
+    let mut cassette = None;
+    let mut cassette_playing = options.cassette.is_some();
+    if options.cassette.is_some() || options.record.is_some() {
+        let tape = match &options.cassette {
+            Some(path) => {
+                let tape = Cassette::open(path)?;
+                println!("Cassette {} loaded", path.display());
+                tape
+            }
+            None => Cassette::new(Vec::new()),
+        };
+        let tape = Rc::new(RefCell::new(tape));
+        let line: SharedLine = tape.clone();
+        altair.add_device(Box::new(SerialPort::new(SerialCard::Sio, Cassette::PORT, line)));
+        cassette = Some(tape);
+    }
+    altair.set_switches((options.sense as u16) << 8);
+    if options.panel {
+        altair.stop();
+    }
+
+    let mut pacer = Pacer::new(Altair::CLOCK_HZ);
+    if options.uncapped {
+        pacer.set_speed(Speed::Uncapped, 0);
+    }
+
+    // With piped input, stop once it has all been read and the program has
+    // gone quiet for a second
+    let mut quiet = QuietTimer::new(Altair::CLOCK_HZ);
+
+    loop {
+        if !altair.is_running() || console.borrow().take_escape() {
+            altair.stop();
+            pacer.set_paused(true, altair.cycles());
+            if !front_panel(&mut altair, &mut console.borrow_mut()) {
+                break;
+            }
+            pacer.set_paused(false, altair.cycles());
+        }
+
+        altair.run(SLICE);
+
+        if options.reader.is_some() && paper_tape.as_ref().is_some_and(|tape| tape.borrow().is_finished()) {
+            println!("\n[End of paper tape]");
+            paper_tape = None;
+        }
+        if cassette_playing && cassette.as_ref().is_some_and(|tape| tape.borrow().is_finished()) {
+            println!("\n[End of cassette]");
+            cassette_playing = false;
+        }
+
+        let console = console.borrow();
+        if console.input_closed() && quiet.is_quiet(console.bytes_sent(), altair.cycles()) {
+            break;
+        }
+        drop(console);
+
+        pacer.wait(altair.cycles());
+    }
+
+    // Restores the terminal before printing
+    drop(altair);
+    drop(console);
+    println!();
+
+    if let (Some(path), Some(cassette)) = (&options.record, cassette) {
+        if cassette.borrow().save(path)? {
+            println!("Recorded cassette saved to {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+const PANEL_HELP: &str = "\
+sw VALUE  set the switches       x [ADDR]  examine        xn        examine next
+d [BYTE]  deposit                dn [BYTE] deposit next   s [N]     single step
+reset     reset                  r         run            q         quit";
+
+/// Operates the front panel from typed commands until it is told to run,
+/// returning false to quit. Numbers are octal as on the panel, or `0x` hex.
+fn front_panel(altair: &mut Altair, console: &mut HostConsole) -> bool {
+    println!("\n{}", PANEL_HELP);
+    loop {
+        print!("{}> ", panel::render(altair.lights(), altair.switches()));
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+
+        let line = match console.read_line() {
+            Some(line) => line,
+            None => return false,
+        };
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => continue,
+        };
+        let number = match words.next().map(parse_panel_number) {
+            Some(Some(number)) => Some(number),
+            Some(None) => {
+                println!("Not a number, use octal or 0x hex");
+                continue;
+            }
+            None => None,
+        };
+        let mut set_switches = |mask: u16| {
+            if let Some(number) = number {
+                altair.set_switches(altair.switches() & !mask | number & mask);
+            }
+        };
+
+        match command {
+            "sw" => set_switches(0xFFFF),
+            "x" => {
+                set_switches(0xFFFF);
+                altair.examine();
+            }
+            "xn" => altair.examine_next(),
+            "d" => {
+                set_switches(0x00FF);
+                altair.deposit();
+            }
+            "dn" => {
+                set_switches(0x00FF);
+                altair.deposit_next();
+            }
+            "s" => {
+                for _ in 0..number.unwrap_or(1) {
+                    altair.single_step();
+                }
+            }
+            "reset" => altair.reset(),
+            "r" => {
+                altair.start();
+                println!("Running. Ctrl-] for the front panel.");
+                return true;
+            }
+            "q" => return false,
+            _ => println!("{}", PANEL_HELP),
+        }
+    }
+}
+
+fn parse_panel_number(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => u16::from_str_radix(text, 8).ok(),
+    }
+}
@@ -0,0 +1,169 @@
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+
+use super::SerialLine;
+
+/// Ctrl-], as in telnet, since every other control key means something to
+/// the programs being run. The Altair brings up its front panel, machines
+/// without one quit.
+pub const ESCAPE_KEY: u8 = 0x1D;
+
+/// The host terminal as a serial line: stdin in raw mode, so keys reach the
+/// emulated machine one at a time and unechoed, and stdout.
+pub struct HostConsole {
+    input: Receiver<u8>,
+    input_closed: bool,
+    escape: Arc<AtomicBool>,
+    /// Terminal settings to put back on exit, when stdin is a terminal
+    saved: Option<libc::termios>,
+    bytes_sent: u64,
+}
+
+impl HostConsole {
+    pub fn open() -> io::Result<Self> {
+        let saved = if unsafe { libc::isatty(libc::STDIN_FILENO) } == 1 {
+            Some(raw_mode()?)
+        } else {
+            None
+        };
+        let interactive = saved.is_some();
+
+        // Reads block, so they happen on their own thread and the machine
+        // polls the channel
+        let (sender, input) = mpsc::channel();
+        let escape = Arc::new(AtomicBool::new(false));
+        let escape_flag = escape.clone();
+        std::thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                let byte = match byte {
+                    Ok(byte) => byte,
+                    Err(_) => break,
+                };
+
+                let byte = match byte {
+                    ESCAPE_KEY if interactive => {
+                        escape_flag.store(true, Ordering::Relaxed);
+                        continue;
+                    }
+                    // Piped text has Unix line endings, terminals send CR
+                    b'\n' if !interactive => b'\r',
+                    byte => byte,
+                };
+
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            input,
+            input_closed: false,
+            escape,
+            saved,
+            bytes_sent: 0,
+        })
+    }
+
+    /// Whether the escape key has been pressed since the last call
+    pub fn take_escape(&self) -> bool {
+        self.escape.swap(false, Ordering::Relaxed)
+    }
+
+    /// Waits for a line typed at the host, echoing it. `None` once stdin
+    /// has ended.
+    pub fn read_line(&mut self) -> Option<String> {
+        let mut stdout = io::stdout();
+        let mut line = String::new();
+        loop {
+            let byte = match self.input.recv() {
+                Ok(byte) => byte,
+                Err(_) => {
+                    self.input_closed = true;
+                    return None;
+                }
+            };
+
+            match byte {
+                b'\r' | b'\n' => {
+                    let _ = stdout.write_all(b"\n");
+                    let _ = stdout.flush();
+                    return Some(line);
+                }
+                // Backspace or delete
+                0x08 | 0x7F if line.pop().is_some() => {
+                    let _ = stdout.write_all(b"\x08 \x08");
+                }
+                byte if byte.is_ascii_graphic() || byte == b' ' => {
+                    line.push(byte as char);
+                    let _ = stdout.write_all(&[byte]);
+                }
+                _ => {}
+            }
+            let _ = stdout.flush();
+        }
+    }
+
+    /// Whether stdin has ended and everything from it has been received
+    pub fn input_closed(&self) -> bool {
+        self.input_closed
+    }
+
+    /// Bytes written to stdout so far, for noticing when output stops
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+}
+
+impl SerialLine for HostConsole {
+    fn receive(&mut self) -> Option<u8> {
+        match self.input.try_recv() {
+            Ok(byte) => Some(byte),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.input_closed = true;
+                None
+            }
+        }
+    }
+
+    fn send(&mut self, byte: u8) {
+        // Strip the parity bit some programs set
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&[byte & 0x7F]);
+        let _ = stdout.flush();
+        self.bytes_sent += 1;
+    }
+}
+
+impl Drop for HostConsole {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.as_ref() {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+            }
+        }
+    }
+}
+
+/// Switches stdin to raw mode and returns the previous settings
+fn raw_mode() -> io::Result<libc::termios> {
+    unsafe {
+        let mut saved: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut saved) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = saved;
+        libc::cfmakeraw(&mut raw);
+        // Keep output processing so '\n' from the host side still works
+        raw.c_oflag = saved.c_oflag;
+        if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(saved)
+    }
+}
@@ -0,0 +1,245 @@
+use super::Device;
+use crate::disk::{DiskError, DiskImage};
+
+// The MITS 88-DCDD floppy controller. It does very little itself: the
+// program steps the head, waits for the sector it wants to come round and
+// then moves every byte through the data port, formatting included, which
+// is why Altair disks have 137 byte sectors where the software keeps the
+// track, sector and checksum. The controller here works the same way with
+// whatever geometry the image has, though Altair software expects
+// `Geometry::ALTAIR_8INCH` or the minidisk.
+
+/// Status bits on port 0x08. The port is active low: a bit reads 0 when
+/// its condition is true.
+pub mod status {
+    /// Ready for the next byte to write
+    pub const ENWD: u8 = 1 << 0;
+    /// The head can be stepped
+    pub const MOVE_HEAD: u8 = 1 << 1;
+    /// The head is loaded and settled
+    pub const HEAD: u8 = 1 << 2;
+    pub const INTE: u8 = 1 << 5;
+    pub const TRACK_0: u8 = 1 << 6;
+    /// New read data available
+    pub const NRDA: u8 = 1 << 7;
+}
+
+/// Bits of the control port, 0x09
+pub mod control {
+    pub const STEP_IN: u8 = 1 << 0;
+    pub const STEP_OUT: u8 = 1 << 1;
+    pub const HEAD_LOAD: u8 = 1 << 2;
+    pub const HEAD_UNLOAD: u8 = 1 << 3;
+    pub const INTERRUPT_ENABLE: u8 = 1 << 4;
+    pub const INTERRUPT_DISABLE: u8 = 1 << 5;
+    pub const WRITE_ENABLE: u8 = 1 << 7;
+}
+
+/// One of up to 16 drives
+#[derive(Default)]
+struct Drive {
+    disk: Option<DiskImage>,
+    track: usize,
+    head_loaded: bool,
+    /// Whether the user has been told the disk is write protected
+    warned: bool,
+}
+
+pub struct Dcdd {
+    base: u8,
+    drives: Vec<Drive>,
+    selected: Option<usize>,
+    interrupts: bool,
+    /// Sector under the head, moving on every time the program reads the
+    /// sector position, which is all the rotation a polling loop can see
+    sector: usize,
+    /// Next byte of the sector to read, past the end until a sector
+    /// comes round
+    position: usize,
+    /// Sector being written, `None` when not writing
+    write: Option<Vec<u8>>,
+}
+
+impl Dcdd {
+    /// Port the controller answers on as shipped, it uses three from there
+    pub const PORT: u8 = 0x08;
+    pub const DRIVES: usize = 16;
+
+    pub fn new(base: u8) -> Self {
+        Self {
+            base,
+            drives: (0..Self::DRIVES).map(|_| Drive::default()).collect(),
+            selected: None,
+            interrupts: false,
+            sector: 0,
+            position: usize::MAX,
+            write: None,
+        }
+    }
+
+    /// Puts a disk in `drive`, returning the one that was there
+    pub fn insert(&mut self, drive: usize, disk: DiskImage) -> Option<DiskImage> {
+        self.drives[drive].warned = false;
+        self.drives[drive].disk.replace(disk)
+    }
+
+    fn drive(&mut self) -> Option<&mut Drive> {
+        self.drives.get_mut(self.selected?)
+    }
+
+    fn sector_size(&self) -> usize {
+        let drive = &self.drives[self.selected.unwrap_or(0)];
+        drive.disk.as_ref().map_or(0, |disk| disk.geometry().sector_size)
+    }
+
+    fn status(&mut self) -> u8 {
+        let interrupts = self.interrupts;
+        let reading = self.position < self.sector_size();
+        let writing = self.write.is_some();
+        let drive = match self.drive() {
+            Some(drive) => drive,
+            None => return 0xFF,
+        };
+
+        let mut status = status::MOVE_HEAD;
+        if drive.head_loaded {
+            status |= status::HEAD;
+            if reading && !writing {
+                status |= status::NRDA;
+            }
+        }
+        if writing {
+            status |= status::ENWD;
+        }
+        if interrupts {
+            status |= status::INTE;
+        }
+        if drive.track == 0 {
+            status |= status::TRACK_0;
+        }
+        !status
+    }
+
+    fn select(&mut self, value: u8) {
+        self.write = None;
+        self.position = usize::MAX;
+        let drive = (value & 0x0F) as usize;
+        // Bit 7 deselects, and so does choosing an empty drive
+        self.selected = (value & 0x80 == 0 && self.drives[drive].disk.is_some()).then_some(drive);
+    }
+
+    fn control(&mut self, value: u8) {
+        if value & control::INTERRUPT_ENABLE != 0 {
+            self.interrupts = true;
+        }
+        if value & control::INTERRUPT_DISABLE != 0 {
+            self.interrupts = false;
+        }
+
+        let drive = match self.drive() {
+            Some(drive) => drive,
+            None => return,
+        };
+        let tracks = drive.disk.as_ref().map_or(1, |disk| disk.geometry().tracks);
+        if value & control::STEP_IN != 0 {
+            drive.track = (drive.track + 1).min(tracks - 1);
+        }
+        if value & control::STEP_OUT != 0 {
+            drive.track = drive.track.saturating_sub(1);
+        }
+        if value & control::HEAD_LOAD != 0 {
+            drive.head_loaded = true;
+        }
+        if value & control::HEAD_UNLOAD != 0 {
+            drive.head_loaded = false;
+        }
+
+        if value & (control::STEP_IN | control::STEP_OUT | control::HEAD_UNLOAD) != 0 {
+            self.write = None;
+            self.position = usize::MAX;
+        }
+        if value & control::WRITE_ENABLE != 0 {
+            self.write = Some(Vec::new());
+        }
+    }
+
+    /// Sector position: bits 1-5 the sector coming up, bit 0 low when the
+    /// head is at its start. Reads 0xFF with no head loaded.
+    fn next_sector(&mut self) -> u8 {
+        let drive = match self.selected {
+            Some(drive) => &self.drives[drive],
+            None => return 0xFF,
+        };
+        let sectors = match (&drive.disk, drive.head_loaded) {
+            (Some(disk), true) => disk.geometry().sectors,
+            _ => return 0xFF,
+        };
+
+        // A half written sector is lost when the next one comes round
+        self.write = None;
+        self.sector = (self.sector + 1) % sectors;
+        self.position = 0;
+        0xC0 | ((self.sector as u8) << 1 & 0x3E)
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let (sector, position) = (self.sector, self.position);
+        let byte = self.drive().and_then(|drive| {
+            let disk = drive.disk.as_ref()?;
+            disk.read_sector(drive.track, sector)?.get(position).copied()
+        });
+
+        if byte.is_some() {
+            self.position += 1;
+        }
+        byte.unwrap_or(0)
+    }
+
+    fn write_data(&mut self, value: u8) {
+        let size = self.sector_size();
+        let buffer = match self.write.as_mut() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+
+        buffer.push(value);
+        if buffer.len() < size {
+            return;
+        }
+
+        let data = self.write.take().unwrap();
+        let (number, sector) = (self.selected.unwrap_or(0), self.sector);
+        if let Some(drive) = self.drive() {
+            if let Some(disk) = drive.disk.as_mut() {
+                // The 8" drives have no write protect status, so writes to a
+                // protected disk just don't happen. Only the user can be told.
+                let result = disk.write_sector(drive.track, sector, &data);
+                if result == Err(DiskError::WriteProtected) && !drive.warned {
+                    println!("Disk {} is write protected", number);
+                    drive.warned = true;
+                }
+            }
+        }
+    }
+}
+
+impl Device for Dcdd {
+    fn read(&mut self, port: u8) -> Option<u8> {
+        match port.wrapping_sub(self.base) {
+            0 => Some(self.status()),
+            1 => Some(self.next_sector()),
+            2 => Some(self.read_data()),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, port: u8, value: u8) -> bool {
+        match port.wrapping_sub(self.base) {
+            0 => self.select(value),
+            1 => self.control(value),
+            2 => self.write_data(value),
+            _ => return false,
+        }
+        true
+    }
+}
@@ -0,0 +1,122 @@
+use crate::em8080::BusCycle;
+
+// The front panel LEDs are wired straight to the bus, so while a program
+// runs they glow in proportion to how often each line is active. When the
+// machine is stopped they show the machine cycle it stopped in.
+
+/// How brightly each front panel light is lit, 0.0 to 1.0
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Lights {
+    /// Status word bits D0-D7, see `BusCycle`
+    pub status: [f32; 8],
+    pub address: [f32; 16],
+    pub data: [f32; 8],
+    pub inte: bool,
+    /// The CPU is stopped
+    pub wait: bool,
+}
+
+impl Lights {
+    /// Average brightness over a run of machine cycles
+    pub fn from_cycles(cycles: &[BusCycle]) -> Self {
+        let mut lights = Lights::default();
+        if cycles.is_empty() {
+            return lights;
+        }
+
+        for cycle in cycles {
+            add_bits(&mut lights.status, cycle.status as u16);
+            add_bits(&mut lights.address, cycle.address);
+            add_bits(&mut lights.data, cycle.data as u16);
+        }
+
+        let count = cycles.len() as f32;
+        for light in lights.status.iter_mut().chain(&mut lights.address).chain(&mut lights.data) {
+            *light /= count;
+        }
+        lights
+    }
+
+    /// Lights held by a single machine cycle
+    pub fn latched(cycle: BusCycle) -> Self {
+        Self::from_cycles(&[cycle])
+    }
+}
+
+fn add_bits(lights: &mut [f32], value: u16) {
+    for (bit, light) in lights.iter_mut().enumerate() {
+        if value & (1 << bit) != 0 {
+            *light += 1.0;
+        }
+    }
+}
+
+fn lamp(brightness: f32) -> char {
+    if brightness >= 0.5 {
+        '*'
+    } else if brightness > 0.0 {
+        'o'
+    } else {
+        '.'
+    }
+}
+
+/// Lamps for bits `high` down to 0, grouped in threes for octal like the
+/// panel's silk screen
+fn lamp_row(lights: &[f32], high: usize) -> String {
+    let mut row = String::new();
+    for bit in (0..=high).rev() {
+        row.push(' ');
+        row.push(lamp(lights[bit]));
+        if bit % 3 == 0 && bit != 0 {
+            row.push_str(" |");
+        }
+    }
+    row
+}
+
+/// Text picture of the panel, `*` for lit, `o` for glowing and `.` for off.
+/// Switches are `^` for up (1) and `v` for down.
+pub fn render(lights: &Lights, switches: u16) -> String {
+    let status = |flag: u8| lights.status[flag.trailing_zeros() as usize];
+    let columns = [
+        ("INTE", lights.inte as u8 as f32),
+        ("PROT", 0.0),
+        ("MEMR", status(BusCycle::MEMR)),
+        ("INP", status(BusCycle::INP)),
+        ("M1", status(BusCycle::M1)),
+        ("OUT", status(BusCycle::OUT)),
+        ("HLTA", status(BusCycle::HLTA)),
+        ("STACK", status(BusCycle::STACK)),
+        ("WO", status(BusCycle::WO)),
+        ("INT", status(BusCycle::INTA)),
+        ("WAIT", lights.wait as u8 as f32),
+        ("HLDA", 0.0),
+    ];
+
+    let mut names = String::new();
+    let mut lamps = String::new();
+    for (name, brightness) in columns {
+        names.push_str(&format!(" {:<5}", name));
+        lamps.push_str(&format!(" {:<5}", lamp(brightness)));
+    }
+
+    let value = |lights: &[f32]| lights.iter().rev().fold(0u16, |value, &light| value << 1 | (light >= 0.5) as u16);
+
+    [
+        format!("STATUS  {}", names.trim_end()),
+        format!("        {}", lamps.trim_end()),
+        format!("DATA     {}    {:03o}", lamp_row(&lights.data, 7), value(&lights.data)),
+        format!("ADDRESS  {}    {:06o}", lamp_row(&lights.address, 15), value(&lights.address)),
+        format!(
+            "SWITCHES {}    {:06o}",
+            (0..=15)
+                .rev()
+                .map(|bit| format!(" {}{}", if switches & (1 << bit) != 0 { '^' } else { 'v' }, if bit % 3 == 0 && bit != 0 { " |" } else { "" }))
+                .collect::<String>(),
+            switches
+        ),
+    ]
+    .join("\n")
+        + "\n"
+}
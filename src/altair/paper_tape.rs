@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::{Altair, SerialLine};
+
+/// A paper tape reader and punch on a serial line, like the high speed
+/// reader people hung off the 2SIO's second channel or the one built into
+/// a Teletype. The reader delivers bytes no faster than the line's baud
+/// rate, as programs that poll it expect; the punch keeps up with anything.
+pub struct PaperTape {
+    tape: Vec<u8>,
+    position: usize,
+    punch: Option<Box<dyn Write>>,
+    cycles_per_byte: u64,
+    /// CPU cycles until the next byte is under the read head
+    wait: u64,
+}
+
+impl PaperTape {
+    /// Reader loaded with `tape`, at `baud` bits a second. At 110 baud a
+    /// byte is 11 bits long, as on a Teletype, otherwise 10.
+    pub fn new(tape: Vec<u8>, baud: u32) -> Self {
+        Self::with_frame(tape, baud, if baud <= 110 { 11 } else { 10 })
+    }
+
+    /// Reader with `bits` bit times per byte, counting start and stop bits
+    pub fn with_frame(tape: Vec<u8>, baud: u32, bits: u32) -> Self {
+        let cycles_per_byte = Altair::CLOCK_HZ * bits as u64 / baud.max(1) as u64;
+
+        Self {
+            tape,
+            position: 0,
+            punch: None,
+            cycles_per_byte,
+            wait: cycles_per_byte,
+        }
+    }
+
+    /// Reader loaded with the contents of `path`
+    pub fn open(path: &Path, baud: u32) -> io::Result<Self> {
+        Ok(Self::new(std::fs::read(path)?, baud))
+    }
+
+    /// Punches to a new file at `path`
+    pub fn punch_to_file(&mut self, path: &Path) -> io::Result<()> {
+        self.set_punch(Box::new(BufWriter::new(File::create(path)?)));
+        Ok(())
+    }
+
+    pub fn set_punch(&mut self, punch: Box<dyn Write>) {
+        self.punch = Some(punch);
+    }
+
+    /// Whether the reader has run off the end of the tape
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.tape.len()
+    }
+}
+
+impl SerialLine for PaperTape {
+    fn receive(&mut self) -> Option<u8> {
+        if self.wait > 0 {
+            return None;
+        }
+
+        let byte = self.tape.get(self.position).copied()?;
+        self.position += 1;
+        self.wait = self.cycles_per_byte;
+        Some(byte)
+    }
+
+    fn send(&mut self, byte: u8) {
+        // Output with no punch attached goes nowhere, as with the punch off
+        if let Some(punch) = self.punch.as_mut() {
+            if let Err(e) = punch.write_all(&[byte]) {
+                println!("Paper tape punch: {}", e);
+                self.punch = None;
+            }
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.wait = self.wait.saturating_sub(cycles);
+    }
+}
+
+impl Drop for PaperTape {
+    fn drop(&mut self) {
+        if let Some(punch) = self.punch.as_mut() {
+            let _ = punch.flush();
+        }
+    }
+}
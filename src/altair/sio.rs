@@ -0,0 +1,89 @@
+use super::{Device, SharedLine};
+
+/// Which MITS serial board a port emulates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SerialCard {
+    /// 88-SIO: status bit 0 low when a byte has arrived, bit 7 low when
+    /// ready to send. Used by 4K BASIC.
+    Sio,
+    /// One channel of the 88-2SIO, a Motorola 6850 ACIA: status bit 0 high
+    /// when a byte has arrived, bit 1 high when ready to send. Writes to the
+    /// status port set the control register.
+    Sio2,
+}
+
+/// A serial port on two consecutive I/O ports, status/control then data
+pub struct SerialPort {
+    card: SerialCard,
+    base: u8,
+    line: SharedLine,
+    /// Byte received from the line and not yet read by the CPU
+    received: Option<u8>,
+}
+
+impl SerialPort {
+    /// Usual addresses: the 88-SIO at 0x00, the 2SIO's channels at 0x10 and 0x12
+    pub const SIO_PORT: u8 = 0x00;
+    pub const SIO2_PORT: u8 = 0x10;
+    pub const SIO2_SECOND_PORT: u8 = 0x12;
+
+    pub fn new(card: SerialCard, base: u8, line: SharedLine) -> Self {
+        Self {
+            card,
+            base,
+            line,
+            received: None,
+        }
+    }
+
+    fn status(&mut self) -> u8 {
+        if self.received.is_none() {
+            self.received = self.line.borrow_mut().receive();
+        }
+        let ready = self.received.is_some();
+
+        // We can always send
+        match self.card {
+            SerialCard::Sio => {
+                if ready {
+                    0b0111_1110
+                } else {
+                    0b0111_1111
+                }
+            }
+            SerialCard::Sio2 => 0b0000_0010 | ready as u8,
+        }
+    }
+}
+
+impl Device for SerialPort {
+    fn read(&mut self, port: u8) -> Option<u8> {
+        if port == self.base {
+            Some(self.status())
+        } else if port == self.base.wrapping_add(1) {
+            // Reading without waiting for a byte gets whatever is there
+            Some(self.received.take().or_else(|| self.line.borrow_mut().receive()).unwrap_or(0))
+        } else {
+            None
+        }
+    }
+
+    fn write(&mut self, port: u8, value: u8) -> bool {
+        if port == self.base {
+            // Control register: only the 6850's master reset matters here
+            if self.card == SerialCard::Sio2 && value & 0b11 == 0b11 {
+                self.received = None;
+            }
+            true
+        } else if port == self.base.wrapping_add(1) {
+            self.line.borrow_mut().send(value);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.line.borrow_mut().tick(cycles);
+    }
+}
@@ -0,0 +1,78 @@
+// MITS distributed BASIC on paper tape and cassette in its "checksum
+// loader" format. The tape starts with a leader and a small bootstrap loader
+// (read by the loader toggled in on the front panel), followed by records:
+//
+//   0x3C count addr_lo addr_hi data[count] checksum    load record
+//   0x78 addr_lo addr_hi                               end, start address
+//
+// The checksum is the 8 bit sum of the address and data bytes. Rather than
+// running the bootstrap, records are found directly: the first 0x3C that
+// starts a record with a good checksum begins the data.
+
+/// Program read from a tape image
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tape {
+    pub records: Vec<(u16, Vec<u8>)>,
+    /// Where the program starts, from the end record
+    pub start: Option<u16>,
+}
+
+const LOAD_RECORD: u8 = 0x3C;
+const END_RECORD: u8 = 0x78;
+
+impl Tape {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let mut tape = Tape::default();
+
+        // Skip the leader and bootstrap
+        let mut pos = (0..data.len())
+            .find(|&pos| load_record(data, pos).is_some())
+            .ok_or("No load records found on the tape")?;
+
+        while pos < data.len() {
+            match data[pos] {
+                LOAD_RECORD => {
+                    let (address, bytes, length) =
+                        load_record(data, pos).ok_or(format!("Bad load record at offset {}", pos))?;
+                    tape.records.push((address, bytes.to_vec()));
+                    pos += length;
+                }
+                END_RECORD => {
+                    if pos + 3 <= data.len() {
+                        tape.start = Some(u16::from_le_bytes([data[pos + 1], data[pos + 2]]));
+                    }
+                    break;
+                }
+                // Padding between records
+                0x00 => pos += 1,
+                byte => return Err(format!("Unexpected byte {:02X} at offset {}", byte, pos)),
+            }
+        }
+
+        Ok(tape)
+    }
+
+    /// Bytes loaded altogether
+    pub fn loaded_bytes(&self) -> usize {
+        self.records.iter().map(|(_, bytes)| bytes.len()).sum()
+    }
+}
+
+/// Load record at `pos`: address, data and length on tape
+fn load_record(data: &[u8], pos: usize) -> Option<(u16, &[u8], usize)> {
+    if data.get(pos) != Some(&LOAD_RECORD) {
+        return None;
+    }
+
+    let count = *data.get(pos + 1)? as usize;
+    let header = data.get(pos + 2..pos + 4)?;
+    let bytes = data.get(pos + 4..pos + 4 + count)?;
+    let checksum = *data.get(pos + 4 + count)?;
+
+    let sum = header.iter().chain(bytes).fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    if sum != checksum {
+        return None;
+    }
+
+    Some((u16::from_le_bytes([header[0], header[1]]), bytes, count + 5))
+}
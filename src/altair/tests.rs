@@ -0,0 +1,361 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::altair::tape::Tape;
+use crate::altair::dcdd::{control, status, Dcdd};
+use crate::altair::panel::{self, Lights};
+use crate::altair::{Altair, Cassette, Device, PaperTape, SerialCard, SerialLine, SerialPort};
+use crate::disk::{DiskImage, Geometry};
+use crate::em8080::BusCycle;
+use crate::kcs;
+use crate::testing::{loopback, SharedBuffer};
+
+#[test]
+fn test_sio_status() {
+    let line = loopback(b"A");
+    let mut sio = SerialPort::new(SerialCard::Sio, 0x00, line.clone());
+
+    // Active low: byte waiting, ready to send
+    assert_eq!(sio.read(0x00), Some(0b0111_1110));
+    assert_eq!(sio.read(0x01), Some(b'A'));
+    assert_eq!(sio.read(0x00), Some(0b0111_1111));
+
+    assert!(sio.write(0x01, b'B'));
+    assert_eq!(line.borrow().output, b"B");
+    assert_eq!(sio.read(0x02), None);
+    assert!(!sio.write(0x10, 0));
+}
+
+#[test]
+fn test_2sio_status() {
+    let line = loopback(b"AB");
+    let mut sio = SerialPort::new(SerialCard::Sio2, 0x10, line.clone());
+
+    assert_eq!(sio.read(0x10), Some(0b0000_0011));
+    assert_eq!(sio.read(0x11), Some(b'A'));
+
+    // Master reset drops the byte already received
+    assert_eq!(sio.read(0x10), Some(0b0000_0011));
+    assert!(sio.write(0x10, 0b0000_0011));
+    assert_eq!(sio.read(0x10), Some(0b0000_0010));
+}
+
+#[test]
+fn test_echo_program() {
+    // loop: IN 10h; RRC; JNC loop; IN 11h; OUT 11h; JMP loop
+    let program = [0xDB, 0x10, 0x0F, 0xD2, 0x00, 0x00, 0xDB, 0x11, 0xD3, 0x11, 0xC3, 0x00, 0x00];
+    let line = loopback(b"HELLO\r");
+
+    let mut altair = Altair::new(4 * 1024);
+    altair.load(0, &program).unwrap();
+    altair.add_device(Box::new(SerialPort::new(SerialCard::Sio2, 0x10, line.clone())));
+    altair.run(10_000);
+
+    assert_eq!(line.borrow().output, b"HELLO\r");
+}
+
+#[test]
+fn test_sense_switches_and_empty_ports() {
+    // IN FFh; STA 0100h; IN 42h; STA 0101h; JMP $
+    let program = [0xDB, 0xFF, 0x32, 0x00, 0x01, 0xDB, 0x42, 0x32, 0x01, 0x01, 0xC3, 0x0A, 0x00];
+
+    let mut altair = Altair::new(1024);
+    altair.load(0, &program).unwrap();
+    altair.set_switches(0o123 << 8 | 0o377);
+    altair.run(100);
+
+    assert_eq!(altair.cpu.memory[0x100], 0o123);
+    assert_eq!(altair.cpu.memory[0x101], 0xFF);
+}
+
+#[test]
+fn test_ram_size() {
+    let mut altair = Altair::new(4 * 1024);
+    assert!(altair.load(0x0F00, &[0; 0x100]).is_ok());
+    assert!(altair.load(0x0F00, &[0; 0x101]).is_err());
+}
+
+fn record(address: u16, bytes: &[u8]) -> Vec<u8> {
+    let mut record = vec![0x3C, bytes.len() as u8];
+    record.extend(address.to_le_bytes());
+    record.extend(bytes);
+    record.push(record[2..].iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)));
+    record
+}
+
+#[test]
+fn test_tape_records() {
+    let mut data = vec![0xAE; 8];
+    // Bootstrap, with a 0x3C that isn't a record
+    data.extend([0x3C, 0x02, 0x00, 0x00, 0x01, 0x02, 0x99, 0x21, 0x00, 0x00]);
+    data.extend(record(0x0100, &[0xAA, 0xBB, 0xCC]));
+    data.extend([0x00, 0x00]);
+    data.extend(record(0x0103, &[0xDD, 0xEE]));
+    data.extend([0x78, 0x00, 0x01]);
+
+    let tape = Tape::parse(&data).unwrap();
+    assert_eq!(tape.records, vec![(0x0100, vec![0xAA, 0xBB, 0xCC]), (0x0103, vec![0xDD, 0xEE])]);
+    assert_eq!(tape.start, Some(0x0100));
+    assert_eq!(tape.loaded_bytes(), 5);
+
+    let mut altair = Altair::new(1024);
+    altair.load_tape(&tape).unwrap();
+    assert_eq!(altair.cpu.memory[0x100..0x105], [0xAA, 0xBB, 0xCC, 0xDD, 0xEE]);
+    assert_eq!(altair.cpu.pc(), 0x0100);
+
+    // A damaged record is an error rather than a partial load
+    let last = data.len() - 4;
+    data[last] ^= 1;
+    assert!(Tape::parse(&data).is_err());
+    assert!(Tape::parse(&[0; 16]).is_err());
+}
+
+#[test]
+fn test_panel_examine_deposit() {
+    let mut altair = Altair::new(1024);
+    altair.stop();
+
+    // Toggle in MVI A,5; OUT 1 at address 0o40
+    altair.set_switches(0o40);
+    altair.examine();
+    for (n, byte) in [0x3E, 0x05, 0xD3, 0x01].into_iter().enumerate() {
+        altair.set_switches(byte);
+        if n == 0 {
+            altair.deposit();
+        } else {
+            altair.deposit_next();
+        }
+    }
+    assert_eq!(altair.cpu.memory[0o40..0o44], [0x3E, 0x05, 0xD3, 0x01]);
+    assert_eq!(altair.lights().data.map(|light| light as u8), [1, 0, 0, 0, 0, 0, 0, 0]);
+
+    altair.set_switches(0o40);
+    altair.examine();
+    assert_eq!(altair.cpu.pc(), 0o40);
+    altair.examine_next();
+    let lights = altair.lights();
+    assert_eq!(lights.address.map(|light| light as u8)[..8], [1, 0, 0, 0, 0, 1, 0, 0]);
+    assert_eq!(lights.data.map(|light| light as u8), [1, 0, 1, 0, 0, 0, 0, 0]);
+    assert!(lights.wait);
+
+    // Stopped, RUN does nothing until started
+    altair.set_switches(0o40);
+    altair.examine();
+    let cycles = altair.cycles();
+    altair.run(100);
+    assert_eq!(altair.cycles(), cycles);
+
+    // Stepping the OUT leaves its output cycle on the lights
+    altair.single_step();
+    altair.single_step();
+    assert_eq!(altair.cpu.pc(), 0o44);
+    let lights = altair.lights();
+    assert_eq!(lights.status[BusCycle::OUT.trailing_zeros() as usize], 1.0);
+    assert_eq!(lights.status[BusCycle::WO.trailing_zeros() as usize], 0.0);
+    assert_eq!(lights.data.map(|light| light as u8), [1, 0, 1, 0, 0, 0, 0, 0]);
+
+    altair.reset();
+    assert_eq!(altair.cpu.pc(), 0);
+    assert!(!altair.is_running());
+    altair.start();
+    assert!(altair.is_running());
+}
+
+#[test]
+fn test_panel_lights() {
+    let fetch = BusCycle {
+        status: BusCycle::MEMR | BusCycle::M1 | BusCycle::WO,
+        address: 0x0001,
+        data: 0x00,
+    };
+    let write = BusCycle {
+        status: BusCycle::STACK,
+        address: 0x0003,
+        data: 0xFF,
+    };
+
+    let lights = Lights::from_cycles(&[fetch, fetch, fetch, write]);
+    assert_eq!(lights.status[7], 0.75);
+    assert_eq!(lights.status[2], 0.25);
+    assert_eq!(lights.address[0], 1.0);
+    assert_eq!(lights.address[1], 0.25);
+    assert_eq!(lights.data[3], 0.25);
+    assert_eq!(Lights::from_cycles(&[]), Lights::default());
+
+    let text = panel::render(&lights, 0o100001);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert!(lines[0].starts_with("STATUS   INTE  PROT  MEMR"));
+    assert!(lines[2].starts_with("DATA      o o | o o o | o o o"));
+    assert!(lines[3].ends_with("000001"));
+    assert!(lines[4].starts_with("SWITCHES  ^ | v v v |"));
+    assert!(lines[4].ends_with("100001"));
+}
+
+#[test]
+fn test_paper_tape_speed() {
+    // 110 baud is 11 bits a byte: 200,000 cycles at 2 MHz
+    let mut tape = PaperTape::new(b"AB".to_vec(), 110);
+    assert_eq!(tape.receive(), None);
+    tape.tick(199_999);
+    assert_eq!(tape.receive(), None);
+    tape.tick(1);
+    assert_eq!(tape.receive(), Some(b'A'));
+    assert_eq!(tape.receive(), None);
+
+    // 9600 baud is 10 bits a byte
+    let mut tape = PaperTape::new(b"AB".to_vec(), 9600);
+    tape.tick(2_083);
+    assert_eq!(tape.receive(), Some(b'A'));
+    tape.tick(2_083);
+    assert_eq!(tape.receive(), Some(b'B'));
+    assert!(tape.is_finished());
+    tape.tick(1_000_000);
+    assert_eq!(tape.receive(), None);
+}
+
+#[test]
+fn test_paper_tape_copy() {
+    // Copies the reader to the punch on the 2SIO's second channel:
+    // loop: IN 12h; RRC; JNC loop; IN 13h; OUT 13h; JMP loop
+    let program = [0xDB, 0x12, 0x0F, 0xD2, 0x00, 0x00, 0xDB, 0x13, 0xD3, 0x13, 0xC3, 0x00, 0x00];
+    let data: Vec<u8> = (0..=255).collect();
+    let punched = SharedBuffer::default();
+
+    let mut tape = PaperTape::new(data.clone(), 9600);
+    tape.set_punch(Box::new(punched.clone()));
+
+    let mut altair = Altair::new(1024);
+    altair.load(0, &program).unwrap();
+    let line = Rc::new(RefCell::new(tape));
+    altair.add_device(Box::new(SerialPort::new(SerialCard::Sio2, SerialPort::SIO2_SECOND_PORT, line)));
+
+    // A tenth of a second at 960 bytes a second
+    altair.run(Altair::CLOCK_HZ / 10);
+    let copied = punched.0.borrow().len();
+    assert!((95..=96).contains(&copied), "{} bytes after 0.1 s", copied);
+
+    altair.run(Altair::CLOCK_HZ / 2);
+    assert_eq!(*punched.0.borrow(), data);
+}
+
+#[test]
+fn test_cassette_copy() {
+    // Copies the cassette back onto itself through the 88-ACR:
+    // loop: IN 06h; RRC; JC loop; IN 07h; OUT 07h; JMP loop
+    let program = [0xDB, 0x06, 0x0F, 0xDA, 0x00, 0x00, 0xDB, 0x07, 0xD3, 0x07, 0xC3, 0x00, 0x00];
+    let data = b"CLOAD\r".to_vec();
+
+    let mut altair = Altair::new(1024);
+    altair.load(0, &program).unwrap();
+    let cassette = Rc::new(RefCell::new(Cassette::new(data.clone())));
+    altair.add_device(Box::new(SerialPort::new(SerialCard::Sio, Cassette::PORT, cassette.clone())));
+
+    // 300 baud with two stop bits is about 27 bytes a second
+    altair.run(Altair::CLOCK_HZ / 10);
+    assert!(!cassette.borrow().is_finished());
+    altair.run(Altair::CLOCK_HZ / 5);
+    assert!(cassette.borrow().is_finished());
+
+    let recording = cassette.borrow().recording();
+    assert_eq!(kcs::decode(&recording.samples, recording.sample_rate), data);
+
+    // Leader, then the bytes back to back as they arrived at 300 baud
+    let seconds = recording.samples.len() as f64 / recording.sample_rate as f64;
+    let expected = kcs::LEADER_SECONDS + data.len() as f64 * 11.0 / 300.0;
+    assert!((seconds - expected).abs() < 0.01, "{} seconds recorded", seconds);
+}
+
+/// Altair disk whose sectors start with their track and sector numbers
+fn numbered_disk() -> DiskImage {
+    let mut disk = DiskImage::new(Geometry::ALTAIR_8INCH);
+    for track in 0..77 {
+        for sector in 0..32 {
+            disk.write_sector(track, sector, &[track as u8 | 0x80, sector as u8]).unwrap();
+        }
+    }
+    disk
+}
+
+/// Waits for `sector` to come round, as the boot loader does
+fn find_sector(controller: &mut Dcdd, sector: u8) {
+    for _ in 0..32 {
+        let position = controller.read(0x09).unwrap();
+        if position & 1 == 0 && (position >> 1) & 0x1F == sector {
+            return;
+        }
+    }
+    panic!("sector {} never came round", sector);
+}
+
+#[test]
+fn test_dcdd_read() {
+    let mut controller = Dcdd::new(Dcdd::PORT);
+    controller.insert(1, numbered_disk());
+
+    // Nothing in drive 0, so it can't be selected
+    controller.write(0x08, 0);
+    assert_eq!(controller.read(0x08), Some(0xFF));
+
+    controller.write(0x08, 1);
+    assert_eq!(controller.read(0x08), Some(!(status::MOVE_HEAD | status::TRACK_0)));
+    assert_eq!(controller.read(0x09), Some(0xFF));
+
+    controller.write(0x09, control::HEAD_LOAD);
+    controller.write(0x09, control::STEP_IN);
+    controller.write(0x09, control::STEP_IN);
+    controller.write(0x09, control::STEP_OUT);
+    controller.write(0x09, control::STEP_IN);
+    assert_eq!(controller.read(0x08), Some(!(status::MOVE_HEAD | status::HEAD)));
+
+    find_sector(&mut controller, 3);
+    assert_eq!(controller.read(0x08), Some(!(status::MOVE_HEAD | status::HEAD | status::NRDA)));
+    let sector: Vec<u8> = (0..137).map(|_| controller.read(0x0A).unwrap()).collect();
+    assert_eq!(sector[..3], [0x82, 3, 0]);
+    assert_eq!(controller.read(0x08), Some(!(status::MOVE_HEAD | status::HEAD)));
+
+    // Stepping out stops at track 0
+    for _ in 0..5 {
+        controller.write(0x09, control::STEP_OUT);
+    }
+    find_sector(&mut controller, 31);
+    assert_eq!(controller.read(0x0A), Some(0x80));
+    assert_eq!(controller.read(0x08).unwrap() & status::TRACK_0, 0);
+
+    controller.write(0x08, 0x80);
+    assert_eq!(controller.read(0x08), Some(0xFF));
+    assert_eq!(controller.read(0x0B), None);
+}
+
+#[test]
+fn test_dcdd_write() {
+    let mut controller = Dcdd::new(Dcdd::PORT);
+    controller.insert(0, numbered_disk());
+    let mut protected = numbered_disk();
+    protected.set_write_protected(true);
+    controller.insert(1, protected);
+
+    for drive in 0..2 {
+        controller.write(0x08, drive);
+        controller.write(0x09, control::HEAD_LOAD | control::STEP_IN);
+        find_sector(&mut controller, 5);
+        controller.write(0x09, control::WRITE_ENABLE);
+        assert_eq!(controller.read(0x08).unwrap() & status::ENWD, 0);
+        for byte in 0..137 {
+            controller.write(0x0A, byte);
+        }
+        assert_eq!(controller.read(0x08).unwrap() & status::ENWD, status::ENWD);
+    }
+
+    // Each drive kept its own head position
+    controller.write(0x08, 0);
+    controller.write(0x09, control::STEP_OUT);
+    assert_eq!(controller.read(0x08).unwrap() & status::TRACK_0, 0);
+
+    let written = controller.insert(0, DiskImage::new(Geometry::ALTAIR_8INCH)).unwrap();
+    assert_eq!(written.read_sector(1, 5).unwrap()[..3], [0, 1, 2]);
+    assert_eq!(written.read_sector(1, 4).unwrap()[..2], [0x81, 4]);
+
+    let protected = controller.insert(1, DiskImage::new(Geometry::ALTAIR_8INCH)).unwrap();
+    assert_eq!(protected.read_sector(1, 5).unwrap()[..2], [0x81, 5]);
+}
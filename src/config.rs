@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests;
+
+// Configuration files are plain `key = value` lines, where each key is the
+// name of a long command line option without the leading dashes:
+//
+//     # Five lives, bonus at 1000 points
+//     lives = 5
+//     bonus-at = 1000
+//
+// Blank lines and lines starting with `#` are ignored.
+
+/// Reads the `(key, value)` pairs from a configuration file, in file order
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<(String, String)>, String> {
+    let text = fs::read_to_string(&path)
+        .map_err(|e| format!("Could not read {}: {}", path.as_ref().display(), e))?;
+    parse(&text)
+}
+
+pub fn parse(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(format!("Line {}: expected `key = value`", number + 1))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("Line {}: missing key", number + 1));
+        }
+
+        entries.push((key.to_string(), value.trim().to_string()));
+    }
+
+    Ok(entries)
+}
+
+/// Options a front end can be given by name, on the command line or in a
+/// configuration file
+pub trait Settings: Default {
+    /// Options that take no value on the command line
+    const FLAGS: &'static [&'static str];
+
+    /// Applies one option. `value` is `None` for flags given on the command line.
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String>;
+
+    /// Prints the command line usage
+    fn usage();
+}
+
+/// Parses `--name value` and `--flag` arguments in order, taking the
+/// settings in a `--config FILE` where it appears. `--help` prints the usage
+/// and exits.
+pub fn parse_args<S: Settings>(mut args: impl Iterator<Item = String>) -> Result<S, String> {
+    let mut settings = S::default();
+
+    while let Some(arg) = args.next() {
+        if arg == "--help" {
+            S::usage();
+            std::process::exit(0);
+        }
+        let name = arg.strip_prefix("--").ok_or(format!("Unknown argument: {}", arg))?;
+
+        let value = if S::FLAGS.contains(&name) {
+            None
+        } else {
+            Some(args.next().ok_or(format!("{} needs a value", arg))?)
+        };
+
+        if name == "config" {
+            for (key, value) in load(value.unwrap())? {
+                settings.set(&key, Some(&value))?;
+            }
+        } else {
+            settings.set(name, value.as_deref())?;
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Drive number from its letter, `A` or `A:`, if the machine has that many
+pub fn parse_drive(letter: &str, drives: usize) -> Option<usize> {
+    let letter = letter.strip_suffix(':').unwrap_or(letter).to_ascii_uppercase();
+    match letter.as_bytes() {
+        &[letter] if letter.wrapping_sub(b'A') < drives as u8 => Some((letter - b'A') as usize),
+        _ => None,
+    }
+}
+
+/// Parses the value of an on/off setting
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("Expected on or off, got {}", value)),
+    }
+}
@@ -0,0 +1,80 @@
+use std::fs;
+
+use crate::config::{parse, parse_args, parse_bool, parse_drive, Settings};
+
+#[test]
+fn test_parse() {
+    let entries = parse("# comment\nlives = 5\n\n  bonus-at=1000  \noverlay = my overlay.txt\n").unwrap();
+    assert_eq!(
+        entries,
+        vec![
+            ("lives".to_string(), "5".to_string()),
+            ("bonus-at".to_string(), "1000".to_string()),
+            ("overlay".to_string(), "my overlay.txt".to_string()),
+        ]
+    );
+
+    assert!(parse("lives 5").is_err());
+    assert!(parse(" = 5").is_err());
+}
+
+#[test]
+fn test_parse_bool() {
+    assert_eq!(parse_bool("On"), Ok(true));
+    assert_eq!(parse_bool("false"), Ok(false));
+    assert!(parse_bool("maybe").is_err());
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    fast: bool,
+    lives: Vec<String>,
+}
+
+impl Settings for Options {
+    const FLAGS: &'static [&'static str] = &["fast"];
+
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        match (name, value) {
+            ("fast", value) => self.fast = value.map_or(Ok(true), parse_bool)?,
+            ("lives", Some(value)) => self.lives.push(value.to_string()),
+            _ => return Err(format!("Unknown option: {}", name)),
+        }
+        Ok(())
+    }
+
+    fn usage() {}
+}
+
+#[test]
+fn test_parse_args() {
+    let args = |args: &[&str]| parse_args::<Options>(args.iter().map(|arg| arg.to_string()));
+
+    // A config file's settings apply where it is given
+    let path = std::env::temp_dir().join(format!("emulator-8080-config-{}", std::process::id()));
+    fs::write(&path, "lives = 5\nfast = off\n").unwrap();
+    let path = path.to_str().unwrap();
+    assert_eq!(
+        args(&["--fast", "--lives", "3", "--config", path]),
+        Ok(Options {
+            fast: false,
+            lives: vec!["3".into(), "5".into()]
+        })
+    );
+    assert!(args(&["--config", path, "--fast"]).unwrap().fast);
+    fs::remove_file(path).unwrap();
+
+    assert!(args(&["lives"]).is_err());
+    assert!(args(&["--lives"]).is_err());
+    assert!(args(&["--speed", "1"]).is_err());
+}
+
+#[test]
+fn test_parse_drive() {
+    assert_eq!(parse_drive("A", 16), Some(0));
+    assert_eq!(parse_drive("c:", 16), Some(2));
+    assert_eq!(parse_drive("P", 16), Some(15));
+    assert_eq!(parse_drive("E", 4), None);
+    assert_eq!(parse_drive("AB", 16), None);
+    assert_eq!(parse_drive("@", 16), None);
+}
@@ -0,0 +1,203 @@
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use crate::altair::SharedLine;
+use crate::cpmfs::Format;
+use crate::em8080::{Em8080, IOState};
+use bdos::{Bdos, Outcome};
+use ccp::{Ccp, Step};
+
+mod bdos;
+mod ccp;
+pub mod cli;
+mod devices;
+mod files;
+#[cfg(test)]
+mod tests;
+
+// CP/M 2.2 without a disk: the BDOS and CCP are written in Rust and work
+// on host directories, so programs can be run straight off the host file
+// system. The 8080 only runs the transient programs. Its BDOS and BIOS
+// entry points are stubs that trap out with an OUT:
+//
+//   0000    JMP to the BIOS warm boot entry
+//   0003    IOBYTE
+//   0004    current drive, and user number in the high nibble
+//   0005    JMP to the BDOS
+//   005C    default FCBs
+//   0080    command tail and default DMA buffer
+//   0100    TPA, where programs load and run
+//   FE00    BDOS entry: OUT BDOS_PORT; RET
+//   FE10    disk parameter block
+//   FE20    allocation vector
+//   FF00    BIOS jump table, to OUT BIOS_PORT+n; RET stubs at FF80
+
+/// Start of the transient program area
+const TPA: u16 = 0x0100;
+/// The BDOS entry point, and so the end of the TPA
+const TPA_END: u16 = 0xFE00;
+const DPB: u16 = 0xFE10;
+const ALV: u16 = 0xFE20;
+const BIOS: u16 = 0xFF00;
+const BIOS_STUBS: u16 = 0xFF80;
+const BIOS_ENTRIES: u8 = 17;
+
+/// Where programs start with their stack, a warm boot address on top of it
+const STACK: u16 = TPA_END - 2;
+
+const BDOS_PORT: u8 = 0xFF;
+const BIOS_PORT: u8 = 0xE0;
+
+/// Notes which trap port the last OUT from one of the stubs went to
+struct Traps {
+    port: Option<u8>,
+}
+
+impl IOState for Traps {
+    fn input(&mut self, _cpu: &Em8080, _port: u8) -> u8 {
+        0xFF
+    }
+
+    fn output(&mut self, cpu: &Em8080, port: u8, _value: u8) {
+        // Programs are free to use the ports themselves; only the stubs' OUTs
+        // are calls
+        let stub = match port {
+            BDOS_PORT => TPA_END,
+            _ if (BIOS_PORT..BIOS_PORT + BIOS_ENTRIES).contains(&port) => BIOS_STUBS + 3 * (port - BIOS_PORT) as u16,
+            _ => return,
+        };
+        if cpu.pc() == stub {
+            self.port = Some(port);
+        }
+    }
+}
+
+pub struct Cpm {
+    cpu: Em8080,
+    traps: Traps,
+    bdos: Bdos,
+    ccp: Ccp,
+    /// Whether a transient program has the CPU, rather than the CCP
+    running: bool,
+    cycles: u64,
+}
+
+impl Cpm {
+    pub const CLOCK_HZ: u64 = 2_000_000;
+    /// Drives A to P
+    pub const DRIVES: usize = files::Drives::COUNT;
+
+    pub fn new(console: SharedLine) -> Self {
+        let mut cpm = Self {
+            cpu: Em8080::new(),
+            traps: Traps { port: None },
+            bdos: Bdos::new(console),
+            ccp: Ccp::new(),
+            running: false,
+            cycles: 0,
+        };
+        cpm.warm_boot();
+        cpm
+    }
+
+    /// Makes host directory `directory` drive `drive`, 0 for A:
+    pub fn mount(&mut self, drive: usize, directory: PathBuf) {
+        self.bdos.drives.mount(drive as u8, directory);
+    }
+
+    /// Connects the list device, LST:
+    pub fn set_list(&mut self, list: Box<dyn Write>) {
+        self.bdos.devices.set_list(list);
+    }
+
+    /// Connects the punch, PUN:
+    pub fn set_punch(&mut self, punch: Box<dyn Write>) {
+        self.bdos.devices.set_punch(punch);
+    }
+
+    /// Connects the reader, RDR:
+    pub fn set_reader(&mut self, reader: Box<dyn Read>) {
+        self.bdos.devices.set_reader(reader);
+    }
+
+    /// Types `command` at the next A> prompt
+    pub fn queue(&mut self, command: &str) {
+        self.ccp.queue(command);
+    }
+
+    /// Whether the CCP is waiting at its prompt for a command
+    pub fn is_idle(&self) -> bool {
+        !self.running && self.ccp.is_idle()
+    }
+
+    /// Runs for `cycles` CPU cycles. Time waiting for a key counts, so this
+    /// returns early rather than spin.
+    pub fn run(&mut self, cycles: u64) {
+        let end = self.cycles + cycles;
+        while self.cycles < end {
+            if !self.running {
+                match self.ccp.step(&mut self.bdos, &mut self.cpu) {
+                    Step::Waiting => break,
+                    Step::Done => {}
+                    Step::Started => {
+                        self.cpu.memory[4] = self.bdos.user << 4 | self.bdos.drive;
+                        self.running = true;
+                    }
+                }
+                continue;
+            }
+
+            self.cycles += self.cpu.emulate(&mut self.traps);
+            let outcome = match self.traps.port.take() {
+                Some(BDOS_PORT) => self.bdos.call(&mut self.cpu),
+                Some(port) => self.bdos.bios(&mut self.cpu, port - BIOS_PORT),
+                // A program that halts is finished
+                None if self.cpu.is_halted() => Outcome::WarmBoot,
+                None => Outcome::Done,
+            };
+            match outcome {
+                Outcome::Done => {}
+                // Back to the OUT, to try again
+                Outcome::Blocked => {
+                    self.cpu.set_pc(self.cpu.pc().wrapping_sub(2));
+                    break;
+                }
+                Outcome::WarmBoot => self.warm_boot(),
+            }
+        }
+        self.cycles = self.cycles.max(end);
+    }
+
+    /// Puts back page zero and the entry points, which programs are free to
+    /// overwrite, and returns to the CCP
+    fn warm_boot(&mut self) {
+        let memory = &mut self.cpu.memory;
+        let jump = |address: u16| [0xC3, address as u8, (address >> 8) as u8];
+
+        memory[0..3].copy_from_slice(&jump(BIOS + 3));
+        memory[5..8].copy_from_slice(&jump(TPA_END));
+
+        let tpa_end = TPA_END as usize;
+        memory[tpa_end..tpa_end + 3].copy_from_slice(&[0xD3, BDOS_PORT, 0xC9]);
+        // Made to look like a single density 8" disk, for the programs that
+        // work out free space from it
+        memory[DPB as usize..DPB as usize + 15].copy_from_slice(&Format::IBM_3740.dpb.to_bytes());
+        memory[ALV as usize..ALV as usize + 32].fill(0);
+
+        for n in 0..BIOS_ENTRIES {
+            let stub = BIOS_STUBS + 3 * n as u16;
+            let entry = (BIOS + 3 * n as u16) as usize;
+            memory[entry..entry + 3].copy_from_slice(&jump(stub));
+            memory[stub as usize..stub as usize + 3].copy_from_slice(&[0xD3, BIOS_PORT + n, 0xC9]);
+        }
+
+        self.cpu.reset();
+        self.bdos.reset();
+        self.ccp.restart();
+        self.running = false;
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+}
@@ -0,0 +1,539 @@
+use super::devices::Devices;
+use super::files::{Drives, HostFile};
+use crate::altair::SharedLine;
+use crate::cpmfile::{FileName, RECORD};
+use crate::em8080::{Em8080, MEMORY_SIZE};
+
+/// What the machine does after a BDOS or BIOS call
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    /// Waiting for a key, the call is made again once there is one
+    Blocked,
+    /// Back to the CCP
+    WarmBoot,
+}
+
+/// A line being typed for function 10 or the CCP
+pub enum Edit {
+    Typing,
+    Line(Vec<u8>),
+    /// ^C at the start of the line
+    Cancelled,
+}
+
+/// Return codes of the file functions
+const OK: u8 = 0;
+const NOT_FOUND: u8 = 0xFF;
+/// Reading past the end of the file
+const END_OF_FILE: u8 = 1;
+/// Random record beyond what CP/M 2.2 can address
+const SEEK_PAST_END: u8 = 6;
+/// The host refused a write
+const DISK_FULL: u8 = 2;
+
+const CTRL_C: u8 = 0x03;
+
+/// CP/M's file and console calls, answered from host directories rather
+/// than a disk. Programs see a drive with a directory, but nothing below
+/// that: sector level BIOS calls fail.
+pub struct Bdos {
+    pub console: SharedLine,
+    /// Key taken from the console by a status call, not yet read
+    key: Option<u8>,
+    /// Line being typed, while function 10 waits for the rest of it
+    line: Vec<u8>,
+    pub devices: Devices,
+
+    pub drives: Drives,
+    pub drive: u8,
+    pub user: u8,
+    pub dma: u16,
+    /// Directory entries still to come from function 18
+    search: Vec<[u8; 32]>,
+}
+
+impl Bdos {
+    pub fn new(console: SharedLine) -> Self {
+        Self {
+            console,
+            key: None,
+            line: Vec::new(),
+            devices: Devices::default(),
+            drives: Drives::new(),
+            drive: 0,
+            user: 0,
+            dma: 0x0080,
+            search: Vec::new(),
+        }
+    }
+
+    /// What a warm boot puts back
+    pub fn reset(&mut self) {
+        self.devices.flush();
+        self.dma = 0x0080;
+        self.line.clear();
+        self.search.clear();
+    }
+
+    pub fn key_waiting(&mut self) -> bool {
+        if self.key.is_none() {
+            self.key = self.console.borrow_mut().receive();
+        }
+        self.key.is_some()
+    }
+
+    pub fn take_key(&mut self) -> Option<u8> {
+        self.key.take().or_else(|| self.console.borrow_mut().receive())
+    }
+
+    pub fn print(&mut self, text: &[u8]) {
+        let mut console = self.console.borrow_mut();
+        for &byte in text {
+            console.send(byte);
+        }
+    }
+
+    /// Takes whatever keys have arrived into the line being typed, up to
+    /// `max` characters, with CP/M's editing keys
+    pub fn edit_line(&mut self, max: usize) -> Edit {
+        while let Some(key) = self.take_key() {
+            match key {
+                b'\r' | b'\n' => {
+                    self.print(b"\r");
+                    return Edit::Line(std::mem::take(&mut self.line));
+                }
+                CTRL_C if self.line.is_empty() => {
+                    self.print(b"^C");
+                    return Edit::Cancelled;
+                }
+                // Backspace or rubout
+                0x08 | 0x7F if self.line.pop().is_some() => self.print(b"\x08 \x08"),
+                // ^U and ^X throw the line away
+                0x15 | 0x18 => {
+                    for _ in 0..self.line.len() {
+                        self.print(b"\x08 \x08");
+                    }
+                    self.line.clear();
+                }
+                key if (b' '..0x7F).contains(&key) && self.line.len() < max => {
+                    self.line.push(key);
+                    self.print(&[key]);
+                }
+                _ => {}
+            }
+        }
+        Edit::Typing
+    }
+
+    /// Handles a call to address 5, function in C and parameter in E or DE
+    pub fn call(&mut self, cpu: &mut Em8080) -> Outcome {
+        let function = cpu.get_bc() as u8;
+        let de = cpu.get_de();
+        let e = de as u8;
+
+        let result: u16 = match function {
+            0 => return Outcome::WarmBoot,
+            1 => match self.take_key() {
+                Some(key) => {
+                    self.print(&[key]);
+                    key as u16
+                }
+                None => return Outcome::Blocked,
+            },
+            2 => {
+                self.print(&[e]);
+                0
+            }
+            3 => self.devices.read() as u16,
+            4 => {
+                self.devices.punch(e);
+                0
+            }
+            5 => {
+                self.devices.list(e);
+                0
+            }
+            6 => match e {
+                0xFF => self.take_key().unwrap_or(0) as u16,
+                0xFE => self.console_status(),
+                _ => {
+                    self.print(&[e]);
+                    0
+                }
+            },
+            7 => cpu.memory[3] as u16,
+            8 => {
+                cpu.memory[3] = e;
+                0
+            }
+            9 => {
+                let text: Vec<u8> = (de..=0xFFFF)
+                    .map(|address| cpu.memory[address as usize])
+                    .take_while(|&byte| byte != b'$')
+                    .collect();
+                self.print(&text);
+                0
+            }
+            10 => {
+                let max = cpu.memory[de as usize] as usize;
+                match self.edit_line(max) {
+                    Edit::Typing => return Outcome::Blocked,
+                    Edit::Cancelled => return Outcome::WarmBoot,
+                    Edit::Line(line) => {
+                        cpu.memory[at(de, 1)] = line.len() as u8;
+                        write_memory(cpu, de.wrapping_add(2), &line);
+                        0
+                    }
+                }
+            }
+            11 => self.console_status(),
+            // CP/M 2.2 on an 8080
+            12 => 0x0022,
+            13 => {
+                self.drive = 0;
+                self.dma = 0x0080;
+                0
+            }
+            14 => {
+                if self.drives.is_mounted(e) {
+                    self.drive = e;
+                    0
+                } else {
+                    NOT_FOUND as u16
+                }
+            }
+            15 => self.open(cpu, de) as u16,
+            // Nothing is buffered, so closing only needs the file to exist
+            16 => match self.fcb_file(cpu, de) {
+                Some(_) => OK as u16,
+                None => NOT_FOUND as u16,
+            },
+            17 => self.search_first(cpu, de) as u16,
+            18 => self.search_next(cpu) as u16,
+            19 => self.delete(cpu, de) as u16,
+            20 => self.read_sequential(cpu, de) as u16,
+            21 => self.write_sequential(cpu, de) as u16,
+            22 => self.make(cpu, de) as u16,
+            23 => self.rename(cpu, de) as u16,
+            24 => self.drives.vector(),
+            25 => self.drive as u16,
+            26 => {
+                self.dma = de;
+                0
+            }
+            27 => super::ALV,
+            // Drives can't be made read only and none are
+            28 | 29 => 0,
+            // File attributes aren't kept
+            30 => match self.fcb_file(cpu, de) {
+                Some(_) => OK as u16,
+                None => NOT_FOUND as u16,
+            },
+            31 => super::DPB,
+            32 => match e {
+                0xFF => self.user as u16,
+                user => {
+                    self.user = user & 0x0F;
+                    0
+                }
+            },
+            33 => self.read_random(cpu, de) as u16,
+            // Host files fill gaps with zeros anyway
+            34 | 40 => self.write_random(cpu, de) as u16,
+            35 => {
+                match self.fcb_file(cpu, de) {
+                    Some(file) => set_random_record(cpu, de, file.records()),
+                    None => set_random_record(cpu, de, 0),
+                }
+                0
+            }
+            36 => {
+                let record = sequential_record(cpu, de);
+                set_random_record(cpu, de, record);
+                0
+            }
+            // 37 resets drives, which have nothing to reset
+            _ => 0,
+        };
+
+        return_value(cpu, result);
+        Outcome::Done
+    }
+
+    /// Handles BIOS entry `n`. The character devices work, but there is no
+    /// disk under the drives to read sectors from.
+    pub fn bios(&mut self, cpu: &mut Em8080, n: u8) -> Outcome {
+        let c = cpu.get_bc() as u8;
+        match n {
+            0 | 1 => return Outcome::WarmBoot,
+            2 => cpu.set_a(self.console_status() as u8),
+            3 => match self.take_key() {
+                Some(key) => cpu.set_a(key),
+                None => return Outcome::Blocked,
+            },
+            4 => self.print(&[c]),
+            5 => self.devices.list(c),
+            6 => self.devices.punch(c),
+            7 => cpu.set_a(self.devices.read()),
+            // SELDSK: no disk parameter header
+            9 => cpu.set_hl(0),
+            // READ and WRITE fail
+            13 | 14 => cpu.set_a(1),
+            15 => cpu.set_a(if self.devices.list_ready() { 0xFF } else { 0 }),
+            // SECTRAN: no skew
+            16 => cpu.set_hl(cpu.get_bc()),
+            _ => {}
+        }
+        Outcome::Done
+    }
+
+    fn console_status(&mut self) -> u16 {
+        if self.key_waiting() {
+            0xFF
+        } else {
+            0
+        }
+    }
+
+    /// Drive and user the FCB at `fcb` refers to
+    fn fcb_drive(&self, cpu: &Em8080, fcb: u16) -> u8 {
+        match cpu.memory[fcb as usize] {
+            0 | b'?' => self.drive,
+            drive => drive - 1,
+        }
+    }
+
+    fn fcb_name(&self, cpu: &Em8080, fcb: u16) -> FileName {
+        FileName::from_fcb(&read_memory(cpu, fcb, 12))
+    }
+
+    fn fcb_file(&self, cpu: &Em8080, fcb: u16) -> Option<HostFile> {
+        let name = self.fcb_name(cpu, fcb);
+        self.drives.find(self.fcb_drive(cpu, fcb), self.user, &name)
+    }
+
+    fn open(&mut self, cpu: &mut Em8080, fcb: u16) -> u8 {
+        match self.fcb_file(cpu, fcb) {
+            Some(file) => {
+                write_memory(cpu, fcb.wrapping_add(1), &file.name.0);
+                // s2
+                cpu.memory[at(fcb, 14)] = 0;
+                set_record_count(cpu, fcb, &file);
+                OK
+            }
+            None => NOT_FOUND,
+        }
+    }
+
+    fn make(&mut self, cpu: &mut Em8080, fcb: u16) -> u8 {
+        let name = self.fcb_name(cpu, fcb);
+        if name.is_ambiguous() {
+            return NOT_FOUND;
+        }
+        match self.drives.create(self.fcb_drive(cpu, fcb), self.user, &name) {
+            Ok(_) => {
+                cpu.memory[at(fcb, 14)] = 0;
+                cpu.memory[at(fcb, 15)] = 0;
+                OK
+            }
+            Err(_) => NOT_FOUND,
+        }
+    }
+
+    fn delete(&mut self, cpu: &Em8080, fcb: u16) -> u8 {
+        let pattern = self.fcb_name(cpu, fcb);
+        let files = self.drives.list(self.fcb_drive(cpu, fcb), self.user, &pattern);
+        if files.is_empty() {
+            return NOT_FOUND;
+        }
+        for file in files {
+            let _ = std::fs::remove_file(&file.path);
+        }
+        OK
+    }
+
+    /// Renames the file named in the first half of the FCB to the name in
+    /// the second
+    fn rename(&mut self, cpu: &Em8080, fcb: u16) -> u8 {
+        let name = FileName::from_fcb(&read_memory(cpu, fcb.wrapping_add(16), 12));
+        let drive = self.fcb_drive(cpu, fcb);
+        if name.is_ambiguous() || self.drives.find(drive, self.user, &name).is_some() {
+            return NOT_FOUND;
+        }
+        match self.fcb_file(cpu, fcb) {
+            Some(file) if self.drives.rename(&file, &name).is_ok() => OK,
+            _ => NOT_FOUND,
+        }
+    }
+
+    /// Starts a directory search. Every file gets an entry per 16K extent,
+    /// like a real directory, but only when the FCB's extent is `?`; the
+    /// usual search with extent 0 finds each file once.
+    fn search_first(&mut self, cpu: &mut Em8080, fcb: u16) -> u8 {
+        let pattern = self.fcb_name(cpu, fcb);
+        let all_extents = cpu.memory[at(fcb, 12)] == b'?' || cpu.memory[fcb as usize] == b'?';
+        let files = self.drives.list(self.fcb_drive(cpu, fcb), self.user, &pattern);
+
+        self.search.clear();
+        for file in files {
+            let records = file.records();
+            let extents = records.div_ceil(128).max(1);
+            let first = if all_extents { 0 } else { extents - 1 };
+            for extent in first..extents {
+                let mut entry = [0; 32];
+                entry[0] = self.user;
+                entry[1..12].copy_from_slice(&file.name.0);
+                entry[12] = (extent % 32) as u8;
+                entry[14] = (extent / 32) as u8;
+                let count = (records - extent * 128).min(128);
+                entry[15] = count as u8;
+                // Made up 1K block numbers, so that programs counting the
+                // blocks in use get about the right answer
+                for (i, block) in entry[16..].iter_mut().enumerate().take(count.div_ceil(8) as usize) {
+                    *block = i as u8 + 1;
+                }
+                self.search.push(entry);
+            }
+        }
+        self.search.reverse();
+        self.search_next(cpu)
+    }
+
+    /// Puts the next directory entry found at the DMA address, as the
+    /// first of the four entries in a directory record
+    fn search_next(&mut self, cpu: &mut Em8080) -> u8 {
+        match self.search.pop() {
+            Some(entry) => {
+                let mut record = [0xE5; RECORD];
+                record[..32].copy_from_slice(&entry);
+                write_memory(cpu, self.dma, &record);
+                0
+            }
+            None => NOT_FOUND,
+        }
+    }
+
+    fn read_sequential(&mut self, cpu: &mut Em8080, fcb: u16) -> u8 {
+        let record = sequential_record(cpu, fcb);
+        let result = self.read(cpu, fcb, record);
+        if result == OK {
+            set_sequential_record(cpu, fcb, record + 1);
+        }
+        result
+    }
+
+    fn write_sequential(&mut self, cpu: &mut Em8080, fcb: u16) -> u8 {
+        let record = sequential_record(cpu, fcb);
+        let result = self.write(cpu, fcb, record);
+        if result == OK {
+            set_sequential_record(cpu, fcb, record + 1);
+        }
+        result
+    }
+
+    /// Random reads and writes leave the sequential position on the record,
+    /// so a sequential read after them reads it again
+    fn read_random(&mut self, cpu: &mut Em8080, fcb: u16) -> u8 {
+        match random_record(cpu, fcb) {
+            Some(record) => {
+                set_sequential_record(cpu, fcb, record);
+                self.read(cpu, fcb, record)
+            }
+            None => SEEK_PAST_END,
+        }
+    }
+
+    fn write_random(&mut self, cpu: &mut Em8080, fcb: u16) -> u8 {
+        match random_record(cpu, fcb) {
+            Some(record) => {
+                set_sequential_record(cpu, fcb, record);
+                self.write(cpu, fcb, record)
+            }
+            None => SEEK_PAST_END,
+        }
+    }
+
+    fn read(&mut self, cpu: &mut Em8080, fcb: u16, record: u32) -> u8 {
+        let file = match self.fcb_file(cpu, fcb) {
+            Some(file) => file,
+            None => return END_OF_FILE,
+        };
+        match file.read_record(record) {
+            Ok(Some(data)) => {
+                write_memory(cpu, self.dma, &data);
+                OK
+            }
+            _ => END_OF_FILE,
+        }
+    }
+
+    fn write(&mut self, cpu: &mut Em8080, fcb: u16, record: u32) -> u8 {
+        let mut file = match self.fcb_file(cpu, fcb) {
+            Some(file) => file,
+            None => return NOT_FOUND,
+        };
+        match file.write_record(record, &read_memory(cpu, self.dma, RECORD)) {
+            Ok(()) => {
+                set_record_count(cpu, fcb, &file);
+                OK
+            }
+            Err(_) => DISK_FULL,
+        }
+    }
+}
+
+/// Results go in HL, and in A and B for programs written for CP/M 1.4
+fn return_value(cpu: &mut Em8080, value: u16) {
+    cpu.set_hl(value);
+    cpu.set_a(value as u8);
+    cpu.set_bc(value & 0xFF00 | cpu.get_bc() & 0x00FF);
+}
+
+/// Record the next sequential read or write uses, from the extent (ex and
+/// s2) and the current record in the extent (cr)
+fn sequential_record(cpu: &Em8080, fcb: u16) -> u32 {
+    let extent = cpu.memory[at(fcb, 12)] as u32 % 32 + (cpu.memory[at(fcb, 14)] as u32 & 0x3F) * 32;
+    extent * 128 + cpu.memory[at(fcb, 32)] as u32 % 128
+}
+
+fn set_sequential_record(cpu: &mut Em8080, fcb: u16, record: u32) {
+    let extent = record / 128;
+    cpu.memory[at(fcb, 12)] = (extent % 32) as u8;
+    cpu.memory[at(fcb, 14)] = (extent / 32) as u8;
+    cpu.memory[at(fcb, 32)] = (record % 128) as u8;
+}
+
+/// Record number from r0-r2, `None` past the 8M CP/M 2.2 can address
+fn random_record(cpu: &Em8080, fcb: u16) -> Option<u32> {
+    match cpu.memory[at(fcb, 35)] {
+        0 => Some(cpu.memory[at(fcb, 33)] as u32 | (cpu.memory[at(fcb, 34)] as u32) << 8),
+        _ => None,
+    }
+}
+
+fn set_random_record(cpu: &mut Em8080, fcb: u16, record: u32) {
+    write_memory(cpu, fcb.wrapping_add(33), &record.to_le_bytes()[..3]);
+}
+
+/// Sets rc, the records in the FCB's current extent
+fn set_record_count(cpu: &mut Em8080, fcb: u16, file: &HostFile) {
+    let extent = cpu.memory[at(fcb, 12)] as u32 % 32 + (cpu.memory[at(fcb, 14)] as u32 & 0x3F) * 32;
+    cpu.memory[at(fcb, 15)] = file.records().saturating_sub(extent * 128).min(128) as u8;
+}
+
+/// Index of the byte `offset` past `address`. Addresses come from the
+/// program, so they wrap round the top of memory rather than run off it.
+fn at(address: u16, offset: usize) -> usize {
+    (address as usize + offset) % MEMORY_SIZE
+}
+
+fn read_memory(cpu: &Em8080, address: u16, length: usize) -> Vec<u8> {
+    (0..length).map(|i| cpu.memory[at(address, i)]).collect()
+}
+
+fn write_memory(cpu: &mut Em8080, address: u16, data: &[u8]) {
+    for (i, &byte) in data.iter().enumerate() {
+        cpu.memory[at(address, i)] = byte;
+    }
+}
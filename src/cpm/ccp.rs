@@ -0,0 +1,335 @@
+use std::collections::VecDeque;
+use std::fs;
+
+use super::bdos::{Bdos, Edit};
+use super::{STACK, TPA, TPA_END};
+use crate::cpmfile::{FileName, EOF};
+use crate::em8080::Em8080;
+
+/// Where the CCP puts the command tail and the two file names after the
+/// command, as CP/M 2.2's does
+const FCB1: usize = 0x005C;
+const FCB2: usize = 0x006C;
+const TAIL: usize = 0x0080;
+
+/// Longest command line, the size of CP/M's buffer
+const MAX_LINE: usize = 127;
+
+/// What the CCP did with the keys it had
+pub enum Step {
+    /// Needs more keys
+    Waiting,
+    /// Ran a built in command, or didn't understand the line
+    Done,
+    /// Loaded a transient program, the CPU runs it from 0x100
+    Started,
+}
+
+/// What came of running a transient program
+enum Load {
+    Started,
+    NotFound,
+    /// Too big for the TPA
+    TooBig,
+}
+
+enum State {
+    /// The prompt needs printing
+    Idle,
+    /// At the prompt, reading a command
+    Reading,
+    /// Asking whether ERA *.* should go ahead
+    Confirming(u8, FileName),
+}
+
+/// The console command processor, built in rather than loaded from disk:
+/// the A> prompt, DIR, TYPE, ERA, REN, SAVE and USER, and running .COM
+/// files.
+pub struct Ccp {
+    state: State,
+    /// Command lines to run before reading any from the console
+    queue: VecDeque<String>,
+}
+
+impl Ccp {
+    pub fn new() -> Self {
+        Self {
+            state: State::Idle,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Types `command` at the next prompt
+    pub fn queue(&mut self, command: &str) {
+        self.queue.push_back(command.to_string());
+    }
+
+    /// Whether the CCP is at its prompt with nothing queued or typed
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, State::Reading) && self.queue.is_empty()
+    }
+
+    /// Goes back to the prompt, after a warm boot
+    pub fn restart(&mut self) {
+        self.state = State::Idle;
+    }
+
+    pub fn step(&mut self, bdos: &mut Bdos, cpu: &mut Em8080) -> Step {
+        if let State::Idle = self.state {
+            bdos.print(format!("\r\n{}>", (b'A' + bdos.drive) as char).as_bytes());
+            self.state = State::Reading;
+        }
+
+        let line = match self.state {
+            State::Confirming(drive, pattern) => {
+                let answer = match bdos.edit_line(MAX_LINE) {
+                    Edit::Typing => return Step::Waiting,
+                    Edit::Line(line) => line,
+                    Edit::Cancelled => Vec::new(),
+                };
+                if answer.first().is_some_and(|key| key.eq_ignore_ascii_case(&b'Y')) {
+                    erase(bdos, drive, &pattern);
+                }
+                self.state = State::Idle;
+                return Step::Done;
+            }
+            _ => match self.queue.pop_front() {
+                Some(command) => {
+                    bdos.print(command.as_bytes());
+                    command.into_bytes()
+                }
+                None => match bdos.edit_line(MAX_LINE) {
+                    Edit::Typing => return Step::Waiting,
+                    Edit::Line(line) => line,
+                    Edit::Cancelled => Vec::new(),
+                },
+            },
+        };
+        bdos.print(b"\r\n");
+        self.state = State::Idle;
+
+        let line = String::from_utf8_lossy(&line).to_ascii_uppercase();
+        self.execute(bdos, cpu, line.trim())
+    }
+
+    fn execute(&mut self, bdos: &mut Bdos, cpu: &mut Em8080, line: &str) -> Step {
+        let (command, tail) = line.split_once(' ').unwrap_or((line, ""));
+        let tail = tail.trim_start();
+        let argument = tail.split(' ').next().unwrap_or("");
+
+        match command {
+            "" => {}
+            "DIR" => dir(bdos, argument),
+            "TYPE" => type_file(bdos, argument),
+            "ERA" => match parse_file(bdos, argument) {
+                Some((drive, pattern)) if pattern.0 == [b'?'; 11] => {
+                    bdos.print(b"ALL (Y/N)?");
+                    self.state = State::Confirming(drive, pattern);
+                }
+                Some((drive, pattern)) => {
+                    if !erase(bdos, drive, &pattern) {
+                        bdos.print(b"NO FILE\r\n");
+                    }
+                }
+                None => error(bdos, argument),
+            },
+            "REN" => rename(bdos, tail),
+            "SAVE" => save(bdos, cpu, tail),
+            "USER" => match argument.parse::<u8>() {
+                Ok(user) if user < 16 => bdos.user = user,
+                _ => error(bdos, argument),
+            },
+            // Changing drive, A:
+            command if command.len() == 2 && command.ends_with(':') && tail.is_empty() => match parse_drive(command) {
+                Some(drive) if bdos.drives.is_mounted(drive) => bdos.drive = drive,
+                _ => error(bdos, command),
+            },
+            command => match load(bdos, cpu, command, tail) {
+                Load::Started => return Step::Started,
+                Load::NotFound => error(bdos, command),
+                Load::TooBig => bdos.print(b"BAD LOAD\r\n"),
+            },
+        }
+        Step::Done
+    }
+}
+
+/// The CCP's answer to anything it doesn't understand
+fn error(bdos: &mut Bdos, text: &str) {
+    bdos.print(format!("{}?\r\n", text).as_bytes());
+}
+
+/// Drive number of `A:` to `P:`
+fn parse_drive(text: &str) -> Option<u8> {
+    match text.as_bytes() {
+        &[letter, b':'] if (b'A'..=b'P').contains(&letter) => Some(letter - b'A'),
+        _ => None,
+    }
+}
+
+/// Splits `B:NAME.TYP` into a drive and name, on the current drive if there
+/// is no drive. No name at all means every file.
+fn parse_file(bdos: &Bdos, text: &str) -> Option<(u8, FileName)> {
+    let (drive, name) = match text.get(..2).and_then(parse_drive) {
+        Some(drive) => (drive, &text[2..]),
+        None => (bdos.drive, text),
+    };
+    let name = if name.is_empty() {
+        FileName([b'?'; 11])
+    } else {
+        FileName::parse(name)?
+    };
+    Some((drive, name))
+}
+
+/// Lists files four to a line, the way CP/M 2.2 does
+fn dir(bdos: &mut Bdos, argument: &str) {
+    let (drive, pattern) = match parse_file(bdos, argument) {
+        Some(file) => file,
+        None => return error(bdos, argument),
+    };
+    let files = bdos.drives.list(drive, bdos.user, &pattern);
+    if files.is_empty() {
+        bdos.print(b"NO FILE\r\n");
+        return;
+    }
+
+    for (i, file) in files.iter().enumerate() {
+        let [name, kind] =
+            [&file.name.0[..8], &file.name.0[8..]].map(|part| String::from_utf8_lossy(part).into_owned());
+        let entry = match i % 4 {
+            0 => format!("{}: {} {}", (b'A' + drive) as char, name, kind),
+            _ => format!(" : {} {}", name, kind),
+        };
+        bdos.print(entry.as_bytes());
+        if i % 4 == 3 || i == files.len() - 1 {
+            bdos.print(b"\r\n");
+        }
+    }
+}
+
+/// Prints a text file up to its ^Z. Host text files end lines with a bare
+/// LF, which gets a CR to go with it.
+fn type_file(bdos: &mut Bdos, argument: &str) {
+    let file = match parse_file(bdos, argument) {
+        Some((drive, name)) if !name.is_ambiguous() => bdos.drives.find(drive, bdos.user, &name),
+        _ => return error(bdos, argument),
+    };
+    let text = match file.and_then(|file| fs::read(file.path).ok()) {
+        Some(text) => text,
+        None => return bdos.print(b"NO FILE\r\n"),
+    };
+
+    let mut previous = 0;
+    for &byte in text.iter().take_while(|&&byte| byte != EOF) {
+        if byte == b'\n' && previous != b'\r' {
+            bdos.print(b"\r");
+        }
+        bdos.print(&[byte]);
+        previous = byte;
+    }
+}
+
+fn erase(bdos: &mut Bdos, drive: u8, pattern: &FileName) -> bool {
+    let files = bdos.drives.list(drive, bdos.user, pattern);
+    for file in &files {
+        let _ = fs::remove_file(&file.path);
+    }
+    !files.is_empty()
+}
+
+/// REN NEW=OLD
+fn rename(bdos: &mut Bdos, tail: &str) {
+    let names = tail
+        .split_once('=')
+        .and_then(|(new, old)| Some((parse_file(bdos, new)?, parse_file(bdos, old)?)));
+    let ((drive, new), (old_drive, old)) = match names {
+        Some(names) if !names.0 .1.is_ambiguous() && !names.1 .1.is_ambiguous() => names,
+        _ => return error(bdos, tail),
+    };
+    // Only a drive on the new name, REN B:NEW=OLD, applies to both
+    let drive = if tail.get(1..2) == Some(":") { drive } else { old_drive };
+
+    if bdos.drives.find(drive, bdos.user, &new).is_some() {
+        return bdos.print(b"FILE EXISTS\r\n");
+    }
+    match bdos.drives.find(drive, bdos.user, &old) {
+        Some(file) if bdos.drives.rename(&file, &new).is_ok() => {}
+        Some(_) => error(bdos, tail),
+        None => bdos.print(b"NO FILE\r\n"),
+    }
+}
+
+/// SAVE n NAME.TYP writes n 256 byte pages from 0x100
+fn save(bdos: &mut Bdos, cpu: &Em8080, tail: &str) {
+    let (pages, name) = tail.split_once(' ').unwrap_or((tail, ""));
+    let (pages, (drive, name)) = match (pages.parse::<usize>(), parse_file(bdos, name.trim())) {
+        (Ok(pages), Some(file)) if !name.trim().is_empty() && !file.1.is_ambiguous() && pages <= 255 => (pages, file),
+        _ => return error(bdos, tail),
+    };
+
+    let end = (TPA as usize + pages * 256).min(cpu.memory.len());
+    let saved = bdos
+        .drives
+        .create(drive, bdos.user, &name)
+        .and_then(|file| fs::write(file.path, &cpu.memory[TPA as usize..end]));
+    if saved.is_err() {
+        bdos.print(b"NO SPACE\r\n");
+    }
+}
+
+/// Loads `command`.COM and sets up page zero for it
+fn load(bdos: &mut Bdos, cpu: &mut Em8080, command: &str, tail: &str) -> Load {
+    let (drive, name) = match parse_file(bdos, command) {
+        Some((drive, name)) if name.kind().is_empty() && !name.is_ambiguous() => (drive, name),
+        _ => return Load::NotFound,
+    };
+    let mut com = name;
+    com.0[8..].copy_from_slice(b"COM");
+    let program = match bdos
+        .drives
+        .find(drive, bdos.user, &com)
+        .and_then(|file| fs::read(file.path).ok())
+    {
+        Some(program) => program,
+        None => return Load::NotFound,
+    };
+    if program.len() > (TPA_END - TPA) as usize {
+        return Load::TooBig;
+    }
+
+    let start = TPA as usize;
+    cpu.memory[start..start + program.len()].copy_from_slice(&program);
+
+    // Default FCBs from the first two arguments, with their extent,
+    // current record and random record cleared
+    cpu.memory[FCB1..TAIL].fill(0);
+    let mut arguments = tail.split(' ').filter(|argument| !argument.is_empty());
+    for fcb in [FCB1, FCB2] {
+        let argument = arguments.next().unwrap_or("");
+        let (drive, name) = match argument.get(..2).and_then(parse_drive) {
+            Some(drive) => (drive + 1, &argument[2..]),
+            None => (0, argument),
+        };
+        cpu.memory[fcb] = drive;
+        let name = FileName::parse(name).unwrap_or(FileName([b' '; 11]));
+        cpu.memory[fcb + 1..fcb + 12].copy_from_slice(&name.0);
+    }
+
+    // The tail keeps the space after the command and is null terminated
+    let tail = match tail {
+        "" => String::new(),
+        tail => format!(" {}", tail),
+    };
+    let tail = &tail.as_bytes()[..tail.len().min(MAX_LINE - 1)];
+    cpu.memory[TAIL] = tail.len() as u8;
+    cpu.memory[TAIL + 1..TAIL + 1 + tail.len()].copy_from_slice(tail);
+    cpu.memory[TAIL + 1 + tail.len()] = 0;
+
+    bdos.dma = TAIL as u16;
+    // Returning from the program warm boots, through a 0 on the stack
+    cpu.set_sp(STACK);
+    cpu.memory[STACK as usize..STACK as usize + 2].fill(0);
+    cpu.set_pc(TPA);
+    Load::Started
+}
@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+
+use super::devices;
+use super::Cpm;
+use crate::config::{self, Settings};
+use crate::session::{self, Machine};
+use crate::terminal::{self, Console};
+
+/// Command line options of `emulator-8080 cpm`
+#[derive(Default)]
+struct Options {
+    /// Host directories by drive number
+    drives: Vec<(usize, PathBuf)>,
+    /// Commands to type at the A> prompt, after which the emulator quits
+    run: Vec<String>,
+    /// Files or `|COMMAND` pipes for the list device, punch and reader
+    list: Option<String>,
+    punch: Option<String>,
+    reader: Option<String>,
+    terminal: terminal::Options,
+    uncapped: bool,
+}
+
+impl Options {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options: Self = config::parse_args(args)?;
+
+        if options.drives.is_empty() {
+            options.drives.push((0, PathBuf::from(".")));
+        }
+        Ok(options)
+    }
+}
+
+impl Settings for Options {
+    const FLAGS: &'static [&'static str] = &["window", "uncapped"];
+
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        if self.terminal.set(name, value)? {
+            return Ok(());
+        }
+        let flag = || value.map_or(Ok(true), config::parse_bool);
+        let value = || value.ok_or(format!("{} needs a value", name));
+
+        match name {
+            "drive" => {
+                let value = value()?;
+                let (letter, path) = value
+                    .split_once('=')
+                    .ok_or(format!("Expected DRIVE=DIRECTORY, got {}", value))?;
+                let drive =
+                    config::parse_drive(letter, Cpm::DRIVES).ok_or(format!("Drives are A to P, got {}", letter))?;
+                self.drives.retain(|&(other, _)| other != drive);
+                self.drives.push((drive, path.into()));
+            }
+            "run" => self.run.push(value()?.to_string()),
+            "list" => self.list = Some(value()?.to_string()),
+            "punch" => self.punch = Some(value()?.to_string()),
+            "reader" => self.reader = Some(value()?.to_string()),
+            "uncapped" => self.uncapped = flag()?,
+            _ => return Err(format!("Unknown option: {}", name)),
+        }
+
+        Ok(())
+    }
+
+    fn usage() {
+        println!("Usage: emulator-8080 cpm [--config FILE] [--drive A=DIRECTORY]... [--run COMMAND]...");
+        println!("                         [--list TARGET] [--punch TARGET] [--reader TARGET]");
+        println!("                         [--terminal adm3a|vt52] [--window] [--uncapped]");
+        println!("A TARGET is a file, or |COMMAND to pipe through a shell command.");
+    }
+}
+
+impl Machine for Cpm {
+    const CLOCK_HZ: u64 = Cpm::CLOCK_HZ;
+
+    fn run(&mut self, cycles: u64) {
+        Cpm::run(self, cycles)
+    }
+
+    fn cycles(&self) -> u64 {
+        Cpm::cycles(self)
+    }
+
+    fn is_idle(&self) -> bool {
+        Cpm::is_idle(self)
+    }
+}
+
+pub fn main(args: impl Iterator<Item = String>) {
+    let options = Options::parse(args).unwrap_or_else(|e| {
+        println!("{}", e);
+        Options::usage();
+        std::process::exit(1);
+    });
+
+    if let Err(e) = run(&options) {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    for (drive, path) in &options.drives {
+        if !path.is_dir() {
+            return Err(format!("{} is not a directory", path.display()));
+        }
+        println!("{}: {}", (b'A' + *drive as u8) as char, path.display());
+    }
+
+    let mut console = Console::open(&options.terminal)?;
+    let mut machine = Cpm::new(console.line());
+    for (drive, path) in &options.drives {
+        machine.mount(*drive, path.clone());
+    }
+    if let Some(target) = &options.list {
+        machine.set_list(devices::open_output(target).map_err(|e| format!("{}: {}", target, e))?);
+    }
+    if let Some(target) = &options.punch {
+        machine.set_punch(devices::open_output(target).map_err(|e| format!("{}: {}", target, e))?);
+    }
+    if let Some(target) = &options.reader {
+        machine.set_reader(devices::open_input(target).map_err(|e| format!("{}: {}", target, e))?);
+    }
+    for command in &options.run {
+        machine.queue(command);
+    }
+    println!("Ctrl-] to quit.");
+
+    // Commands given with --run are a batch, done when the CCP is back at
+    // its prompt
+    let batch = !options.run.is_empty();
+    session::run(&mut machine, &mut console, batch, options.uncapped);
+
+    // Flushes the devices and restores the terminal before printing
+    drop(machine);
+    drop(console);
+    println!();
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::process::{Child, Command, Stdio};
+
+use crate::cpmfile::EOF;
+
+/// CP/M's other character devices: the list device (LST:, a printer), the
+/// punch (PUN:) and the reader (RDR:). Each can be connected to a host
+/// file or pipe; output to a device that isn't goes nowhere, and the
+/// reader reads ^Z, end of file.
+#[derive(Default)]
+pub struct Devices {
+    list: Option<Box<dyn Write>>,
+    punch: Option<Box<dyn Write>>,
+    reader: Option<Box<dyn Read>>,
+}
+
+impl Devices {
+    pub fn set_list(&mut self, list: Box<dyn Write>) {
+        self.list = Some(list);
+    }
+
+    pub fn set_punch(&mut self, punch: Box<dyn Write>) {
+        self.punch = Some(punch);
+    }
+
+    pub fn set_reader(&mut self, reader: Box<dyn Read>) {
+        self.reader = Some(reader);
+    }
+
+    /// Whether the list device is ready, which it never is with nothing
+    /// attached, so programs that check can tell
+    pub fn list_ready(&self) -> bool {
+        self.list.is_some()
+    }
+
+    pub fn list(&mut self, byte: u8) {
+        send(&mut self.list, byte, "List device");
+    }
+
+    pub fn punch(&mut self, byte: u8) {
+        send(&mut self.punch, byte, "Punch");
+    }
+
+    /// Next byte from the reader, waiting for it if the reader is a pipe
+    pub fn read(&mut self) -> u8 {
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return EOF,
+        };
+        let mut byte = [0];
+        match reader.read_exact(&mut byte) {
+            Ok(()) => byte[0],
+            Err(e) => {
+                if e.kind() != io::ErrorKind::UnexpectedEof {
+                    println!("Reader: {}", e);
+                }
+                self.reader = None;
+                EOF
+            }
+        }
+    }
+
+    /// Pushes out buffered output, for when a program finishes
+    pub fn flush(&mut self) {
+        for device in [&mut self.list, &mut self.punch].into_iter().flatten() {
+            let _ = device.flush();
+        }
+    }
+}
+
+fn send(device: &mut Option<Box<dyn Write>>, byte: u8, name: &str) {
+    if let Some(output) = device.as_mut() {
+        if let Err(e) = output.write_all(&[byte]) {
+            println!("{}: {}", name, e);
+            *device = None;
+        }
+    }
+}
+
+/// Output to `target`: a file, which can be a named pipe, or `|COMMAND`
+/// for the standard input of a shell command
+pub fn open_output(target: &str) -> io::Result<Box<dyn Write>> {
+    Ok(match target.strip_prefix('|') {
+        Some(command) => Box::new(BufWriter::new(Pipe::spawn(command, Stdio::piped(), Stdio::inherit())?)),
+        None => Box::new(BufWriter::new(File::create(target)?)),
+    })
+}
+
+/// Input from `target`: a file, or `|COMMAND` for the standard output of a
+/// shell command
+pub fn open_input(target: &str) -> io::Result<Box<dyn Read>> {
+    Ok(match target.strip_prefix('|') {
+        Some(command) => Box::new(BufReader::new(Pipe::spawn(command, Stdio::null(), Stdio::piped())?)),
+        None => Box::new(BufReader::new(File::open(target)?)),
+    })
+}
+
+/// A shell command at the other end of a device. Dropping it closes its
+/// input and output and waits for it to finish.
+struct Pipe {
+    child: Child,
+}
+
+impl Pipe {
+    fn spawn(command: &str, stdin: Stdio, stdout: Stdio) -> io::Result<Self> {
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(stdin)
+            .stdout(stdout)
+            .spawn()?;
+        Ok(Self { child })
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.child.stdin.as_mut().ok_or(io::ErrorKind::BrokenPipe)?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.child.stdin.as_mut().map_or(Ok(()), |stdin| stdin.flush())
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.child.stdout.as_mut().ok_or(io::ErrorKind::BrokenPipe)?.read(buf)
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        drop(self.child.stdin.take());
+        drop(self.child.stdout.take());
+        let _ = self.child.wait();
+    }
+}
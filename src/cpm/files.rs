@@ -0,0 +1,137 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use crate::cpmfile::{FileName, EOF, RECORD};
+
+/// A file on a host directory standing in for a CP/M drive
+#[derive(Clone, Debug)]
+pub struct HostFile {
+    pub name: FileName,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+impl HostFile {
+    /// Length in 128 byte records, counting a partial last record
+    pub fn records(&self) -> u32 {
+        self.size.div_ceil(RECORD as u64) as u32
+    }
+
+    /// Record `record` of the file, padded with ^Z past the end of the
+    /// host file, or `None` if it starts past the end
+    pub fn read_record(&self, record: u32) -> io::Result<Option<[u8; RECORD]>> {
+        let mut file = fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(record as u64 * RECORD as u64))?;
+
+        let mut data = [EOF; RECORD];
+        let mut length = 0;
+        while length < RECORD {
+            match file.read(&mut data[length..])? {
+                0 => break,
+                n => length += n,
+            }
+        }
+        Ok((length > 0).then_some(data))
+    }
+
+    pub fn write_record(&mut self, record: u32, data: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new().write(true).open(&self.path)?;
+        let offset = record as u64 * RECORD as u64;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(data)?;
+        self.size = self.size.max(offset + data.len() as u64);
+        Ok(())
+    }
+}
+
+/// Host directories mounted as drives A: to P:. User 0 is the directory
+/// itself and users 1 to 15 are subdirectories named by number.
+pub struct Drives {
+    directories: Vec<Option<PathBuf>>,
+}
+
+impl Drives {
+    pub const COUNT: usize = 16;
+
+    pub fn new() -> Self {
+        Self {
+            directories: vec![None; Self::COUNT],
+        }
+    }
+
+    pub fn mount(&mut self, drive: u8, directory: PathBuf) {
+        self.directories[drive as usize] = Some(directory);
+    }
+
+    pub fn is_mounted(&self, drive: u8) -> bool {
+        self.directories.get(drive as usize).is_some_and(Option::is_some)
+    }
+
+    /// Bit n set for every drive n that is mounted
+    pub fn vector(&self) -> u16 {
+        (0..Self::COUNT as u8)
+            .filter(|&drive| self.is_mounted(drive))
+            .fold(0, |vector, drive| vector | 1 << drive)
+    }
+
+    fn directory(&self, drive: u8, user: u8) -> Option<PathBuf> {
+        let directory = self.directories.get(drive as usize)?.as_ref()?;
+        Some(match user {
+            0 => directory.clone(),
+            user => directory.join(user.to_string()),
+        })
+    }
+
+    /// Files matching `pattern`, sorted by name. Host files whose names
+    /// CP/M can't have are left out.
+    pub fn list(&self, drive: u8, user: u8, pattern: &FileName) -> Vec<HostFile> {
+        let entries = match self
+            .directory(drive, user)
+            .and_then(|directory| fs::read_dir(directory).ok())
+        {
+            Some(entries) => entries,
+            None => return Vec::new(),
+        };
+
+        let mut files: Vec<HostFile> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+            .filter_map(|entry| {
+                let name = FileName::parse(entry.file_name().to_str()?)?;
+                (!name.is_ambiguous() && pattern.matches(&name)).then(|| HostFile {
+                    name,
+                    path: entry.path(),
+                    size: entry.metadata().map_or(0, |metadata| metadata.len()),
+                })
+            })
+            .collect();
+        files.sort_by_key(|file| file.name);
+        files
+    }
+
+    pub fn find(&self, drive: u8, user: u8, name: &FileName) -> Option<HostFile> {
+        self.list(drive, user, name).into_iter().next()
+    }
+
+    /// Creates an empty file, replacing any file of the same name
+    pub fn create(&self, drive: u8, user: u8, name: &FileName) -> io::Result<HostFile> {
+        let directory = self.directory(drive, user).ok_or(io::ErrorKind::NotFound)?;
+        fs::create_dir_all(&directory)?;
+
+        let path = match self.find(drive, user, name) {
+            Some(file) => file.path,
+            None => directory.join(name.to_string()),
+        };
+        fs::File::create(&path)?;
+        Ok(HostFile {
+            name: *name,
+            path,
+            size: 0,
+        })
+    }
+
+    pub fn rename(&self, file: &HostFile, name: &FileName) -> io::Result<()> {
+        fs::rename(&file.path, file.path.with_file_name(name.to_string()))
+    }
+}
@@ -0,0 +1,428 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::cpm::bdos::Outcome;
+use crate::cpm::devices;
+use crate::cpm::{Cpm, BDOS_PORT};
+use crate::cpmfile::FileName;
+use crate::terminal;
+use crate::testing::{loopback, Loopback, SharedBuffer};
+
+/// A directory for drive A, removed at the end of the test
+struct Drive(PathBuf);
+
+impl Drive {
+    fn new(test: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("emulator-8080-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Drive(path)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for Drive {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn machine(drive: &Drive) -> (Cpm, Rc<RefCell<Loopback>>) {
+    let terminal = loopback(b"");
+    let mut machine = Cpm::new(terminal.clone());
+    machine.mount(0, drive.0.clone());
+    (machine, terminal)
+}
+
+/// Runs each command from the A> prompt until the CCP is back at it
+fn run(machine: &mut Cpm, commands: &[&str]) {
+    for command in commands {
+        machine.queue(command);
+    }
+    for _ in 0..1000 {
+        machine.run(100_000);
+        if machine.is_idle() {
+            return;
+        }
+    }
+    panic!("Still running {:?}", commands);
+}
+
+/// Makes BDOS call `function` with `de` and returns A
+fn bdos(machine: &mut Cpm, function: u8, de: u16) -> u8 {
+    machine.cpu.set_bc(function as u16);
+    machine.cpu.set_de(de);
+    assert_eq!(machine.bdos.call(&mut machine.cpu), Outcome::Done);
+    machine.cpu.a()
+}
+
+/// Puts an FCB for `name` at `address`
+fn fcb(machine: &mut Cpm, address: u16, name: &str) {
+    let address = address as usize;
+    machine.cpu.memory[address..address + 36].fill(0);
+    machine.cpu.memory[address + 1..address + 12].copy_from_slice(&FileName::parse(name).unwrap().0);
+}
+
+fn copy_test_program(drive: &Drive, name: &str) {
+    fs::copy(Path::new("test_data").join(name), drive.path(name)).unwrap();
+}
+
+#[test]
+fn test_prompt_and_dir() {
+    let drive = Drive::new("dir");
+    for name in [
+        "PIP.COM",
+        "STAT.COM",
+        "ASM.COM",
+        "DUMP.ASM",
+        "ED.COM",
+        "readme.txt",
+        "not a cpm name",
+    ] {
+        fs::write(drive.path(name), b"").unwrap();
+    }
+    let (mut machine, terminal) = machine(&drive);
+
+    run(&mut machine, &[]);
+    assert_eq!(terminal.borrow_mut().take_output(), "\r\nA>");
+
+    run(&mut machine, &["dir", "dir *.com", "dir x.*", "b:"]);
+    assert_eq!(
+        terminal.borrow_mut().take_output(),
+        concat!(
+            "dir\r\n",
+            "A: ASM      COM : DUMP     ASM : ED       COM : PIP      COM\r\n",
+            "A: README   TXT : STAT     COM\r\n",
+            "\r\nA>dir *.com\r\n",
+            "A: ASM      COM : ED       COM : PIP      COM : STAT     COM\r\n",
+            "\r\nA>dir x.*\r\n",
+            "NO FILE\r\n",
+            "\r\nA>b:\r\n",
+            "B:?\r\n",
+            "\r\nA>",
+        )
+    );
+}
+
+#[test]
+fn test_builtins() {
+    let drive = Drive::new("builtins");
+    fs::write(drive.path("HELLO.TXT"), b"Hello\nWorld\r\n\x1aIgnored").unwrap();
+    fs::write(drive.path("OTHER.TXT"), b"").unwrap();
+    let (mut machine, terminal) = machine(&drive);
+
+    run(&mut machine, &["TYPE HELLO.TXT"]);
+    assert!(terminal
+        .borrow_mut()
+        .take_output()
+        .ends_with("TYPE HELLO.TXT\r\nHello\r\nWorld\r\n\r\nA>"));
+
+    run(
+        &mut machine,
+        &[
+            "REN GREET.TXT=HELLO.TXT",
+            "REN GREET.TXT=OTHER.TXT",
+            "REN NEW.TXT=MISSING.TXT",
+        ],
+    );
+    assert!(drive.path("GREET.TXT").exists() && !drive.path("HELLO.TXT").exists());
+    let output = terminal.borrow_mut().take_output();
+    assert!(output.contains("FILE EXISTS\r\n") && output.contains("NO FILE\r\n"));
+
+    machine.cpu.memory[0x100..0x300].fill(0x42);
+    run(&mut machine, &["SAVE 2 DATA.BIN", "ERA OTHER.TXT", "ERA OTHER.TXT"]);
+    assert_eq!(fs::read(drive.path("DATA.BIN")).unwrap(), [0x42; 512]);
+    assert!(!drive.path("OTHER.TXT").exists());
+    assert!(terminal
+        .borrow_mut()
+        .take_output()
+        .ends_with("ERA OTHER.TXT\r\nNO FILE\r\n\r\nA>"));
+
+    // User 3 has a directory of its own
+    run(&mut machine, &["USER 3", "SAVE 1 USER.BIN", "DIR", "USER 0"]);
+    assert!(drive.path("3").join("USER.BIN").exists());
+    assert!(terminal.borrow_mut().take_output().contains("A: USER     BIN\r\n"));
+
+    // ERA *.* asks first
+    terminal.borrow_mut().input.extend(b"n\r");
+    run(&mut machine, &["ERA *.*"]);
+    assert!(drive.path("DATA.BIN").exists());
+    terminal.borrow_mut().input.extend(b"y\r");
+    run(&mut machine, &["ERA *.*"]);
+    assert!(!drive.path("DATA.BIN").exists() && !drive.path("GREET.TXT").exists());
+    assert!(terminal.borrow_mut().take_output().contains("ALL (Y/N)?"));
+
+    // A program too big for the TPA leaves the CCP at its prompt, rather
+    // than running whatever the CPU was at, here a JMP to itself
+    fs::write(drive.path("BIG.COM"), vec![0; 0xFE00]).unwrap();
+    machine.cpu.memory[0x8000..0x8003].copy_from_slice(&[0xC3, 0x00, 0x80]);
+    machine.cpu.set_pc(0x8000);
+    run(&mut machine, &["BIG"]);
+    assert!(terminal
+        .borrow_mut()
+        .take_output()
+        .ends_with("BIG\r\nBAD LOAD\r\n\r\nA>"));
+}
+
+#[test]
+fn test_command_tail_and_fcbs() {
+    let drive = Drive::new("tail");
+    // Just HLT, which returns to the CCP
+    fs::write(drive.path("PROG.COM"), [0x76]).unwrap();
+    let (mut machine, terminal) = machine(&drive);
+
+    run(&mut machine, &["prog b:file.txt *.asm"]);
+    let memory = &machine.cpu.memory;
+    assert_eq!(memory[0x5C], 2);
+    assert_eq!(&memory[0x5D..0x68], b"FILE    TXT");
+    assert_eq!(memory[0x6C], 0);
+    assert_eq!(&memory[0x6D..0x78], b"????????ASM");
+    assert_eq!(memory[0x7C..0x80], [0; 4]);
+    assert_eq!(memory[0x80], 17);
+    assert_eq!(&memory[0x81..0x93], b" B:FILE.TXT *.ASM\0");
+    assert_eq!(machine.bdos.dma, 0x80);
+
+    run(&mut machine, &["PROG", "MISSING", "PROG.COM"]);
+    assert_eq!(machine.cpu.memory[0x80..0x82], [0, 0]);
+    assert_eq!(&machine.cpu.memory[0x5D..0x68], b"           ");
+    let output = terminal.borrow_mut().take_output();
+    assert!(output.contains("MISSING\r\nMISSING?\r\n") && output.contains("PROG.COM?\r\n"));
+}
+
+#[test]
+fn test_runs_cpu_diagnostic() {
+    let drive = Drive::new("diagnostic");
+    copy_test_program(&drive, "TST8080.COM");
+    let (mut machine, terminal) = machine(&drive);
+
+    // It prints through BDOS function 9 and exits with a JMP 0
+    run(&mut machine, &["TST8080"]);
+    let output = terminal.borrow_mut().take_output();
+    assert!(output.contains("CPU IS OPERATIONAL"), "{}", output);
+    assert!(output.ends_with("\r\nA>"));
+}
+
+#[test]
+fn test_out_outside_the_stubs() {
+    let drive = Drive::new("out");
+    // MVI C,2; MVI E,'X'; OUT BDOS_PORT; HLT
+    fs::write(drive.path("OUT.COM"), [0x0E, 0x02, 0x1E, b'X', 0xD3, BDOS_PORT, 0x76]).unwrap();
+    let (mut machine, terminal) = machine(&drive);
+
+    // Only a call through the BDOS entry prints
+    run(&mut machine, &["OUT"]);
+    assert!(terminal.borrow_mut().take_output().ends_with("OUT\r\n\r\nA>"));
+}
+
+#[test]
+fn test_file_functions() {
+    let drive = Drive::new("files");
+    let (mut machine, _) = machine(&drive);
+    const FCB: u16 = 0x5C;
+
+    // Make a file 200 records long, then read it back
+    fcb(&mut machine, FCB, "DATA.DAT");
+    assert_eq!(bdos(&mut machine, 22, FCB), 0);
+    for record in 0..200u8 {
+        machine.cpu.memory[0x80..0x100].fill(record);
+        assert_eq!(bdos(&mut machine, 21, FCB), 0);
+    }
+    assert_eq!(bdos(&mut machine, 16, FCB), 0);
+    assert_eq!(fs::metadata(drive.path("DATA.DAT")).unwrap().len(), 200 * 128);
+
+    fcb(&mut machine, FCB, "DATA.DAT");
+    assert_eq!(bdos(&mut machine, 15, FCB), 0);
+    assert_eq!(machine.cpu.memory[FCB as usize + 15], 128);
+    for record in 0..200u8 {
+        assert_eq!(bdos(&mut machine, 20, FCB), 0);
+        assert_eq!(machine.cpu.memory[0x80..0x100], [record; 128]);
+    }
+    assert_eq!(bdos(&mut machine, 20, FCB), 1);
+
+    // Random access, then sequential carries on from there
+    machine.cpu.memory[FCB as usize + 33..FCB as usize + 36].copy_from_slice(&[150, 0, 0]);
+    assert_eq!(bdos(&mut machine, 33, FCB), 0);
+    assert_eq!(machine.cpu.memory[0x80], 150);
+    assert_eq!(bdos(&mut machine, 20, FCB), 0);
+    assert_eq!(machine.cpu.memory[0x80], 150);
+    assert_eq!(bdos(&mut machine, 20, FCB), 0);
+    assert_eq!(machine.cpu.memory[0x80], 151);
+    assert_eq!(bdos(&mut machine, 35, FCB), 0);
+    assert_eq!(machine.cpu.memory[FCB as usize + 33..FCB as usize + 36], [200, 0, 0]);
+
+    // Search finds each file once, or every extent when asked
+    fs::write(drive.path("OTHER.DAT"), b"x").unwrap();
+    fcb(&mut machine, FCB, "*.DAT");
+    assert_eq!(bdos(&mut machine, 17, FCB), 0);
+    assert_eq!(&machine.cpu.memory[0x81..0x8C], b"DATA    DAT");
+    assert_eq!(machine.cpu.memory[0x8C], 1);
+    assert_eq!(machine.cpu.memory[0x8F], 72);
+    assert_eq!(bdos(&mut machine, 18, 0), 0);
+    assert_eq!(&machine.cpu.memory[0x81..0x8C], b"OTHER   DAT");
+    assert_eq!(machine.cpu.memory[0x8F], 1);
+    assert_eq!(bdos(&mut machine, 18, 0), 0xFF);
+    machine.cpu.memory[FCB as usize + 12] = b'?';
+    assert_eq!(bdos(&mut machine, 17, FCB), 0);
+    assert_eq!(bdos(&mut machine, 18, 0), 0);
+    assert_eq!(bdos(&mut machine, 18, 0), 0);
+    assert_eq!(bdos(&mut machine, 18, 0), 0xFF);
+
+    // Rename and delete
+    fcb(&mut machine, FCB, "OTHER.DAT");
+    machine.cpu.memory[FCB as usize + 17..FCB as usize + 28].copy_from_slice(b"RENAMED DAT");
+    assert_eq!(bdos(&mut machine, 23, FCB), 0);
+    assert!(drive.path("RENAMED.DAT").exists());
+    fcb(&mut machine, FCB, "*.DAT");
+    assert_eq!(bdos(&mut machine, 19, FCB), 0);
+    assert_eq!(bdos(&mut machine, 19, FCB), 0xFF);
+    assert_eq!(bdos(&mut machine, 15, FCB), 0xFF);
+}
+
+#[test]
+fn test_addresses_wrap() {
+    let drive = Drive::new("wrap");
+    let (mut machine, terminal) = machine(&drive);
+    const FCB: u16 = 0x5C;
+    fs::write(drive.path("DATA.DAT"), [0x42; 128]).unwrap();
+
+    // A record read to or written from near the top of memory carries on
+    // at 0000
+    assert_eq!(bdos(&mut machine, 26, 0xFFC0), 0);
+    fcb(&mut machine, FCB, "DATA.DAT");
+    assert_eq!(bdos(&mut machine, 15, FCB), 0);
+    assert_eq!(bdos(&mut machine, 20, FCB), 0);
+    assert_eq!(machine.cpu.memory[0xFFC0..], [0x42; 64]);
+    assert_eq!(machine.cpu.memory[..64], [0x42; 64]);
+
+    machine.cpu.memory[..64].fill(0x24);
+    assert_eq!(bdos(&mut machine, 21, FCB), 0);
+    let data = fs::read(drive.path("DATA.DAT")).unwrap();
+    assert_eq!(data[128..192], [0x42; 64]);
+    assert_eq!(data[192..], [0x24; 64]);
+
+    fcb(&mut machine, FCB, "*.*");
+    assert_eq!(bdos(&mut machine, 17, FCB), 0);
+    assert_eq!(bdos(&mut machine, 18, 0), 0xFF);
+    assert_eq!(&machine.cpu.memory[0xFFC1..0xFFCC], b"DATA    DAT");
+    assert_eq!(machine.cpu.memory[..64], [0xE5; 64]);
+
+    // As does a line typed into a buffer at FFFF
+    machine.cpu.memory[0xFFFF] = 10;
+    terminal.borrow_mut().input.extend(b"abc\r");
+    assert_eq!(bdos(&mut machine, 10, 0xFFFF), 0);
+    assert_eq!(machine.cpu.memory[..4], [3, b'a', b'b', b'c']);
+}
+
+#[test]
+fn test_read_buffer() {
+    let drive = Drive::new("buffer");
+    let (mut machine, terminal) = machine(&drive);
+    terminal.borrow_mut().input.extend(b"abX\x08c");
+
+    machine.cpu.memory[0x200] = 10;
+    machine.cpu.set_bc(10);
+    machine.cpu.set_de(0x200);
+    assert_eq!(machine.bdos.call(&mut machine.cpu), Outcome::Blocked);
+    terminal.borrow_mut().input.extend(b"\r");
+    assert_eq!(bdos(&mut machine, 10, 0x200), 0);
+    assert_eq!(machine.cpu.memory[0x201..0x205], [3, b'a', b'b', b'c']);
+    assert_eq!(terminal.borrow_mut().take_output(), "abX\x08 \x08c\r");
+}
+
+#[test]
+fn test_list_punch_and_reader() {
+    // Copies RDR: to both PUN: and LST: until ^Z
+    let copy = [
+        0x0E, 0x03, 0xCD, 0x05, 0x00, // loop: MVI C,3; CALL 5
+        0xFE, 0x1A, 0xCA, 0x00, 0x00, // CPI 1Ah; JZ 0
+        0xF5, 0x5F, 0x0E, 0x04, 0xCD, 0x05, 0x00, // PUSH PSW; MOV E,A; MVI C,4; CALL 5
+        0xF1, 0x5F, 0x0E, 0x05, 0xCD, 0x05, 0x00, // POP PSW; MOV E,A; MVI C,5; CALL 5
+        0xC3, 0x00, 0x01, // JMP loop
+    ];
+    let drive = Drive::new("devices");
+    fs::write(drive.path("COPY.COM"), copy).unwrap();
+    let (mut machine, _) = machine(&drive);
+
+    // With nothing attached the reader is at end of file
+    run(&mut machine, &["COPY"]);
+
+    let (list, punch) = (SharedBuffer::default(), SharedBuffer::default());
+    machine.set_list(Box::new(list.clone()));
+    machine.set_punch(Box::new(punch.clone()));
+    machine.set_reader(Box::new(Cursor::new(b"REPORT\r\n\x00\xFF".to_vec())));
+    run(&mut machine, &["COPY"]);
+    assert_eq!(*list.0.borrow(), b"REPORT\r\n\x00\xFF");
+    assert_eq!(*punch.0.borrow(), b"REPORT\r\n\x00\xFF");
+
+    // The reader stays at end of file
+    run(&mut machine, &["COPY"]);
+    assert_eq!(list.0.borrow().len(), 10);
+}
+
+#[test]
+fn test_device_pipes() {
+    let drive = Drive::new("pipes");
+    let path = drive.path("LISTING.PRN");
+
+    let mut list = devices::open_output(&format!("|cat > {}", path.display())).unwrap();
+    list.write_all(b"Page 1\r\n").unwrap();
+    drop(list);
+    assert_eq!(fs::read(&path).unwrap(), b"Page 1\r\n");
+
+    let mut reader = devices::open_input("|printf 'ABC'").unwrap();
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"ABC");
+
+    let mut reader = devices::open_input(path.to_str().unwrap()).unwrap();
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"Page 1\r\n");
+}
+
+#[test]
+fn test_terminal_console() {
+    let drive = Drive::new("terminal");
+    // A screen drawn for an ADM-3A, as a full screen program would
+    fs::write(
+        drive.path("MENU.TXT"),
+        b"\x1B*\x1B=\x25\x28MAIN MENU\x1B=\x27\x28^KS SAVE\x1B=\x28\x28^KX EXIT\x1E",
+    )
+    .unwrap();
+
+    let terminal = Rc::new(RefCell::new(terminal::Terminal::new(terminal::Kind::Adm3a)));
+    let mut machine = Cpm::new(terminal.clone());
+    machine.mount(0, drive.0.clone());
+    run(&mut machine, &["TYPE MENU.TXT"]);
+
+    let snapshot = terminal.borrow().snapshot();
+    let lines: Vec<&str> = snapshot.lines().collect();
+    assert_eq!(
+        lines[..9],
+        [
+            "",
+            "A>",
+            "",
+            "",
+            "",
+            "        MAIN MENU",
+            "",
+            "        ^KS SAVE",
+            "        ^KX EXIT"
+        ]
+    );
+    assert_eq!(terminal.borrow().cursor(), (1, 2));
+
+    // Keys typed on the terminal reach the CCP
+    for &key in b"DIR\r" {
+        terminal.borrow_mut().press(key);
+    }
+    machine.run(1_000_000);
+    assert!(terminal.borrow().snapshot().contains("A: MENU     TXT"));
+}
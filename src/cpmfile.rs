@@ -0,0 +1,92 @@
+use std::fmt;
+
+#[cfg(test)]
+mod tests;
+
+// What CP/M's files are made of, for the emulated BDOS working on host
+// directories and the file system in disk images alike.
+
+/// Bytes in a CP/M record, the unit files are read and written in
+pub const RECORD: usize = 128;
+
+/// Text files end at the first of these in their last record
+pub const EOF: u8 = 0x1A;
+
+/// A CP/M file name as stored in FCBs and the directory: eight characters
+/// of name and three of type, upper case and padded with spaces. `?`
+/// matches any character.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileName(pub [u8; 11]);
+
+impl FileName {
+    /// Parses `NAME.TYP`, where `*` fills the rest of the name or type with
+    /// `?`. `None` if CP/M couldn't have the name.
+    pub fn parse(text: &str) -> Option<FileName> {
+        let (name, kind) = text.split_once('.').unwrap_or((text, ""));
+        let mut bytes = [b' '; 11];
+        if name.is_empty() || !fill(&mut bytes[..8], name) || !fill(&mut bytes[8..], kind) {
+            return None;
+        }
+        Some(FileName(bytes))
+    }
+
+    /// The name in an FCB or directory entry, ignoring the attribute bits
+    /// kept in the top bit of each character
+    pub fn from_fcb(fcb: &[u8]) -> FileName {
+        let mut bytes = [0; 11];
+        for (byte, &fcb) in bytes.iter_mut().zip(&fcb[1..12]) {
+            *byte = (fcb & 0x7F).to_ascii_uppercase();
+        }
+        FileName(bytes)
+    }
+
+    pub fn is_ambiguous(&self) -> bool {
+        self.0.contains(&b'?')
+    }
+
+    /// Whether `self`, which may be ambiguous, matches `name`
+    pub fn matches(&self, name: &FileName) -> bool {
+        self.0
+            .iter()
+            .zip(&name.0)
+            .all(|(&pattern, &byte)| pattern == b'?' || pattern == byte)
+    }
+
+    pub fn name(&self) -> &str {
+        std::str::from_utf8(&self.0[..8]).unwrap_or("").trim_end()
+    }
+
+    pub fn kind(&self) -> &str {
+        std::str::from_utf8(&self.0[8..]).unwrap_or("").trim_end()
+    }
+}
+
+impl fmt::Display for FileName {
+    /// `NAME.TYP`, or `NAME` with no type, which is also the host file name
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind() {
+            "" => write!(f, "{}", self.name()),
+            kind => write!(f, "{}.{}", self.name(), kind),
+        }
+    }
+}
+
+/// Copies a name or type into its space padded field
+fn fill(field: &mut [u8], text: &str) -> bool {
+    if text.len() > field.len() && !text.ends_with('*') {
+        return false;
+    }
+
+    for (i, byte) in text.bytes().enumerate() {
+        match byte.to_ascii_uppercase() {
+            b'*' => {
+                field[i..].fill(b'?');
+                return true;
+            }
+            _ if i >= field.len() => return false,
+            byte if byte.is_ascii_graphic() && !b"<>.,;:=[]_%|()/\\".contains(&byte) => field[i] = byte,
+            _ => return false,
+        }
+    }
+    true
+}
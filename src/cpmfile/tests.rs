@@ -0,0 +1,23 @@
+use crate::cpmfile::FileName;
+
+#[test]
+fn test_file_names() {
+    let name = FileName::parse("dump.asm").unwrap();
+    assert_eq!(&name.0, b"DUMP    ASM");
+    assert_eq!(name.to_string(), "DUMP.ASM");
+    assert_eq!(FileName::parse("README").unwrap().to_string(), "README");
+
+    // Wildcards, and names CP/M can't have
+    let pattern = FileName::parse("D*.A?M").unwrap();
+    assert_eq!(&pattern.0, b"D???????A?M");
+    assert!(pattern.is_ambiguous() && pattern.matches(&name));
+    assert!(!pattern.matches(&FileName::parse("DUMP.COM").unwrap()));
+    for bad in ["", ".COM", "TOOLONGNAME", "A.TOOL", "A:B", "NOT A NAME"] {
+        assert_eq!(FileName::parse(bad), None, "{}", bad);
+    }
+
+    // Attribute bits in an FCB are ignored
+    let mut fcb = *b"\0DUMP    ASM";
+    fcb[9] |= 0x80;
+    assert_eq!(FileName::from_fcb(&fcb), name);
+}
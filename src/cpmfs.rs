@@ -0,0 +1,535 @@
+use crate::cpmfile::{FileName, EOF, RECORD};
+use crate::disk::{DiskError, DiskImage, Geometry};
+
+pub mod cli;
+#[cfg(test)]
+mod tests;
+
+// CP/M 2.2 file systems in disk images, for moving files in and out
+// without booting, as cpmtools does. The data area starts after the
+// reserved (boot) tracks and is divided into blocks of 1K or more. The
+// directory fills the first blocks: 32 byte entries, each naming a file
+// and listing up to 16 of its blocks (8 on disks with more than 256
+// blocks, which need 16 bit block numbers).
+//
+//   0       user number, 0xE5 for an unused entry
+//   1-11    file name and type, attributes in the top bits
+//   12, 14  extent number, low 5 bits in ex and the rest in s2
+//   15      records in the entry's last 16K logical extent
+//   16-31   block numbers, 0 for none
+//
+// An entry holds (EXM + 1) 16K logical extents; a file bigger than that
+// gets more entries, told apart by their extent numbers.
+
+/// The disk parameter block the BIOS gives the BDOS for a drive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dpb {
+    /// 128 byte records per track
+    pub spt: u16,
+    /// Block shift and mask: blocks are 128 << bsh bytes
+    pub bsh: u8,
+    pub blm: u8,
+    /// Extent mask, logical extents per directory entry less one
+    pub exm: u8,
+    /// Highest block number
+    pub dsm: u16,
+    /// Highest directory entry number
+    pub drm: u16,
+    /// Blocks taken by the directory, a bit each from the top of al0
+    pub al0: u8,
+    pub al1: u8,
+    /// Size of the directory check vector
+    pub cks: u16,
+    /// Reserved tracks before the data area
+    pub off: u16,
+}
+
+impl Dpb {
+    pub fn block_size(&self) -> usize {
+        RECORD << self.bsh
+    }
+
+    fn records_per_block(&self) -> usize {
+        self.blm as usize + 1
+    }
+
+    fn blocks(&self) -> usize {
+        self.dsm as usize + 1
+    }
+
+    fn directory_entries(&self) -> usize {
+        self.drm as usize + 1
+    }
+
+    fn directory_blocks(&self) -> usize {
+        u16::from_be_bytes([self.al0, self.al1]).count_ones() as usize
+    }
+
+    fn wide_pointers(&self) -> bool {
+        self.dsm > 255
+    }
+
+    fn pointers_per_entry(&self) -> usize {
+        if self.wide_pointers() {
+            8
+        } else {
+            16
+        }
+    }
+
+    fn records_per_entry(&self) -> usize {
+        (self.exm as usize + 1) * 128
+    }
+
+    /// The DPB as it is laid out in memory
+    pub fn to_bytes(self) -> [u8; 15] {
+        let mut bytes = [0; 15];
+        bytes[0..2].copy_from_slice(&self.spt.to_le_bytes());
+        bytes[2..5].copy_from_slice(&[self.bsh, self.blm, self.exm]);
+        bytes[5..7].copy_from_slice(&self.dsm.to_le_bytes());
+        bytes[7..9].copy_from_slice(&self.drm.to_le_bytes());
+        bytes[9..11].copy_from_slice(&[self.al0, self.al1]);
+        bytes[11..13].copy_from_slice(&self.cks.to_le_bytes());
+        bytes[13..15].copy_from_slice(&self.off.to_le_bytes());
+        bytes
+    }
+}
+
+/// Where a record's 128 bytes are in a sector
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SectorLayout {
+    /// The sector is the record
+    Plain,
+    /// MITS 137 byte sectors, laid out by the software as the 88-DCDD
+    /// leaves it to. On tracks 0 to 5 the record follows the track number
+    /// and two unused bytes, and is followed by a 0xFF stop byte and its
+    /// checksum. On the rest it follows the track and sector numbers, the
+    /// file number and byte count, the checksum and a next group pointer,
+    /// and is followed by the stop byte. CP/M leaves the file number, byte
+    /// count and pointer 0.
+    Altair,
+}
+
+/// Tracks with the shorter Altair sector header
+const ALTAIR_SYSTEM_TRACKS: usize = 6;
+
+/// A disk format: the image geometry and how CP/M's BIOS uses it, like a
+/// cpmtools diskdef
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Format {
+    pub name: &'static str,
+    pub geometry: Geometry,
+    pub dpb: Dpb,
+    /// Sector interleave of the data tracks, 1 for none
+    pub skew: usize,
+    pub layout: SectorLayout,
+}
+
+impl Format {
+    /// 8" single density, as in CP/M's distribution and cpmsim drives A to D
+    pub const IBM_3740: Format = Format {
+        name: "ibm-3740",
+        geometry: Geometry::IBM_3740,
+        dpb: Dpb {
+            spt: 26,
+            bsh: 3,
+            blm: 7,
+            exm: 0,
+            dsm: 242,
+            drm: 63,
+            al0: 0xC0,
+            al1: 0x00,
+            cks: 16,
+            off: 2,
+        },
+        skew: 6,
+        layout: SectorLayout::Plain,
+    };
+    /// z80pack's 4 MB hard disk, cpmsim drives I and J
+    pub const Z80PACK_HD: Format = Format {
+        name: "z80pack-hd",
+        geometry: Geometry::Z80PACK_HD,
+        dpb: Dpb {
+            spt: 128,
+            bsh: 4,
+            blm: 15,
+            exm: 0,
+            dsm: 2039,
+            drm: 1023,
+            al0: 0xFF,
+            al1: 0xFF,
+            cks: 0,
+            off: 0,
+        },
+        skew: 1,
+        layout: SectorLayout::Plain,
+    };
+    /// Altair CP/M 2.2 on an 8" disk for the 88-DCDD
+    pub const ALTAIR: Format = Format {
+        name: "altair",
+        geometry: Geometry::ALTAIR_8INCH,
+        dpb: Dpb {
+            spt: 32,
+            bsh: 4,
+            blm: 15,
+            exm: 0,
+            dsm: 149,
+            drm: 63,
+            al0: 0x80,
+            al1: 0x00,
+            cks: 16,
+            off: 2,
+        },
+        skew: 17,
+        layout: SectorLayout::Altair,
+    };
+
+    pub const ALL: [Format; 3] = [Self::IBM_3740, Self::Z80PACK_HD, Self::ALTAIR];
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        Self::ALL.into_iter().find(|format| format.name == name)
+    }
+
+    /// The usual format of disks with `geometry`. There is none for the
+    /// Altair minidisk.
+    pub fn for_geometry(geometry: Geometry) -> Option<Format> {
+        Self::ALL.into_iter().find(|format| format.geometry == geometry)
+    }
+
+    /// Physical sector of each logical sector in a track, the BIOS's
+    /// sector translation table counting from 0
+    fn translation(&self) -> Vec<usize> {
+        let sectors = self.dpb.spt as usize;
+        let mut table = Vec::with_capacity(sectors);
+        let mut used = vec![false; sectors];
+        let mut sector = 0;
+        for _ in 0..sectors {
+            while used[sector] {
+                sector = (sector + 1) % sectors;
+            }
+            used[sector] = true;
+            table.push(sector);
+            sector = (sector + self.skew) % sectors;
+        }
+        table
+    }
+
+    /// Where the record starts in a sector on `track`
+    fn record_offset(&self, track: usize) -> usize {
+        match self.layout {
+            SectorLayout::Plain => 0,
+            SectorLayout::Altair if track < ALTAIR_SYSTEM_TRACKS => 3,
+            SectorLayout::Altair => 7,
+        }
+    }
+
+    /// The whole of a sector holding `record`
+    fn sector(&self, track: usize, sector: usize, record: &[u8; RECORD]) -> Vec<u8> {
+        let mut data = vec![0; self.geometry.sector_size];
+        let offset = self.record_offset(track);
+        data[offset..offset + RECORD].copy_from_slice(record);
+
+        if self.layout == SectorLayout::Altair {
+            let checksum = record.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+            data[0] = track as u8 | 0x80;
+            if track < ALTAIR_SYSTEM_TRACKS {
+                data[131] = 0xFF;
+                data[132] = checksum;
+            } else {
+                data[1] = sector as u8;
+                data[4] = checksum;
+                data[135] = 0xFF;
+            }
+        }
+        data
+    }
+}
+
+/// A file in the directory, all its entries taken together
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    pub user: u8,
+    pub name: FileName,
+    pub records: usize,
+}
+
+impl DirEntry {
+    pub fn size(&self) -> usize {
+        self.records * RECORD
+    }
+}
+
+type Entry = [u8; 32];
+
+const UNUSED: u8 = 0xE5;
+/// Users 0 to 15 hold files, higher numbers are labels and the like
+const USERS: u8 = 16;
+
+pub struct FileSystem {
+    disk: DiskImage,
+    format: Format,
+    translation: Vec<usize>,
+}
+
+impl FileSystem {
+    pub fn new(disk: DiskImage, format: Format) -> Result<Self, String> {
+        if disk.geometry() != format.geometry {
+            return Err(format!("The disk image is not in the {} format", format.name));
+        }
+        Ok(Self {
+            disk,
+            translation: format.translation(),
+            format,
+        })
+    }
+
+    /// File system on `disk` in the usual format for its size
+    pub fn open(disk: DiskImage) -> Result<Self, String> {
+        let format = Format::for_geometry(disk.geometry()).ok_or("There is no CP/M format for this disk image")?;
+        Self::new(disk, format)
+    }
+
+    pub fn disk(&self) -> &DiskImage {
+        &self.disk
+    }
+
+    /// Empties the directory, leaving the boot tracks alone
+    pub fn make(&mut self) -> Result<(), String> {
+        for index in 0..self.format.dpb.directory_entries() {
+            self.write_entry(index, &[UNUSED; 32])?;
+        }
+        Ok(())
+    }
+
+    /// Files sorted by user and name
+    pub fn files(&self) -> Vec<DirEntry> {
+        let mut files: Vec<DirEntry> = Vec::new();
+        for entry in self.entries().into_iter().filter(|entry| entry[0] < USERS) {
+            let (user, name) = (entry[0], FileName::from_fcb(&entry[..12]));
+            let records = self.entry_start(&entry) + self.entry_records(&entry);
+            match files.iter_mut().find(|file| file.user == user && file.name == name) {
+                Some(file) => file.records = file.records.max(records),
+                None => files.push(DirEntry { user, name, records }),
+            }
+        }
+        files.sort_by_key(|file| (file.user, file.name));
+        files
+    }
+
+    /// Contents of a file, whole records. Records never written, in files
+    /// written at random, read as zeros.
+    pub fn read(&self, user: u8, name: &FileName) -> Result<Vec<u8>, String> {
+        let entries = self.file_entries(user, name);
+        if entries.is_empty() {
+            return Err(format!("{}: no such file", name));
+        }
+
+        let records_per_block = self.format.dpb.records_per_block();
+        let mut data = Vec::new();
+        for (_, entry) in entries {
+            let start = self.entry_start(&entry);
+            let records = self.entry_records(&entry);
+            data.resize(data.len().max((start + records) * RECORD), 0);
+
+            for record in 0..records {
+                let block = self.pointer(&entry, record / records_per_block);
+                if block != 0 {
+                    let offset = (start + record) * RECORD;
+                    let data_record = block * records_per_block + record % records_per_block;
+                    data[offset..offset + RECORD].copy_from_slice(&self.read_record(data_record)?);
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    /// Writes a file, replacing any of the same name. A partial last
+    /// record is padded with ^Z.
+    pub fn write(&mut self, user: u8, name: &FileName, data: &[u8]) -> Result<(), String> {
+        if name.is_ambiguous() || user >= USERS {
+            return Err(format!("{}: not a file name CP/M can use", name));
+        }
+
+        let dpb = self.format.dpb;
+        let records = data.len().div_ceil(RECORD);
+        let records_per_block = dpb.records_per_block();
+        let records_per_entry = dpb
+            .records_per_entry()
+            .min(dpb.pointers_per_entry() * records_per_block);
+        let entry_count = records.div_ceil(records_per_entry).max(1);
+
+        // Make room first, a file being replaced frees its own space
+        let old_entries = self.file_entries(user, name);
+        let mut entries = self.entries();
+        for (index, _) in &old_entries {
+            entries[*index][0] = UNUSED;
+        }
+        let free_entries: Vec<usize> = (0..entries.len())
+            .filter(|&index| entries[index][0] == UNUSED)
+            .collect();
+        let free_blocks = self.free_blocks_in(&entries);
+        if free_entries.len() < entry_count {
+            return Err(format!("{}: the directory is full", name));
+        }
+        if free_blocks.len() < records.div_ceil(records_per_block) {
+            return Err(format!("{}: the disk is full", name));
+        }
+        for (index, _) in &old_entries {
+            self.write_entry(*index, &[UNUSED; 32])?;
+        }
+
+        let mut blocks = free_blocks.into_iter();
+        for (n, &index) in free_entries.iter().take(entry_count).enumerate() {
+            let first = n * records_per_entry;
+            let count = (records - first.min(records)).min(records_per_entry);
+
+            let mut entry = [0; 32];
+            entry[0] = user;
+            entry[1..12].copy_from_slice(&name.0);
+            let last = count.saturating_sub(1) / 128;
+            let extent = n * (dpb.exm as usize + 1) + last;
+            entry[12] = (extent % 32) as u8;
+            entry[14] = (extent / 32) as u8;
+            entry[15] = (count - last * 128) as u8;
+
+            for block_index in 0..count.div_ceil(records_per_block) {
+                let block = blocks.next().unwrap();
+                set_pointer(&mut entry, block_index, block, dpb.wide_pointers());
+                for record in 0..records_per_block {
+                    let offset = (first + block_index * records_per_block + record) * RECORD;
+                    if offset >= data.len() {
+                        break;
+                    }
+                    let mut buffer = [EOF; RECORD];
+                    let length = (data.len() - offset).min(RECORD);
+                    buffer[..length].copy_from_slice(&data[offset..offset + length]);
+                    self.write_record(block * records_per_block + record, &buffer)?;
+                }
+            }
+            self.write_entry(index, &entry)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a file, marking its entries unused as CP/M does
+    pub fn remove(&mut self, user: u8, name: &FileName) -> Result<(), String> {
+        let entries = self.file_entries(user, name);
+        if entries.is_empty() {
+            return Err(format!("{}: no such file", name));
+        }
+        for (index, mut entry) in entries {
+            entry[0] = UNUSED;
+            self.write_entry(index, &entry)?;
+        }
+        Ok(())
+    }
+
+    /// Free space in bytes
+    pub fn free(&self) -> usize {
+        self.free_blocks_in(&self.entries()).len() * self.format.dpb.block_size()
+    }
+
+    fn free_blocks_in(&self, entries: &[Entry]) -> Vec<usize> {
+        let dpb = self.format.dpb;
+        let mut used = vec![false; dpb.blocks()];
+        used[..dpb.directory_blocks()].fill(true);
+        for entry in entries.iter().filter(|entry| entry[0] < USERS) {
+            for i in 0..dpb.pointers_per_entry() {
+                if let Some(used) = used.get_mut(self.pointer(entry, i)) {
+                    *used = true;
+                }
+            }
+        }
+        (0..dpb.blocks()).filter(|&block| !used[block]).collect()
+    }
+
+    /// The directory entries of a file, with their indexes, in order
+    fn file_entries(&self, user: u8, name: &FileName) -> Vec<(usize, Entry)> {
+        let mut entries: Vec<(usize, Entry)> = self
+            .entries()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, entry)| entry[0] == user && FileName::from_fcb(&entry[..12]) == *name)
+            .collect();
+        entries.sort_by_key(|(_, entry)| extent(entry));
+        entries
+    }
+
+    /// Record in the file of the entry's first record
+    fn entry_start(&self, entry: &Entry) -> usize {
+        let exm = self.format.dpb.exm as usize;
+        (extent(entry) & !exm) * 128
+    }
+
+    fn entry_records(&self, entry: &Entry) -> usize {
+        let exm = self.format.dpb.exm as usize;
+        (extent(entry) & exm) * 128 + (entry[15] as usize).min(128)
+    }
+
+    fn pointer(&self, entry: &Entry, index: usize) -> usize {
+        if self.format.dpb.wide_pointers() {
+            u16::from_le_bytes([entry[16 + 2 * index], entry[17 + 2 * index]]) as usize
+        } else {
+            entry[16 + index] as usize
+        }
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        let count = self.format.dpb.directory_entries();
+        let mut entries = Vec::with_capacity(count);
+        for record in 0..count.div_ceil(4) {
+            let data = self.read_record(record).unwrap_or([UNUSED; RECORD]);
+            for entry in data.chunks(32) {
+                entries.push(entry.try_into().unwrap());
+            }
+        }
+        entries.truncate(count);
+        entries
+    }
+
+    fn write_entry(&mut self, index: usize, entry: &Entry) -> Result<(), String> {
+        let mut record = self.read_record(index / 4)?;
+        record[index % 4 * 32..][..32].copy_from_slice(entry);
+        self.write_record(index / 4, &record)
+    }
+
+    /// Track and physical sector of a record in the data area
+    fn locate(&self, record: usize) -> (usize, usize) {
+        let dpb = self.format.dpb;
+        let spt = dpb.spt as usize;
+        let record = dpb.off as usize * spt + record;
+        (record / spt, self.translation[record % spt])
+    }
+
+    fn read_record(&self, record: usize) -> Result<[u8; RECORD], String> {
+        let (track, sector) = self.locate(record);
+        let data = self
+            .disk
+            .read_sector(track, sector)
+            .ok_or(format!("No track {} sector {}", track, sector))?;
+        let offset = self.format.record_offset(track);
+        Ok(data[offset..offset + RECORD].try_into().unwrap())
+    }
+
+    fn write_record(&mut self, record: usize, data: &[u8; RECORD]) -> Result<(), String> {
+        let (track, sector) = self.locate(record);
+        let data = self.format.sector(track, sector, data);
+        self.disk.write_sector(track, sector, &data).map_err(|e| match e {
+            DiskError::WriteProtected => "The disk is write protected".to_string(),
+            DiskError::NoSuchSector => format!("No track {} sector {}", track, sector),
+        })
+    }
+}
+
+/// Extent number of a directory entry, from ex and s2
+fn extent(entry: &Entry) -> usize {
+    (entry[12] as usize & 0x1F) | (entry[14] as usize & 0x3F) << 5
+}
+
+fn set_pointer(entry: &mut Entry, index: usize, block: usize, wide: bool) {
+    if wide {
+        entry[16 + 2 * index..18 + 2 * index].copy_from_slice(&(block as u16).to_le_bytes());
+    } else {
+        entry[16 + index] = block as u8;
+    }
+}
@@ -0,0 +1,188 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{FileSystem, Format};
+use crate::cpmfile::FileName;
+use crate::disk::DiskImage;
+
+/// Command line of `emulator-8080 cpmfs`: the command, its arguments, and
+/// the options that can go anywhere among them
+#[derive(Default)]
+struct Options {
+    arguments: Vec<String>,
+    format: Option<Format>,
+    user: u8,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" {
+                usage(0);
+            }
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name,
+                None => {
+                    options.arguments.push(arg);
+                    continue;
+                }
+            };
+            let value = args.next().ok_or(format!("{} needs a value", arg))?;
+
+            match name {
+                "format" => {
+                    options.format = Some(Format::from_name(&value).ok_or_else(|| {
+                        let names: Vec<&str> = Format::ALL.iter().map(|format| format.name).collect();
+                        format!("Unknown format {}, expected one of {}", value, names.join(", "))
+                    })?)
+                }
+                "user" => {
+                    options.user = value
+                        .parse()
+                        .ok()
+                        .filter(|&user| user < 16)
+                        .ok_or(format!("Users are 0 to 15, got {}", value))?
+                }
+                _ => return Err(format!("Unknown option: {}", name)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+pub fn main(args: impl Iterator<Item = String>) {
+    let options = Options::parse(args).unwrap_or_else(|e| {
+        println!("{}", e);
+        usage(1);
+    });
+
+    let arguments: Vec<&str> = options.arguments.iter().map(String::as_str).collect();
+    let result = match arguments.as_slice() {
+        ["ls", image, names @ ..] => list(&options, image, names),
+        ["cp", "in", image, files @ ..] if !files.is_empty() => copy_in(&options, image, files),
+        ["cp", "out", image, directory, names @ ..] => copy_out(&options, image, directory, names),
+        ["rm", image, names @ ..] if !names.is_empty() => remove(&options, image, names),
+        ["mkfs", image] => make(&options, image),
+        _ => usage(1),
+    };
+
+    if let Err(e) = result {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Prints the usage and exits with `code`
+fn usage(code: i32) -> ! {
+    println!("Usage: emulator-8080 cpmfs ls IMAGE [NAME]...");
+    println!("       emulator-8080 cpmfs cp in IMAGE FILE...");
+    println!("       emulator-8080 cpmfs cp out IMAGE DIRECTORY [NAME]...");
+    println!("       emulator-8080 cpmfs rm IMAGE NAME...");
+    println!("       emulator-8080 cpmfs mkfs IMAGE");
+    println!("Options: --format ibm-3740|z80pack-hd|altair, by default from the image size, and --user N");
+    println!("NAMEs are CP/M file names, with * and ? wildcards.");
+    std::process::exit(code);
+}
+
+fn open(options: &Options, image: &str, write_protected: bool) -> Result<FileSystem, String> {
+    let disk = DiskImage::open(Path::new(image), write_protected)?;
+    if !write_protected && disk.is_write_protected() {
+        return Err(format!("{} is write protected", image));
+    }
+    match options.format {
+        Some(format) => FileSystem::new(disk, format),
+        None => FileSystem::open(disk),
+    }
+}
+
+/// CP/M file names from the command line, every file if there are none
+fn patterns(names: &[&str]) -> Result<Vec<FileName>, String> {
+    if names.is_empty() {
+        return Ok(vec![FileName([b'?'; 11])]);
+    }
+    names
+        .iter()
+        .map(|name| FileName::parse(name).ok_or(format!("{} is not a CP/M file name", name)))
+        .collect()
+}
+
+fn list(options: &Options, image: &str, names: &[&str]) -> Result<(), String> {
+    let file_system = open(options, image, true)?;
+    let patterns = patterns(names)?;
+
+    let files: Vec<_> = file_system
+        .files()
+        .into_iter()
+        .filter(|file| file.user == options.user && patterns.iter().any(|pattern| pattern.matches(&file.name)))
+        .collect();
+    for file in &files {
+        println!("{:<12} {:>7}", file.name.to_string(), file.size());
+    }
+    println!("{} files, {}K free", files.len(), file_system.free() / 1024);
+    Ok(())
+}
+
+fn copy_in(options: &Options, image: &str, files: &[&str]) -> Result<(), String> {
+    let mut file_system = open(options, image, false)?;
+    for file in files {
+        let path = PathBuf::from(file);
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(FileName::parse)
+            .filter(|name| !name.is_ambiguous())
+            .ok_or(format!("{} can't be a CP/M file name", path.display()))?;
+        let data = fs::read(&path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        file_system.write(options.user, &name, &data)?;
+    }
+    Ok(())
+}
+
+fn copy_out(options: &Options, image: &str, directory: &str, names: &[&str]) -> Result<(), String> {
+    let file_system = open(options, image, true)?;
+    let patterns = patterns(names)?;
+
+    let mut copied = 0;
+    for file in file_system.files().into_iter().filter(|file| file.user == options.user) {
+        if patterns.iter().any(|pattern| pattern.matches(&file.name)) {
+            let path = Path::new(directory).join(file.name.to_string());
+            let data = file_system.read(file.user, &file.name)?;
+            fs::write(&path, data).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+            copied += 1;
+        }
+    }
+    if copied == 0 {
+        return Err("No files to copy".into());
+    }
+    Ok(())
+}
+
+fn remove(options: &Options, image: &str, names: &[&str]) -> Result<(), String> {
+    let mut file_system = open(options, image, false)?;
+    for pattern in patterns(names)? {
+        let files: Vec<_> = file_system
+            .files()
+            .into_iter()
+            .filter(|file| file.user == options.user && pattern.matches(&file.name))
+            .collect();
+        if files.is_empty() {
+            return Err(format!("{}: no such file", pattern));
+        }
+        for file in files {
+            file_system.remove(file.user, &file.name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a new, empty image. Without a boot loader on its reserved
+/// tracks it can't be booted, only used as a second drive.
+fn make(options: &Options, image: &str) -> Result<(), String> {
+    let format = options.format.unwrap_or(Format::IBM_3740);
+    let mut file_system = FileSystem::new(DiskImage::new(format.geometry), format)?;
+    file_system.make()?;
+    fs::write(image, file_system.disk().data()).map_err(|e| format!("Could not write {}: {}", image, e))
+}
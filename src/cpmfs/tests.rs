@@ -0,0 +1,185 @@
+use crate::cpmfile::FileName;
+use crate::cpmfs::{FileSystem, Format};
+use crate::disk::{DiskImage, Geometry};
+
+fn name(name: &str) -> FileName {
+    FileName::parse(name).unwrap()
+}
+
+fn empty(format: Format) -> FileSystem {
+    let mut file_system = FileSystem::new(DiskImage::new(format.geometry), format).unwrap();
+    file_system.make().unwrap();
+    file_system
+}
+
+fn pattern(length: usize) -> Vec<u8> {
+    (0..length).map(|i| (i * 7 + i / 251) as u8).collect()
+}
+
+#[test]
+fn test_format_parameters() {
+    // CP/M 2.2's own 8" BIOS translation table, counting from 1
+    let table = [
+        1, 7, 13, 19, 25, 5, 11, 17, 23, 3, 9, 15, 21, 2, 8, 14, 20, 26, 6, 12, 18, 24, 4, 10, 16, 22,
+    ];
+    let translation: Vec<usize> = table.iter().map(|sector| sector - 1).collect();
+    assert_eq!(Format::IBM_3740.translation(), translation);
+    assert_eq!(Format::Z80PACK_HD.translation(), (0..128).collect::<Vec<_>>());
+
+    assert_eq!(
+        Format::IBM_3740.dpb.to_bytes(),
+        [26, 0, 3, 7, 0, 242, 0, 63, 0, 0xC0, 0, 16, 0, 2, 0]
+    );
+    assert_eq!(Format::IBM_3740.dpb.block_size(), 1024);
+    assert_eq!(Format::Z80PACK_HD.dpb.block_size(), 2048);
+    assert_eq!(Format::from_name("z80pack-hd"), Some(Format::Z80PACK_HD));
+    assert_eq!(Format::from_name("nonsense"), None);
+}
+
+#[test]
+fn test_empty_disk() {
+    let file_system = empty(Format::IBM_3740);
+    assert!(file_system.files().is_empty());
+    // 243 blocks less 2 for the directory
+    assert_eq!(file_system.free(), 241 * 1024);
+
+    // A freshly formatted image is an empty file system already
+    let file_system = FileSystem::open(DiskImage::new(Format::IBM_3740.geometry)).unwrap();
+    assert!(file_system.files().is_empty());
+}
+
+#[test]
+fn test_write_read_and_remove() {
+    let mut file_system = empty(Format::IBM_3740);
+    let small = b"10 PRINT \"HELLO\"\r\n".to_vec();
+    // Three directory entries: 16K, 16K and 8K and a bit
+    let large = pattern(40_000);
+
+    file_system.write(0, &name("HELLO.BAS"), &small).unwrap();
+    file_system.write(0, &name("BIG.DAT"), &large).unwrap();
+    file_system.write(3, &name("EMPTY"), &[]).unwrap();
+
+    let files = file_system.files();
+    let listing: Vec<(u8, String, usize)> = files
+        .iter()
+        .map(|file| (file.user, file.name.to_string(), file.size()))
+        .collect();
+    assert_eq!(
+        listing,
+        [
+            (0, "BIG.DAT".into(), 40_064),
+            (0, "HELLO.BAS".into(), 128),
+            (3, "EMPTY".into(), 0)
+        ]
+    );
+    assert_eq!(file_system.free(), (241 - 40 - 1) * 1024);
+
+    // Short records are padded with ^Z
+    let mut padded = small.clone();
+    padded.resize(128, 0x1A);
+    assert_eq!(file_system.read(0, &name("HELLO.BAS")).unwrap(), padded);
+    assert_eq!(file_system.read(0, &name("BIG.DAT")).unwrap()[..40_000], large);
+    assert_eq!(file_system.read(3, &name("EMPTY")).unwrap(), []);
+    assert!(file_system.read(0, &name("EMPTY")).is_err());
+
+    // Replacing a file reuses its space
+    file_system.write(0, &name("BIG.DAT"), &small).unwrap();
+    assert_eq!(file_system.read(0, &name("BIG.DAT")).unwrap(), padded);
+    assert_eq!(file_system.free(), (241 - 2) * 1024);
+
+    file_system.remove(0, &name("BIG.DAT")).unwrap();
+    assert!(file_system.remove(0, &name("BIG.DAT")).is_err());
+    assert_eq!(file_system.files().len(), 2);
+    assert_eq!(file_system.free(), (241 - 1) * 1024);
+}
+
+#[test]
+fn test_disk_layout() {
+    let mut file_system = empty(Format::IBM_3740);
+    for i in 0..5 {
+        file_system
+            .write(0, &name(&format!("FILE{}.TXT", i)), &[i; 10])
+            .unwrap();
+    }
+    let disk = file_system.disk();
+
+    // The directory starts on track 2, and its second record is in the
+    // second logical sector, which is physical sector 6
+    assert_eq!(&disk.read_sector(2, 0).unwrap()[..12], b"\0FILE0   TXT");
+    assert_eq!(&disk.read_sector(2, 6).unwrap()[..12], b"\0FILE4   TXT");
+    // Block numbers start after the directory's two blocks
+    assert_eq!(disk.read_sector(2, 0).unwrap()[16..18], [2, 0]);
+    // Block 2 starts at record 16 of the data area, logical sector 16
+    assert_eq!(
+        disk.read_sector(2, 19).unwrap()[..11],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x1A]
+    );
+}
+
+#[test]
+fn test_hard_disk() {
+    // 16 bit block numbers, 8 to an entry
+    let mut file_system = empty(Format::Z80PACK_HD);
+    let data = pattern(100_000);
+    file_system.write(15, &name("HUGE.BIN"), &data).unwrap();
+
+    assert_eq!(file_system.read(15, &name("HUGE.BIN")).unwrap()[..100_000], data);
+    assert_eq!(file_system.files()[0].size(), 100_096);
+    assert_eq!(file_system.free(), (2040 - 16 - 49) * 2048);
+    // The first entry's block numbers
+    assert_eq!(file_system.disk().read_sector(0, 0).unwrap()[16..20], [16, 0, 17, 0]);
+}
+
+#[test]
+fn test_full_disk() {
+    let mut file_system = empty(Format::IBM_3740);
+    assert!(file_system.write(0, &name("HUGE.BIN"), &vec![0; 242 * 1024]).is_err());
+    assert!(file_system.files().is_empty());
+    file_system.write(0, &name("HUGE.BIN"), &vec![0; 241 * 1024]).unwrap();
+    assert_eq!(file_system.free(), 0);
+
+    let mut file_system = empty(Format::IBM_3740);
+    for i in 0..64 {
+        file_system.write(0, &name(&format!("F{}", i)), &[]).unwrap();
+    }
+    assert!(file_system.write(0, &name("ONEMORE"), &[]).is_err());
+    assert!(file_system.write(0, &name("*.*"), &[]).is_err());
+
+    let mut disk = DiskImage::new(Format::IBM_3740.geometry);
+    disk.set_write_protected(true);
+    let mut file_system = FileSystem::open(disk).unwrap();
+    assert!(file_system.write(0, &name("FILE"), &[]).is_err());
+}
+
+#[test]
+fn test_altair_disk() {
+    assert_eq!(Format::for_geometry(Format::ALTAIR.geometry), Some(Format::ALTAIR));
+    assert_eq!(Format::for_geometry(Geometry::ALTAIR_MINIDISK), None);
+    let translation = Format::ALTAIR.translation();
+    assert_eq!(translation[..4], [0, 17, 2, 19]);
+    assert_eq!(translation[31], 15);
+
+    let mut file_system = empty(Format::ALTAIR);
+    // 150 2K blocks less 1 for the directory
+    assert_eq!(file_system.free(), 149 * 2048);
+    // Enough to reach track 6, where the sector header gets longer
+    let data = pattern(20_000);
+    file_system.write(0, &name("FILE.DAT"), &data).unwrap();
+    assert_eq!(file_system.read(0, &name("FILE.DAT")).unwrap()[..20_000], data);
+    let checksum = |record: &[u8]| record.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+
+    // The directory on track 2: the track, the record from byte 3, then
+    // the stop byte and checksum
+    let sector = file_system.disk().read_sector(2, 0).unwrap();
+    assert_eq!(sector[0], 0x82);
+    assert_eq!(&sector[3..15], b"\0FILE    DAT");
+    assert_eq!(sector[131..133], [0xFF, checksum(&sector[3..131])]);
+
+    // Block 8 starts track 6 and holds the file's 113th record, from byte 7
+    let sector = file_system.disk().read_sector(6, 0).unwrap();
+    assert_eq!(sector[..2], [0x86, 0]);
+    assert_eq!(sector[7..135], data[112 * 128..113 * 128]);
+    assert_eq!(sector[4], checksum(&sector[7..135]));
+    assert_eq!(sector[135], 0xFF);
+    assert_eq!(file_system.disk().read_sector(6, 17).unwrap()[..2], [0x86, 17]);
+}
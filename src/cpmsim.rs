@@ -0,0 +1,295 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::altair::SharedLine;
+use crate::disk::DiskImage;
+use crate::em8080::{Em8080, IOState, MEMORY_SIZE};
+
+pub mod cli;
+#[cfg(test)]
+mod tests;
+
+// The I/O map of z80pack's cpmsim, which a lot of ready made CP/M disk
+// images are built for. It isn't real hardware: the "floppy controller"
+// copies whole sectors to and from memory in one OUT, which makes its
+// BIOS tiny. cpmsim emulates a Z80, but the CP/M 2.2 BIOS and most of the
+// software on those disks stick to 8080 instructions.
+//
+//   0, 1    console status (0xFF when a key is waiting) and data
+//   2, 3    printer status and data
+//   4, 5    auxiliary (reader/punch) status and data
+//   10-17   disk: drive, track, sector low, command, status, DMA low and
+//           high, sector high
+//   20-22   MMU: bank count, bank select, segment size in 256 byte pages
+//   25, 26  clock: command and data
+
+pub mod port {
+    pub const CONSOLE_STATUS: u8 = 0;
+    pub const CONSOLE_DATA: u8 = 1;
+    pub const PRINTER_STATUS: u8 = 2;
+    pub const PRINTER_DATA: u8 = 3;
+    pub const AUX_STATUS: u8 = 4;
+    pub const AUX_DATA: u8 = 5;
+    pub const DRIVE: u8 = 10;
+    pub const TRACK: u8 = 11;
+    pub const SECTOR: u8 = 12;
+    pub const COMMAND: u8 = 13;
+    pub const STATUS: u8 = 14;
+    pub const DMA_LOW: u8 = 15;
+    pub const DMA_HIGH: u8 = 16;
+    pub const SECTOR_HIGH: u8 = 17;
+    pub const MMU_INIT: u8 = 20;
+    pub const MMU_SELECT: u8 = 21;
+    pub const MMU_SEGMENT: u8 = 22;
+    pub const CLOCK_COMMAND: u8 = 25;
+    pub const CLOCK_DATA: u8 = 26;
+}
+
+/// Result of the last disk command, read on the status port
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DiskStatus {
+    Ok = 0,
+    IllegalDrive = 1,
+    IllegalTrack = 2,
+    IllegalSector = 3,
+    // 4 is a seek error on the host file
+    ReadError = 5,
+    WriteError = 6,
+    IllegalCommand = 7,
+}
+
+/// Work that needs the CPU's memory, which ports only get to read. The
+/// machine does it straight after the OUT, before the next instruction.
+enum Pending {
+    /// Disk read into memory
+    Read(Vec<u8>, u16),
+    /// Swap the banked segment for bank `n`
+    SelectBank(usize),
+}
+
+struct SimIO {
+    console: SharedLine,
+    /// Key taken from the console by a status poll, not yet read
+    key: Option<u8>,
+
+    drives: Vec<Option<DiskImage>>,
+    drive: u8,
+    track: u8,
+    /// Numbered from 1 as on CP/M's 8" disks
+    sector: u16,
+    dma: u16,
+    status: DiskStatus,
+
+    /// Contents of the banked segment of every bank but the one in memory
+    banks: Vec<Vec<u8>>,
+    bank: usize,
+    /// Bytes from address 0 that are banked, the rest is common
+    segment: usize,
+
+    clock_command: u8,
+    pending: Option<Pending>,
+}
+
+impl SimIO {
+    fn disk_command(&mut self, cpu: &Em8080, command: u8) -> DiskStatus {
+        let disk = match self.drives.get_mut(self.drive as usize) {
+            Some(Some(disk)) => disk,
+            _ => return DiskStatus::IllegalDrive,
+        };
+        let geometry = disk.geometry();
+        if self.track as usize >= geometry.tracks {
+            return DiskStatus::IllegalTrack;
+        }
+        if self.sector == 0 || self.sector as usize > geometry.sectors {
+            return DiskStatus::IllegalSector;
+        }
+        let (track, sector) = (self.track as usize, self.sector as usize - 1);
+
+        match command {
+            0 => match disk.read_sector(track, sector) {
+                Some(data) => {
+                    self.pending = Some(Pending::Read(data.to_vec(), self.dma));
+                    DiskStatus::Ok
+                }
+                None => DiskStatus::ReadError,
+            },
+            1 => {
+                let data: Vec<u8> = (0..geometry.sector_size)
+                    .map(|i| cpu.memory[(self.dma as usize + i) % MEMORY_SIZE])
+                    .collect();
+                match disk.write_sector(track, sector, &data) {
+                    Ok(()) => DiskStatus::Ok,
+                    Err(_) => DiskStatus::WriteError,
+                }
+            }
+            _ => DiskStatus::IllegalCommand,
+        }
+    }
+
+    /// The clock's registers, in BCD apart from the day count
+    fn clock(&self) -> u8 {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        // CP/M 3 counts days from the start of 1978, which was day 2922
+        let days = (seconds / 86400).saturating_sub(2922) + 1;
+        let bcd = |n: u64| (((n / 10) << 4) | (n % 10)) as u8;
+
+        match self.clock_command {
+            0 => bcd(seconds % 60),
+            1 => bcd(seconds / 60 % 60),
+            2 => bcd(seconds / 3600 % 24),
+            3 => days as u8,
+            4 => (days >> 8) as u8,
+            _ => 0,
+        }
+    }
+}
+
+impl IOState for SimIO {
+    fn input(&mut self, _cpu: &Em8080, port: u8) -> u8 {
+        match port {
+            port::CONSOLE_STATUS => {
+                if self.key.is_none() {
+                    self.key = self.console.borrow_mut().receive();
+                }
+                if self.key.is_some() {
+                    0xFF
+                } else {
+                    0
+                }
+            }
+            port::CONSOLE_DATA => self.key.take().or_else(|| self.console.borrow_mut().receive()).unwrap_or(0),
+            // Always ready to print, and never anything to read
+            port::PRINTER_STATUS | port::AUX_STATUS => 0xFF,
+            port::AUX_DATA => 0x1A,
+            port::DRIVE => self.drive,
+            port::TRACK => self.track,
+            port::SECTOR => self.sector as u8,
+            port::SECTOR_HIGH => (self.sector >> 8) as u8,
+            port::STATUS => self.status as u8,
+            port::DMA_LOW => self.dma as u8,
+            port::DMA_HIGH => (self.dma >> 8) as u8,
+            port::MMU_INIT => self.banks.len() as u8,
+            port::MMU_SELECT => self.bank as u8,
+            port::CLOCK_DATA => self.clock(),
+            _ => 0xFF,
+        }
+    }
+
+    fn output(&mut self, cpu: &Em8080, port: u8, value: u8) {
+        match port {
+            port::CONSOLE_DATA => self.console.borrow_mut().send(value),
+            port::DRIVE => self.drive = value,
+            port::TRACK => self.track = value,
+            port::SECTOR => self.sector = self.sector & 0xFF00 | value as u16,
+            port::SECTOR_HIGH => self.sector = self.sector & 0x00FF | (value as u16) << 8,
+            port::COMMAND => self.status = self.disk_command(cpu, value),
+            port::DMA_LOW => self.dma = self.dma & 0xFF00 | value as u16,
+            port::DMA_HIGH => self.dma = self.dma & 0x00FF | (value as u16) << 8,
+            // The MMU can only be set up once, the way cpmsim does it
+            port::MMU_INIT if self.banks.len() <= 1 => self.banks = vec![Vec::new(); (value as usize).max(1)],
+            port::MMU_SELECT if (value as usize) < self.banks.len() => {
+                self.pending = Some(Pending::SelectBank(value as usize))
+            }
+            port::MMU_SEGMENT if self.banks.len() <= 1 => self.segment = (value as usize) << 8,
+            port::CLOCK_COMMAND => self.clock_command = value,
+            // No printer or punch attached
+            port::PRINTER_DATA | port::AUX_DATA => {}
+            _ => {}
+        }
+    }
+}
+
+pub struct CpmSim {
+    cpu: Em8080,
+    io_state: SimIO,
+    cycles: u64,
+}
+
+impl CpmSim {
+    /// cpmsim has no clock rate of its own, this is a fast 8080 system
+    pub const CLOCK_HZ: u64 = 4_000_000;
+    /// Drives A to P
+    pub const DRIVES: usize = 16;
+
+    pub fn new(console: SharedLine) -> Self {
+        Self {
+            cpu: Em8080::new(),
+            io_state: SimIO {
+                console,
+                key: None,
+                drives: (0..Self::DRIVES).map(|_| None).collect(),
+                drive: 0,
+                track: 0,
+                sector: 1,
+                dma: 0x0080,
+                status: DiskStatus::Ok,
+                banks: vec![Vec::new()],
+                bank: 0,
+                segment: 0xC000,
+                clock_command: 0,
+                pending: None,
+            },
+            cycles: 0,
+        }
+    }
+
+    /// Puts a disk in `drive`, 0 for A:
+    pub fn insert(&mut self, drive: usize, disk: DiskImage) {
+        self.io_state.drives[drive] = Some(disk);
+    }
+
+    /// Does what cpmsim does on power on: loads the first sector of drive
+    /// A at address 0 and runs it
+    pub fn boot(&mut self) -> Result<(), String> {
+        let disk = self.io_state.drives[0].as_ref().ok_or("There is no disk in drive A")?;
+        let sector = disk.read_sector(0, 0).ok_or("Drive A has no boot sector")?;
+
+        self.cpu.reset();
+        self.cpu.memory[..sector.len()].copy_from_slice(sector);
+        Ok(())
+    }
+
+    /// Runs for at least `cycles` CPU cycles, stopping early at a HLT
+    pub fn run(&mut self, cycles: u64) {
+        let end = self.cycles + cycles;
+        while self.cycles < end && !self.cpu.is_halted() {
+            self.cycles += self.cpu.emulate(&mut self.io_state);
+            if let Some(pending) = self.io_state.pending.take() {
+                self.complete(pending);
+            }
+        }
+    }
+
+    fn complete(&mut self, pending: Pending) {
+        match pending {
+            Pending::Read(data, dma) => {
+                for (i, byte) in data.into_iter().enumerate() {
+                    self.cpu.memory[(dma as usize + i) % MEMORY_SIZE] = byte;
+                }
+            }
+            Pending::SelectBank(bank) => {
+                let io = &mut self.io_state;
+                let segment = &mut self.cpu.memory[..io.segment];
+                io.banks[io.bank] = segment.to_vec();
+                // Banks not used yet start out empty
+                let incoming = std::mem::take(&mut io.banks[bank]);
+                if incoming.len() == segment.len() {
+                    segment.copy_from_slice(&incoming);
+                } else {
+                    segment.fill(0);
+                }
+                io.bank = bank;
+            }
+        }
+    }
+
+    /// Whether the program has stopped the machine with a HLT, which is
+    /// how cpmsim programs exit
+    pub fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+}
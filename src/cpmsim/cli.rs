@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use super::CpmSim;
+use crate::config::{self, Settings};
+use crate::disk::DiskImage;
+use crate::session::{self, Machine};
+use crate::terminal::{self, Console};
+
+/// Command line options of `emulator-8080 cpmsim`
+#[derive(Default)]
+struct Options {
+    /// Disk images by drive number, and whether they are write protected
+    drives: Vec<(usize, PathBuf, bool)>,
+    terminal: terminal::Options,
+    uncapped: bool,
+}
+
+impl Settings for Options {
+    const FLAGS: &'static [&'static str] = &["window", "uncapped"];
+
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        if self.terminal.set(name, value)? {
+            return Ok(());
+        }
+        let flag = || value.map_or(Ok(true), config::parse_bool);
+        let value = || value.ok_or(format!("{} needs a value", name));
+
+        match name {
+            "drive" | "drive-ro" => {
+                let value = value()?;
+                let (letter, path) = value.split_once('=').ok_or(format!("Expected DRIVE=FILE, got {}", value))?;
+                let drive =
+                    config::parse_drive(letter, CpmSim::DRIVES).ok_or(format!("Drives are A to P, got {}", letter))?;
+                self.drives.retain(|&(other, _, _)| other != drive);
+                self.drives.push((drive, path.into(), name == "drive-ro"));
+            }
+            "uncapped" => self.uncapped = flag()?,
+            _ => return Err(format!("Unknown option: {}", name)),
+        }
+
+        Ok(())
+    }
+
+    fn usage() {
+        println!("Usage: emulator-8080 cpmsim [--config FILE] --drive A=FILE [--drive B=FILE]... [--drive-ro C=FILE]...");
+        println!("                            [--terminal adm3a|vt52] [--window] [--uncapped]");
+    }
+}
+
+impl Machine for CpmSim {
+    const CLOCK_HZ: u64 = CpmSim::CLOCK_HZ;
+
+    fn run(&mut self, cycles: u64) {
+        CpmSim::run(self, cycles)
+    }
+
+    fn cycles(&self) -> u64 {
+        CpmSim::cycles(self)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.is_halted()
+    }
+}
+
+pub fn main(args: impl Iterator<Item = String>) {
+    let options: Options = config::parse_args(args).unwrap_or_else(|e| {
+        println!("{}", e);
+        Options::usage();
+        std::process::exit(1);
+    });
+
+    if let Err(e) = run(&options) {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let mut console = Console::open(&options.terminal)?;
+    let mut machine = CpmSim::new(console.line());
+
+    for (drive, path, write_protected) in &options.drives {
+        let disk = DiskImage::open(path, *write_protected)?;
+        println!(
+            "{}: {}{}",
+            (b'A' + *drive as u8) as char,
+            path.display(),
+            if disk.is_write_protected() { " (write protected)" } else { "" }
+        );
+        machine.insert(*drive, disk);
+    }
+    machine.boot()?;
+    println!("Ctrl-] to quit.");
+    session::run(&mut machine, &mut console, false, options.uncapped);
+
+    // Saves the disks and restores the terminal before printing
+    drop(machine);
+    drop(console);
+    println!();
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::cpmsim::{port, CpmSim, DiskStatus};
+use crate::disk::{DiskImage, Geometry};
+use crate::testing::{loopback, Loopback};
+
+fn machine(input: &[u8]) -> (CpmSim, Rc<RefCell<Loopback>>) {
+    let terminal = loopback(input);
+    (CpmSim::new(terminal.clone()), terminal)
+}
+
+/// MVI A,value; OUT port
+fn out(port: u8, value: u8) -> Vec<u8> {
+    vec![0x3E, value, 0xD3, port]
+}
+
+/// IN port; STA address
+fn store_in(port: u8, address: u16) -> Vec<u8> {
+    let [low, high] = address.to_le_bytes();
+    vec![0xDB, port, 0x32, low, high]
+}
+
+/// Runs `program` until it halts. It goes in common memory, above the
+/// banked segment.
+fn run(machine: &mut CpmSim, program: &[Vec<u8>]) {
+    const START: usize = 0xD000;
+    let program: Vec<u8> = program.concat();
+    machine.cpu.memory[START..START + program.len()].copy_from_slice(&program);
+    machine.cpu.memory[START + program.len()] = 0x76;
+    machine.cpu.set_sp(0xF000);
+    machine.cpu.set_pc(START as u16);
+    machine.run(1_000_000);
+    assert!(machine.is_halted());
+}
+
+#[test]
+fn test_boot() {
+    // Reads track 2 sector 26 to 0x1000 and prints it
+    let boot = [
+        0x3E, 0x02, 0xD3, 0x0B, // MVI A,2; OUT 11
+        0x3E, 0x1A, 0xD3, 0x0C, // MVI A,26; OUT 12
+        0x3E, 0x00, 0xD3, 0x0F, // MVI A,0; OUT 15
+        0x3E, 0x10, 0xD3, 0x10, // MVI A,10h; OUT 16
+        0x3E, 0x00, 0xD3, 0x0D, // MVI A,0; OUT 13
+        0xDB, 0x0E, 0x32, 0x00, 0x0F, // IN 14; STA 0F00h
+        0x21, 0x00, 0x10, // LXI H,1000h
+        0x7E, 0xB7, 0xCA, 0x27, 0x00, // loop: MOV A,M; ORA A; JZ done
+        0xD3, 0x01, 0x23, 0xC3, 0x1C, 0x00, // OUT 1; INX H; JMP loop
+        0x76, // done: HLT
+    ];
+    let mut disk = DiskImage::new(Geometry::IBM_3740);
+    disk.write_sector(0, 0, &boot).unwrap();
+    disk.write_sector(2, 25, b"\r\nA>\0").unwrap();
+
+    let (mut machine, terminal) = machine(&[]);
+    assert!(machine.boot().is_err());
+    machine.insert(0, disk);
+    machine.boot().unwrap();
+    machine.run(100_000);
+
+    assert!(machine.is_halted());
+    assert_eq!(machine.cpu.memory[0x0F00], DiskStatus::Ok as u8);
+    assert_eq!(terminal.borrow().output, b"\r\nA>");
+}
+
+#[test]
+fn test_disk_write_and_errors() {
+    let (mut machine, _) = machine(&[]);
+    machine.insert(1, DiskImage::new(Geometry::IBM_3740));
+    let mut protected = DiskImage::new(Geometry::IBM_3740);
+    protected.set_write_protected(true);
+    machine.insert(2, protected);
+    machine.cpu.memory[0x2000..0x2080].fill(0x42);
+
+    run(
+        &mut machine,
+        &[
+            out(port::DMA_LOW, 0x00),
+            out(port::DMA_HIGH, 0x20),
+            // B: track 76 sector 26
+            out(port::DRIVE, 1),
+            out(port::TRACK, 76),
+            out(port::SECTOR, 26),
+            out(port::COMMAND, 1),
+            store_in(port::STATUS, 0x3000),
+            out(port::TRACK, 77),
+            out(port::COMMAND, 1),
+            store_in(port::STATUS, 0x3001),
+            out(port::TRACK, 0),
+            out(port::SECTOR, 0),
+            out(port::COMMAND, 0),
+            store_in(port::STATUS, 0x3002),
+            out(port::SECTOR, 1),
+            out(port::COMMAND, 2),
+            store_in(port::STATUS, 0x3003),
+            // Empty drive, and C: which is write protected
+            out(port::DRIVE, 3),
+            out(port::COMMAND, 0),
+            store_in(port::STATUS, 0x3004),
+            out(port::DRIVE, 2),
+            out(port::COMMAND, 1),
+            store_in(port::STATUS, 0x3005),
+            out(port::COMMAND, 0),
+            store_in(port::STATUS, 0x3006),
+        ],
+    );
+
+    let statuses = [
+        DiskStatus::Ok,
+        DiskStatus::IllegalTrack,
+        DiskStatus::IllegalSector,
+        DiskStatus::IllegalCommand,
+        DiskStatus::IllegalDrive,
+        DiskStatus::WriteError,
+        DiskStatus::Ok,
+    ];
+    assert_eq!(machine.cpu.memory[0x3000..0x3007], statuses.map(|status| status as u8));
+
+    let disk = machine.io_state.drives[1].as_ref().unwrap();
+    assert_eq!(disk.read_sector(76, 25).unwrap(), [0x42; 128]);
+    // The read from C: landed at the DMA address
+    assert_eq!(machine.cpu.memory[0x2000..0x2080], [0xE5; 128]);
+}
+
+#[test]
+fn test_console() {
+    let (mut machine, terminal) = machine(b"X");
+    run(
+        &mut machine,
+        &[
+            store_in(port::CONSOLE_STATUS, 0x3000),
+            store_in(port::CONSOLE_STATUS, 0x3001),
+            store_in(port::CONSOLE_DATA, 0x3002),
+            store_in(port::CONSOLE_STATUS, 0x3003),
+            out(port::CONSOLE_DATA, b'Y'),
+        ],
+    );
+
+    assert_eq!(machine.cpu.memory[0x3000..0x3004], [0xFF, 0xFF, b'X', 0x00]);
+    assert_eq!(terminal.borrow().output, b"Y");
+}
+
+#[test]
+fn test_mmu_banks() {
+    // LDA address; STA address
+    let copy = |from: u16, to: u16| {
+        let ([from_low, from_high], [to_low, to_high]) = (from.to_le_bytes(), to.to_le_bytes());
+        vec![0x3A, from_low, from_high, 0x32, to_low, to_high]
+    };
+    // MVI A,value; STA address
+    let store = |value: u8, address: u16| {
+        let [low, high] = address.to_le_bytes();
+        vec![0x3E, value, 0x32, low, high]
+    };
+
+    let (mut machine, _) = machine(&[]);
+    run(
+        &mut machine,
+        &[
+            out(port::MMU_INIT, 3),
+            store_in(port::MMU_INIT, 0xC010),
+            store(0x11, 0x0080),
+            store(0x33, 0xC100),
+            out(port::MMU_SELECT, 1),
+            copy(0x0080, 0xC000),
+            store(0x22, 0x0080),
+            out(port::MMU_SELECT, 0),
+            copy(0x0080, 0xC001),
+            out(port::MMU_SELECT, 1),
+            copy(0x0080, 0xC002),
+            store_in(port::MMU_SELECT, 0xC011),
+            // Common memory is the same in every bank
+            copy(0xC100, 0xC003),
+        ],
+    );
+
+    assert_eq!(machine.cpu.memory[0xC000..0xC004], [0x00, 0x11, 0x22, 0x33]);
+    assert_eq!(machine.cpu.memory[0xC010..0xC012], [3, 1]);
+}
@@ -0,0 +1,179 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests;
+
+// Floppy disk images as flat files: every sector of track 0 in order, then
+// track 1 and so on, with no headers. The format is told apart by size.
+
+/// Layout of a disk, sectors counted from 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Geometry {
+    pub tracks: usize,
+    pub sectors: usize,
+    pub sector_size: usize,
+}
+
+impl Geometry {
+    /// 8" single sided single density, the CP/M distribution format
+    pub const IBM_3740: Geometry = Geometry {
+        tracks: 77,
+        sectors: 26,
+        sector_size: 128,
+    };
+    /// 8" hard sectored Altair disk as SIMH stores it: each 137 byte sector
+    /// holds the track and sector numbers and a checksum around 128 bytes
+    /// of data, all written by the software rather than the controller
+    pub const ALTAIR_8INCH: Geometry = Geometry {
+        tracks: 77,
+        sectors: 32,
+        sector_size: 137,
+    };
+    /// 5.25" Altair minidisk, in the same SIMH format
+    pub const ALTAIR_MINIDISK: Geometry = Geometry {
+        tracks: 35,
+        sectors: 16,
+        sector_size: 137,
+    };
+    /// z80pack's 4 MB hard disk, drives I and J of cpmsim
+    pub const Z80PACK_HD: Geometry = Geometry {
+        tracks: 255,
+        sectors: 128,
+        sector_size: 128,
+    };
+
+    const KNOWN: [Geometry; 4] = [Self::IBM_3740, Self::ALTAIR_8INCH, Self::ALTAIR_MINIDISK, Self::Z80PACK_HD];
+
+    pub fn image_size(&self) -> usize {
+        self.tracks * self.sectors * self.sector_size
+    }
+
+    /// Geometry of an image file of `size` bytes
+    pub fn from_image_size(size: usize) -> Option<Geometry> {
+        Self::KNOWN.into_iter().find(|geometry| geometry.image_size() == size)
+    }
+}
+
+/// A disk in a drive. Writes go to memory and are saved back to the image
+/// file by `flush`, which also happens when the disk is dropped.
+pub struct DiskImage {
+    geometry: Geometry,
+    data: Vec<u8>,
+    /// File to save to, `None` for disks that only live in memory
+    path: Option<PathBuf>,
+    write_protected: bool,
+    dirty: bool,
+}
+
+impl DiskImage {
+    /// Freshly formatted disk, every byte 0xE5 as CP/M expects of an empty
+    /// directory
+    pub fn new(geometry: Geometry) -> Self {
+        Self {
+            geometry,
+            data: vec![0xE5; geometry.image_size()],
+            path: None,
+            write_protected: false,
+            dirty: false,
+        }
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, String> {
+        let geometry = Geometry::from_image_size(data.len()).ok_or(format!(
+            "{} bytes is not the size of a known disk image format",
+            data.len()
+        ))?;
+
+        Ok(Self {
+            geometry,
+            data,
+            path: None,
+            write_protected: false,
+            dirty: false,
+        })
+    }
+
+    /// Opens an image file. Files we can't write to are write protected.
+    pub fn open(path: &Path, write_protected: bool) -> Result<Self, String> {
+        let error = |e: io::Error| format!("Could not read {}: {}", path.display(), e);
+
+        let mut data = Vec::new();
+        File::open(path).and_then(|mut file| file.read_to_end(&mut data)).map_err(error)?;
+        let mut disk = Self::from_bytes(data).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let writable = OpenOptions::new().write(true).open(path).is_ok();
+        disk.write_protected = write_protected || !writable;
+        disk.path = Some(path.into());
+        Ok(disk)
+    }
+
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    pub fn is_write_protected(&self) -> bool {
+        self.write_protected
+    }
+
+    #[cfg(test)]
+    pub fn set_write_protected(&mut self, write_protected: bool) {
+        self.write_protected = write_protected;
+    }
+
+    /// The whole image
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn offset(&self, track: usize, sector: usize) -> Option<usize> {
+        let geometry = self.geometry;
+        (track < geometry.tracks && sector < geometry.sectors)
+            .then_some((track * geometry.sectors + sector) * geometry.sector_size)
+    }
+
+    pub fn read_sector(&self, track: usize, sector: usize) -> Option<&[u8]> {
+        let offset = self.offset(track, sector)?;
+        Some(&self.data[offset..offset + self.geometry.sector_size])
+    }
+
+    /// Writes a whole sector, padding short data with zeros
+    pub fn write_sector(&mut self, track: usize, sector: usize, data: &[u8]) -> Result<(), DiskError> {
+        if self.write_protected {
+            return Err(DiskError::WriteProtected);
+        }
+        let offset = self.offset(track, sector).ok_or(DiskError::NoSuchSector)?;
+
+        let size = self.geometry.sector_size;
+        let sector = &mut self.data[offset..offset + size];
+        let length = data.len().min(size);
+        sector[..length].copy_from_slice(&data[..length]);
+        sector[length..].fill(0);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Saves changes to the image file
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let (true, Some(path)) = (self.dirty, self.path.as_ref()) {
+            File::create(path)?.write_all(&self.data)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DiskImage {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            println!("Could not save disk {}: {}", self.path.as_ref().unwrap().display(), e);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiskError {
+    WriteProtected,
+    NoSuchSector,
+}
@@ -0,0 +1,42 @@
+use crate::disk::{DiskError, DiskImage, Geometry};
+
+#[test]
+fn test_geometry_from_size() {
+    assert_eq!(Geometry::IBM_3740.image_size(), 256_256);
+    assert_eq!(Geometry::ALTAIR_8INCH.image_size(), 337_568);
+    assert_eq!(Geometry::ALTAIR_MINIDISK.image_size(), 76_720);
+
+    assert_eq!(Geometry::from_image_size(256_256), Some(Geometry::IBM_3740));
+    assert_eq!(Geometry::from_image_size(337_568), Some(Geometry::ALTAIR_8INCH));
+    assert_eq!(Geometry::from_image_size(4_177_920), Some(Geometry::Z80PACK_HD));
+    assert_eq!(Geometry::from_image_size(1000), None);
+    assert!(DiskImage::from_bytes(vec![0; 1000]).is_err());
+}
+
+#[test]
+fn test_sectors() {
+    let mut image = vec![0; Geometry::IBM_3740.image_size()];
+    // Track 2, sector 5
+    image[(2 * 26 + 5) * 128] = 0x42;
+    let mut disk = DiskImage::from_bytes(image).unwrap();
+
+    assert_eq!(disk.read_sector(2, 5).unwrap()[0], 0x42);
+    assert_eq!(disk.read_sector(2, 5).unwrap().len(), 128);
+    assert_eq!(disk.read_sector(77, 0), None);
+    assert_eq!(disk.read_sector(0, 26), None);
+
+    disk.write_sector(76, 25, &[1, 2, 3]).unwrap();
+    assert_eq!(disk.read_sector(76, 25).unwrap()[..4], [1, 2, 3, 0]);
+    assert_eq!(disk.data()[disk.data().len() - 128..][..3], [1, 2, 3]);
+    assert_eq!(disk.write_sector(0, 26, &[]), Err(DiskError::NoSuchSector));
+}
+
+#[test]
+fn test_write_protect() {
+    let mut disk = DiskImage::new(Geometry::ALTAIR_8INCH);
+    assert_eq!(disk.read_sector(0, 0).unwrap(), [0xE5; 137]);
+
+    disk.set_write_protected(true);
+    assert_eq!(disk.write_sector(0, 0, &[0; 137]), Err(DiskError::WriteProtected));
+    assert_eq!(disk.read_sector(0, 0).unwrap(), [0xE5; 137]);
+}
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 use std::{self, fmt};
+use std::cell::{Cell, RefCell};
 
 #[cfg(test)]
 mod tests;
@@ -8,14 +9,17 @@ mod test_int;
 
 mod flags;
 use flags::Flags;
+mod bus;
+pub use bus::BusCycle;
 
 // This file borrows from https://github.com/alexandrejanin/rust-8080/tree/master/srcv
 
-const MEMORY_SIZE: usize = 0xFFFF;
+/// The full 64K address space
+pub const MEMORY_SIZE: usize = 0x10000;
 
 /// Interface between the emulator's IO functions and the machine state
 pub trait IOState {
-    fn input(&self, cpu : &Em8080, port: u8) -> u8;
+    fn input(&mut self, cpu : &Em8080, port: u8) -> u8;
     fn output(&mut self, cpu : &Em8080, port: u8, value: u8);
 }
 
@@ -35,6 +39,8 @@ pub struct Em8080 {
     pc: u16,
 
     pub memory: [u8; MEMORY_SIZE],
+    /// Addresses from here up have no memory fitted
+    memory_limit: usize,
 
     // Flags
     flags: Flags,
@@ -43,6 +49,12 @@ pub struct Em8080 {
     interrupts_enabled : bool,
 
     pub trace : bool,
+
+    /// Record every machine cycle, e.g. for front panel lights
+    bus_monitor: bool,
+    bus_cycles: RefCell<Vec<BusCycle>>,
+    /// Set while pushing and popping, for the STACK status bit
+    stack_access: Cell<bool>,
 }
 
 impl std::default::Default for Em8080 {
@@ -59,6 +71,7 @@ impl std::default::Default for Em8080 {
             pc: 0,
 
             memory: [0; MEMORY_SIZE],
+            memory_limit: MEMORY_SIZE,
 
             flags: Flags {
                 zero: false,
@@ -71,6 +84,10 @@ impl std::default::Default for Em8080 {
             halted : false,
             interrupts_enabled : true,
             trace : false,
+
+            bus_monitor: false,
+            bus_cycles: RefCell::new(Vec::new()),
+            stack_access: Cell::new(false),
         }
     }
 }
@@ -98,12 +115,22 @@ impl Em8080 {
         Self::default()
     }
 
+    /// What the RESET pin does: restarts at address 0 with interrupts
+    /// disabled. Unlike powering on, registers and memory are left alone.
+    pub fn reset(&mut self) {
+        self.pc = 0;
+        self.interrupts_enabled = false;
+        self.halted = false;
+    }
+
     pub fn flags(&self) -> &Flags {
         &self.flags
     }    
 
     pub fn interrupt(&mut self, interrupt_num: u16) {
         if self.interrupts_enabled {
+            // The interrupting device puts an RST on the bus
+            self.log_cycle(BusCycle::INTA | BusCycle::M1 | BusCycle::WO, self.pc, 0xC7 | (interrupt_num as u8) << 3);
             self.push(self.pc);
             self.pc = 8 * interrupt_num;
             self.interrupts_enabled = false;
@@ -111,12 +138,12 @@ impl Em8080 {
     }
 
     pub fn print_op(&mut self) {
-        let op_code = self.read_byte(self.pc);
+        let op_code = self.peek_byte(self.pc);
         println!("PC:{:04X}, SP:{:04X}. op: {:2X} ({})", self.pc, self.sp, op_code, self.op_name(self.pc));
     }
 
     pub fn emulate(&mut self, io_state: &mut dyn IOState ) -> u64 {
-        let op_code = self.read_byte(self.pc);
+        let op_code = self.bus_read(self.pc, BusCycle::MEMR | BusCycle::M1 | BusCycle::WO);
 
         //if cfg!(feature="logging") && self.pc != 0xada && self.pc != 0xadd && self.pc != 0xade {
         //    println!("{}", self);
@@ -404,8 +431,8 @@ impl Em8080 {
 
             // HLT
             0x76 => {
-                println!("HLT instruction received");
                 self.halted = true;
+                self.log_cycle(BusCycle::MEMR | BusCycle::HLTA | BusCycle::WO, self.pc.wrapping_add(1), 0);
                 (1, 7)
             }
 
@@ -413,13 +440,13 @@ impl Em8080 {
             0x03 => { self.set_bc(self.get_bc().wrapping_add(1)); (1, 5) },
             0x13 => { self.set_de(self.get_de().wrapping_add(1)); (1, 5) },
             0x23 => { self.set_hl(self.get_hl().wrapping_add(1)); (1, 5) },
-            0x33 => { self.sp += 1; (1, 5) },
+            0x33 => { self.sp = self.sp.wrapping_add(1); (1, 5) },
 
             // DCX
             0x0B => { self.set_bc(self.get_bc().wrapping_sub(1)); (1, 5) },
             0x1B => { self.set_de(self.get_de().wrapping_sub(1)); (1, 5) },
             0x2B => { self.set_hl(self.get_hl().wrapping_sub(1)); (1, 5) },
-            0x3B => { self.sp -= 1; (1, 5) },
+            0x3B => { self.sp = self.sp.wrapping_sub(1); (1, 5) },
 
             // ADD
             0x80 => { self.add(self.b); (1, 4) },
@@ -842,13 +869,17 @@ impl Em8080 {
         
             // OUT D8
             0xD3 => {
-                io_state.output(&self, self.read_next_byte(), self.a);
+                let port = self.read_next_byte();
+                self.log_cycle(BusCycle::OUT, u16::from_le_bytes([port, port]), self.a);
+                io_state.output(&self, port, self.a);
                 (2, 10)
             }            
 
             // IN D8
             0xDB => {
-                self.a = io_state.input(&self, self.read_next_byte());
+                let port = self.read_next_byte();
+                self.a = io_state.input(&self, port);
+                self.log_cycle(BusCycle::INP | BusCycle::WO, u16::from_le_bytes([port, port]), self.a);
                 (2, 10)
             }  
             
@@ -873,61 +904,96 @@ impl Em8080 {
             } */
         };
 
-        self.pc += op_length;
+        self.pc = self.pc.wrapping_add(op_length);
         cycles        
     }
 
     fn read_byte(&self, address: u16) -> u8 {
+        let stack = if self.stack_access.get() { BusCycle::STACK } else { 0 };
+        self.bus_read(address, BusCycle::MEMR | BusCycle::WO | stack)
+    }
+
+    /// Memory read in a machine cycle with the given status
+    fn bus_read(&self, address: u16, status: u8) -> u8 {
+        let value = self.peek_byte(address);
+        self.log_cycle(status, address, value);
+        value
+    }
+
+    /// Reads memory without it counting as a bus cycle, for disassembly
+    fn peek_byte(&self, address: u16) -> u8 {
+        if address as usize >= self.memory_limit {
+            // Nothing drives the data bus
+            return 0xFF;
+        }
         self.memory[address as usize]
     }
+
+    fn peek_word(&self, address: u16) -> u16 {
+        (self.peek_byte(address.wrapping_add(1)) as u16) << 8 | (self.peek_byte(address) as u16)
+    }
+
+    fn log_cycle(&self, status: u8, address: u16, data: u8) {
+        if self.bus_monitor {
+            self.bus_cycles.borrow_mut().push(BusCycle { status, address, data });
+        }
+    }
     
     fn read_word(&self, address: u16) -> u16 {
-        (self.read_byte(address + 1) as u16) << 8 | (self.read_byte(address) as u16)
+        // Low byte first, as the 8080 does
+        let low = self.read_byte(address) as u16;
+        (self.read_byte(address.wrapping_add(1)) as u16) << 8 | low
     }
 
     // Reads next word from memory
     fn read_next_word(&self) -> u16 {
-        self.read_word(self.pc + 1)
+        self.read_word(self.pc.wrapping_add(1))
     }
 
     // Reads next word from memory
     fn read_next_byte(&self) -> u8 {
-        self.read_byte(self.pc + 1)
+        self.read_byte(self.pc.wrapping_add(1))
     }
 
     fn write_byte(&mut self, address: u16, val: u8) {
+        let stack = if self.stack_access.get() { BusCycle::STACK } else { 0 };
+        self.log_cycle(stack, address, val);
+
+        if address as usize >= self.memory_limit {
+            return;
+        }
         self.memory[address as usize] = val;
     }
 
     fn write_word(&mut self, address: u16, word: u16) {
         self.write_byte(address, (word & 0xFF) as u8);
-        self.write_byte(address + 1, (word >> 8) as u8);
+        self.write_byte(address.wrapping_add(1), (word >> 8) as u8);
     }    
 
-    fn set_bc(&mut self, value: u16) {
+    pub fn set_bc(&mut self, value: u16) {
         self.b = (value >> 8) as u8;
         self.c = (value & 0xFF) as u8;
     }
 
-    fn set_de(&mut self, value: u16) {
+    pub fn set_de(&mut self, value: u16) {
         self.d = (value >> 8) as u8;
         self.e = (value & 0xFF) as u8;
     }
 
-    fn set_hl(&mut self, value: u16) {
+    pub fn set_hl(&mut self, value: u16) {
         self.h = (value >> 8) as u8;
         self.l = (value & 0xFF) as u8;
     }
 
-    fn get_bc(&self) -> u16 {
+    pub fn get_bc(&self) -> u16 {
         (self.b as u16) << 8 | (self.c as u16)
     }
 
-    fn get_de(&self) -> u16 {
+    pub fn get_de(&self) -> u16 {
         (self.d as u16) << 8 | (self.e as u16)
     }
 
-    fn get_hl(&self) -> u16 {
+    pub fn get_hl(&self) -> u16 {
         (self.h as u16) << 8 | (self.l as u16)
     }
 
@@ -1048,7 +1114,7 @@ impl Em8080 {
     }
 
     fn call(&mut self, adr: u16) {
-        self.push(self.pc + 3);
+        self.push(self.pc.wrapping_add(3));
         self.pc = adr;
     }
 
@@ -1057,13 +1123,21 @@ impl Em8080 {
     }    
 
     fn pop(&mut self) -> u16 {
-        self.sp += 2;
-        self.read_word(self.sp - 2)
+        let address = self.sp;
+        self.sp = self.sp.wrapping_add(2);
+        self.stack_access.set(true);
+        let value = self.read_word(address);
+        self.stack_access.set(false);
+        value
     }
 
     fn push(&mut self, value: u16) {
-        self.sp -= 2;
-        self.write_word(self.sp, value);
+        self.sp = self.sp.wrapping_sub(2);
+        // High byte first, as the 8080 does
+        self.stack_access.set(true);
+        self.write_byte(self.sp.wrapping_add(1), (value >> 8) as u8);
+        self.write_byte(self.sp, (value & 0xFF) as u8);
+        self.stack_access.set(false);
     }    
 
     pub fn get_af(&self) -> u16 {
@@ -1090,65 +1164,112 @@ impl Em8080 {
         self.sp = sp;
     }
 
+    pub fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    pub fn a(&self) -> u8 {
+        self.a
+    }
+
+    pub fn set_a(&mut self, value: u8) {
+        self.a = value;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Starts or stops recording machine cycles, see `take_bus_cycles`
+    pub fn set_bus_monitor(&mut self, on: bool) {
+        self.bus_monitor = on;
+        self.bus_cycles.get_mut().clear();
+    }
+
+    /// Machine cycles since the last call, when the bus monitor is on
+    pub fn take_bus_cycles(&mut self) -> Vec<BusCycle> {
+        std::mem::take(self.bus_cycles.get_mut())
+    }
+
+    pub fn interrupts_enabled(&self) -> bool {
+        self.interrupts_enabled
+    }
+
+    /// Whether a HLT has been executed since the last reset
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Only fits `size` bytes of memory from address 0. Reads above it
+    /// return 0xFF and writes are lost, which is how programs such as
+    /// Altair BASIC size memory.
+    pub fn set_memory_limit(&mut self, size: usize) {
+        self.memory_limit = size.min(MEMORY_SIZE);
+    }
+
     /// Returns the name of the instruction at the specified address in memory
     fn op_name(&self, address: u16) -> String {
-        match self.read_byte(address) {
+        match self.peek_byte(address) {
             0x00 | 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => "NOP".into(),
-            0x01 => format!("LXI B, ${:04x}", self.read_word(address + 1)),
+            0x01 => format!("LXI B, ${:04x}", self.peek_word(address.wrapping_add(1))),
             0x02 => "STAX B".into(),
             0x03 => "INX B".into(),
             0x04 => "INR B".into(),
             0x05 => "DCR B".into(),
-            0x06 => format!("MVI B, ${:02x}", self.read_byte(address + 1)),
+            0x06 => format!("MVI B, ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0x07 => "RLC".into(),
             0x09 => "DAD B".into(),
             0x0a => "LDAX B".into(),
             0x0b => "DCX B".into(),
             0x0c => "INR C".into(),
             0x0d => "DCR C".into(),
-            0x0e => format!("MVI C, ${:02x}", self.read_byte(address + 1)),
+            0x0e => format!("MVI C, ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0x0f => "RRC".into(),
-            0x11 => format!("LXI D, ${:04x}", self.read_word(address + 1)),
+            0x11 => format!("LXI D, ${:04x}", self.peek_word(address.wrapping_add(1))),
             0x12 => "STAX D".into(),
             0x13 => "INX D".into(),
             0x14 => "INR D".into(),
             0x15 => "DCR D".into(),
-            0x16 => format!("MVI D, ${:02x}", self.read_byte(address + 1)),
+            0x16 => format!("MVI D, ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0x17 => "RAL".into(),
             0x19 => "DAD D".into(),
             0x1a => "LDAX D".into(),
             0x1b => "DCX D".into(),
             0x1c => "INR E".into(),
             0x1d => "DCR E".into(),
-            0x1e => format!("MVI E, ${:02x}", self.read_byte(address + 1)),
+            0x1e => format!("MVI E, ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0x1f => "RAR".into(),
-            0x21 => format!("LXI H, ${:04x}", self.read_word(address + 1)),
-            0x22 => format!("SHLD ${:04x}", self.read_word(address + 1)),
+            0x21 => format!("LXI H, ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0x22 => format!("SHLD ${:04x}", self.peek_word(address.wrapping_add(1))),
             0x23 => "INX H".into(),
             0x24 => "INR H".into(),
             0x25 => "DCR H".into(),
-            0x26 => format!("MVI H, ${:02x}", self.read_byte(address + 1)),
+            0x26 => format!("MVI H, ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0x27 => "DAA".into(),
             0x29 => "DAD H".into(),
-            0x2a => format!("LHLD ${:04x}", self.read_word(address + 1)),
+            0x2a => format!("LHLD ${:04x}", self.peek_word(address.wrapping_add(1))),
             0x2b => "DCX H".into(),
             0x2c => "INR L".into(),
             0x2d => "DCR L".into(),
-            0x2e => format!("MVI L, ${:02x}", self.read_byte(address + 1)),
+            0x2e => format!("MVI L, ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0x2f => "CMA".into(),
-            0x31 => format!("LXI SP, ${:04x}", self.read_word(address + 1)),
-            0x32 => format!("STA ${:04x}", self.read_word(address + 1)),
+            0x31 => format!("LXI SP, ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0x32 => format!("STA ${:04x}", self.peek_word(address.wrapping_add(1))),
             0x33 => "INX SP".into(),
             0x34 => "INR M".into(),
             0x35 => "DCR M".into(),
-            0x36 => format!("MVI M, ${:02x}", self.read_byte(address + 1)),
+            0x36 => format!("MVI M, ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0x37 => "STC".into(),
             0x39 => "DAD SP".into(),
-            0x3a => format!("LDA ${:04x}", self.read_word(address + 1)),
+            0x3a => format!("LDA ${:04x}", self.peek_word(address.wrapping_add(1))),
             0x3b => "DCX SP".into(),
             0x3c => "INR A".into(),
             0x3d => "DCR A".into(),
-            0x3e => format!("MVI A, ${:02x}", self.read_byte(address + 1)),
+            0x3e => format!("MVI A, ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0x3f => "CMC".into(),
             0x40 => "MOV B,B".into(),
             0x41 => "MOV B,C".into(),
@@ -1280,62 +1401,62 @@ impl Em8080 {
             0xbf => "CMP A".into(),
             0xc0 => "RNZ".into(),
             0xc1 => "POP B".into(),
-            0xc2 => format!("JNZ ${:04x}", self.read_word(address + 1)),
-            0xc3 | 0xcb => format!("JMP ${:04x}", self.read_word(address + 1)),
-            0xc4 => format!("CNZ ${:04x}", self.read_word(address + 1)),
+            0xc2 => format!("JNZ ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0xc3 | 0xcb => format!("JMP ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0xc4 => format!("CNZ ${:04x}", self.peek_word(address.wrapping_add(1))),
             0xc5 => "PUSH B".into(),
-            0xc6 => format!("ADI ${:02x}", self.read_byte(address + 1)),
+            0xc6 => format!("ADI ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0xc7 => "RST 0".into(),
             0xc8 => "RZ".into(),
             0xc9 | 0xd9 => "RET".into(),
-            0xca => format!("JZ ${:04x}", self.read_word(address + 1)),
-            0xcc => format!("CZ ${:04x}", self.read_word(address + 1)),
-            0xcd | 0xdd | 0xed | 0xfd => format!("CALL ${:04x}", self.read_word(address + 1)),
-            0xce => format!("ACI ${:02x}", self.read_byte(address + 1)),
+            0xca => format!("JZ ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0xcc => format!("CZ ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0xcd | 0xdd | 0xed | 0xfd => format!("CALL ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0xce => format!("ACI ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0xcf => "RST 1".into(),
             0xd0 => "RNC".into(),
             0xd1 => "POP D".into(),
-            0xd2 => format!("JNC ${:04x}", self.read_word(address + 1)),
-            0xd3 => format!("OUT ${:02x}", self.read_byte(address + 1)),
-            0xd4 => format!("CNC ${:04x}", self.read_word(address + 1)),
+            0xd2 => format!("JNC ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0xd3 => format!("OUT ${:02x}", self.peek_byte(address.wrapping_add(1))),
+            0xd4 => format!("CNC ${:04x}", self.peek_word(address.wrapping_add(1))),
             0xd5 => "PUSH D".into(),
-            0xd6 => format!("SUI ${:02x}", self.read_byte(address + 1)),
+            0xd6 => format!("SUI ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0xd7 => "RST 2".into(),
             0xd8 => "RC".into(),
-            0xda => format!("JC ${:04x}", self.read_word(address + 1)),
-            0xdb => format!("IN ${:02x}", self.read_byte(address + 1)),
-            0xdc => format!("CC ${:04x}", self.read_word(address + 1)),
+            0xda => format!("JC ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0xdb => format!("IN ${:02x}", self.peek_byte(address.wrapping_add(1))),
+            0xdc => format!("CC ${:04x}", self.peek_word(address.wrapping_add(1))),
             0xde => "SBI D8".into(),
             0xdf => "RST 3".into(),
             0xe0 => "RPO".into(),
             0xe1 => "POP H".into(),
-            0xe2 => format!("JPO ${:04x}", self.read_word(address + 1)),
+            0xe2 => format!("JPO ${:04x}", self.peek_word(address.wrapping_add(1))),
             0xe3 => "XTHL".into(),
-            0xe4 => format!("CPO ${:04x}", self.read_word(address + 1)),
+            0xe4 => format!("CPO ${:04x}", self.peek_word(address.wrapping_add(1))),
             0xe5 => "PUSH H".into(),
-            0xe6 => format!("ANI ${:02x}", self.read_byte(address + 1)),
+            0xe6 => format!("ANI ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0xe7 => "RST 4".into(),
             0xe8 => "RPE".into(),
             0xe9 => "PCHL".into(),
-            0xea => format!("JPE ${:04x}", self.read_word(address + 1)),
+            0xea => format!("JPE ${:04x}", self.peek_word(address.wrapping_add(1))),
             0xeb => "XCHG".into(),
-            0xec => format!("CPE ${:04x}", self.read_word(address + 1)),
-            0xee => format!("XRI ${:02x}", self.read_byte(address + 1)),
+            0xec => format!("CPE ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0xee => format!("XRI ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0xef => "RST 5".into(),
             0xf0 => "RP".into(),
             0xf1 => "POP PSW".into(),
-            0xf2 => format!("JP ${:04x}", self.read_word(address + 1)),
+            0xf2 => format!("JP ${:04x}", self.peek_word(address.wrapping_add(1))),
             0xf3 => "DI".into(),
-            0xf4 => format!("CP ${:04x}", self.read_word(address + 1)),
+            0xf4 => format!("CP ${:04x}", self.peek_word(address.wrapping_add(1))),
             0xf5 => "PUSH AF".into(),
-            0xf6 => format!("ORI ${:02x}", self.read_byte(address + 1)),
+            0xf6 => format!("ORI ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0xf7 => "RST 6".into(),
             0xf8 => "RM".into(),
             0xf9 => "SPHL".into(),
-            0xfa => format!("JM ${:04x}", self.read_word(address + 1)),
+            0xfa => format!("JM ${:04x}", self.peek_word(address.wrapping_add(1))),
             0xfb => "EI".into(),
-            0xfc => format!("CM ${:04x}", self.read_word(address + 1)),
-            0xfe => format!("CPI ${:02x}", self.read_byte(address + 1)),
+            0xfc => format!("CM ${:04x}", self.peek_word(address.wrapping_add(1))),
+            0xfe => format!("CPI ${:02x}", self.peek_byte(address.wrapping_add(1))),
             0xff => "RST 7".into(),
         }
     }    
@@ -0,0 +1,28 @@
+/// One machine cycle as seen on the bus: the status word the 8080 puts out
+/// at the start of the cycle, the address and the byte transferred
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BusCycle {
+    pub status: u8,
+    pub address: u16,
+    pub data: u8,
+}
+
+impl BusCycle {
+    // Status word bits, D0 to D7
+    /// Interrupt acknowledge
+    pub const INTA: u8 = 1 << 0;
+    /// Active low: set for reads and input, clear for writes and output
+    pub const WO: u8 = 1 << 1;
+    pub const STACK: u8 = 1 << 2;
+    /// Halt acknowledge
+    pub const HLTA: u8 = 1 << 3;
+    pub const OUT: u8 = 1 << 4;
+    /// Opcode fetch
+    pub const M1: u8 = 1 << 5;
+    pub const INP: u8 = 1 << 6;
+    pub const MEMR: u8 = 1 << 7;
+
+    pub fn is(&self, flag: u8) -> bool {
+        self.status & flag != 0
+    }
+}
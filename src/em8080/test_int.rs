@@ -21,7 +21,7 @@ struct TestIO {
 }
 
 impl IOState for TestIO {
-    fn input(&self, _cpu : &Em8080, port: u8) -> u8 {
+    fn input(&mut self, _cpu : &Em8080, port: u8) -> u8 {
         self.io[port as usize]
     }
 
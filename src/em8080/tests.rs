@@ -1,6 +1,6 @@
 use std::{num::ParseIntError};
 
-use crate::em8080::Em8080;
+use crate::em8080::{BusCycle, Em8080};
 use crate::em8080::IOState;
 
 // Many (but not all) test cases are coming from
@@ -14,6 +14,137 @@ fn it_works() {
     assert_eq!(result, 4);
 }
 
+#[test]
+fn test_reset() {
+    let mut sys = Em8080::new();
+    sys.memory[0x1234] = 0x56;
+    sys.pc = 0x1000;
+    sys.sp = 0x2400;
+    sys.a = 0x42;
+    sys.halted = true;
+    sys.interrupts_enabled = true;
+
+    sys.reset();
+    assert_eq!(sys.pc, 0);
+    assert!(!sys.interrupts_enabled);
+    assert!(!sys.halted);
+
+    // Only the power on state is lost
+    assert_eq!(sys.memory[0x1234], 0x56);
+    assert_eq!(sys.sp, 0x2400);
+    assert_eq!(sys.a, 0x42);
+}
+
+#[test]
+fn test_memory_limit() {
+    let mut sys = Em8080::new();
+    sys.set_memory_limit(0x1000);
+
+    sys.write_byte(0x0FFF, 0x12);
+    sys.write_byte(0x1000, 0x34);
+    assert_eq!(sys.read_byte(0x0FFF), 0x12);
+    assert_eq!(sys.read_byte(0x1000), 0xFF);
+    assert_eq!(sys.memory[0x1000], 0);
+
+    // The top byte of the address space exists
+    sys.set_memory_limit(0x10000);
+    sys.write_byte(0xFFFF, 0x56);
+    assert_eq!(sys.read_byte(0xFFFF), 0x56);
+}
+
+#[test]
+fn test_bus_cycles() {
+    // MVI A,42h; STA 0100h; PUSH B; OUT 10h; IN 11h
+    let program = [0x3E, 0x42, 0x32, 0x00, 0x01, 0xC5, 0xD3, 0x10, 0xDB, 0x11];
+    let mut sys = Em8080::from_rom(&program, 0, 0);
+    sys.set_sp(0x2000);
+    sys.b = 0x12;
+    sys.c = 0x34;
+    sys.set_bus_monitor(true);
+
+    let mut io = TestIO::new();
+    io.io[0x11] = 0x99;
+    for _ in 0..5 {
+        sys.emulate(&mut io);
+    }
+
+    let fetch = BusCycle::MEMR | BusCycle::M1 | BusCycle::WO;
+    let read = BusCycle::MEMR | BusCycle::WO;
+    let cycle = |status, address, data| BusCycle { status, address, data };
+    assert_eq!(
+        sys.take_bus_cycles(),
+        vec![
+            cycle(fetch, 0x0000, 0x3E),
+            cycle(read, 0x0001, 0x42),
+            cycle(fetch, 0x0002, 0x32),
+            cycle(read, 0x0003, 0x00),
+            cycle(read, 0x0004, 0x01),
+            cycle(0, 0x0100, 0x42),
+            cycle(fetch, 0x0005, 0xC5),
+            cycle(BusCycle::STACK, 0x1FFF, 0x12),
+            cycle(BusCycle::STACK, 0x1FFE, 0x34),
+            cycle(fetch, 0x0006, 0xD3),
+            cycle(read, 0x0007, 0x10),
+            cycle(BusCycle::OUT, 0x1010, 0x42),
+            cycle(fetch, 0x0008, 0xDB),
+            cycle(read, 0x0009, 0x11),
+            cycle(BusCycle::INP | BusCycle::WO, 0x1111, 0x99),
+        ]
+    );
+    assert!(sys.take_bus_cycles().is_empty());
+}
+
+#[test]
+fn test_halt_at_top_of_memory() {
+    // LXI B at FFFE takes its high byte from 0000, HLT at FFFF acknowledges
+    // at 0000
+    let mut sys = Em8080::new();
+    sys.memory[0xFFFE] = 0x01;
+    sys.memory[0xFFFF] = 0x76;
+    sys.memory[0x0000] = 0x12;
+    assert_eq!(sys.op_name(0xFFFE), "LXI B, $1276");
+    assert_eq!(sys.op_name(0xFFFF), "HLT");
+
+    sys.set_pc(0xFFFF);
+    sys.set_bus_monitor(true);
+    sys.emulate(&mut TestIO::new());
+    assert_eq!(sys.pc(), 0x0000);
+    let halt = sys.take_bus_cycles().pop().unwrap();
+    assert_eq!(halt.address, 0x0000);
+    assert_ne!(halt.status & BusCycle::HLTA, 0);
+}
+
+#[test]
+fn test_stack_at_top_of_memory() {
+    // PUSH B with SP at 0000 writes FFFF and FFFE, POP D reads them back
+    let mut sys = Em8080::new();
+    sys.memory[0x0000] = 0x31; // LXI SP, 0000
+    sys.memory[0x0003] = 0xC5; // PUSH B
+    sys.memory[0x0004] = 0xD1; // POP D
+    sys.set_bc(0x1234);
+    for _ in 0..2 {
+        sys.emulate(&mut TestIO::new());
+    }
+    assert_eq!(sys.sp, 0xFFFE);
+    assert_eq!((sys.memory[0xFFFF], sys.memory[0xFFFE]), (0x12, 0x34));
+
+    sys.emulate(&mut TestIO::new());
+    assert_eq!(sys.sp, 0x0000);
+    assert_eq!(sys.get_de(), 0x1234);
+}
+
+#[test]
+fn test_immediate_at_top_of_memory() {
+    // MVI A at FFFF takes its operand from 0000
+    let mut sys = Em8080::new();
+    sys.memory[0xFFFF] = 0x3E;
+    sys.memory[0x0000] = 0x42;
+    sys.set_pc(0xFFFF);
+    sys.emulate(&mut TestIO::new());
+    assert_eq!(sys.a, 0x42);
+    assert_eq!(sys.pc(), 0x0001);
+}
+
 #[test]
 fn test_read_byte() {
     let mut sys = Em8080::new();
@@ -150,7 +281,7 @@ struct TestIO {
 }
 
 impl IOState for TestIO {
-    fn input(&self, _cpu : &Em8080, port: u8) -> u8 {
+    fn input(&mut self, _cpu : &Em8080, port: u8) -> u8 {
         self.io[port as usize]
     }
 
@@ -0,0 +1,270 @@
+use std::fs;
+use std::path::Path;
+
+use minifb::Key;
+
+#[cfg(test)]
+mod tests;
+
+/// Inputs of the machine, independent of where they come from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MachineInput {
+    Coin,
+    P1Start,
+    P2Start,
+    P1Fire,
+    P1Left,
+    P1Right,
+    P2Fire,
+    P2Left,
+    P2Right,
+    Tilt,
+    P1Up,
+    P1Down,
+    P2Up,
+    P2Down,
+}
+
+impl MachineInput {
+    pub const ALL: [MachineInput; 14] = [
+        MachineInput::Coin,
+        MachineInput::P1Start,
+        MachineInput::P2Start,
+        MachineInput::P1Fire,
+        MachineInput::P1Left,
+        MachineInput::P1Right,
+        MachineInput::P2Fire,
+        MachineInput::P2Left,
+        MachineInput::P2Right,
+        MachineInput::Tilt,
+        MachineInput::P1Up,
+        MachineInput::P1Down,
+        MachineInput::P2Up,
+        MachineInput::P2Down,
+    ];
+
+    /// Name used in config files and input scripts
+    pub fn name(self) -> &'static str {
+        match self {
+            MachineInput::Coin => "coin",
+            MachineInput::P1Start => "p1-start",
+            MachineInput::P2Start => "p2-start",
+            MachineInput::P1Fire => "p1-fire",
+            MachineInput::P1Left => "p1-left",
+            MachineInput::P1Right => "p1-right",
+            MachineInput::P2Fire => "p2-fire",
+            MachineInput::P2Left => "p2-left",
+            MachineInput::P2Right => "p2-right",
+            MachineInput::Tilt => "tilt",
+            MachineInput::P1Up => "p1-up",
+            MachineInput::P1Down => "p1-down",
+            MachineInput::P2Up => "p2-up",
+            MachineInput::P2Down => "p2-down",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|input| input.name() == name)
+    }
+}
+
+/// Which machine inputs are currently held down
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InputState {
+    pressed: u32,
+}
+
+impl InputState {
+    pub fn is_pressed(&self, input: MachineInput) -> bool {
+        self.pressed & (1 << input as u32) != 0
+    }
+
+    pub fn set(&mut self, input: MachineInput, pressed: bool) {
+        if pressed {
+            self.pressed |= 1 << input as u32;
+        } else {
+            self.pressed &= !(1 << input as u32);
+        }
+    }
+
+    /// Inputs held in either state
+    pub fn merge(self, other: InputState) -> InputState {
+        InputState {
+            pressed: self.pressed | other.pressed,
+        }
+    }
+}
+
+/// Host keys bound to each machine input. An input can have several keys,
+/// any of them held counts as pressed.
+pub struct KeyBindings {
+    bindings: Vec<(MachineInput, Key)>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (MachineInput::Coin, Key::C),
+                (MachineInput::P1Start, Key::Q),
+                (MachineInput::P2Start, Key::W),
+                (MachineInput::P1Fire, Key::Space),
+                (MachineInput::P1Left, Key::A),
+                (MachineInput::P1Right, Key::D),
+                (MachineInput::P2Fire, Key::Enter),
+                (MachineInput::P2Left, Key::Left),
+                (MachineInput::P2Right, Key::Right),
+                (MachineInput::Tilt, Key::T),
+                (MachineInput::P1Up, Key::R),
+                (MachineInput::P1Down, Key::F),
+                (MachineInput::P2Up, Key::Up),
+                (MachineInput::P2Down, Key::Down),
+            ],
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Replaces the keys of `input` with a comma separated list of key names,
+    /// e.g. `Space, LeftCtrl`
+    pub fn bind(&mut self, input: MachineInput, keys: &str) -> Result<(), String> {
+        let keys = keys
+            .split(',')
+            .map(|name| parse_key(name.trim()).ok_or(format!("Unknown key: {}", name.trim())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.bindings.retain(|&(bound, _)| bound != input);
+        self.bindings.extend(keys.into_iter().map(|key| (input, key)));
+        Ok(())
+    }
+
+    pub fn keys(&self, input: MachineInput) -> Vec<Key> {
+        self.bindings
+            .iter()
+            .filter(|&&(bound, _)| bound == input)
+            .map(|&(_, key)| key)
+            .collect()
+    }
+
+    pub fn poll(&self, window: &minifb::Window) -> InputState {
+        let mut state = InputState::default();
+        for &(input, key) in &self.bindings {
+            if window.is_key_down(key) {
+                state.set(input, true);
+            }
+        }
+        state
+    }
+
+    /// One line per input listing its keys, for the start up help text
+    pub fn describe(&self) -> String {
+        MachineInput::ALL
+            .iter()
+            .map(|&input| {
+                let keys: Vec<String> = self.keys(input).iter().map(|key| format!("{:?}", key)).collect();
+                format!("{:>9}: {}", input.name(), keys.join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+const KEYS: &[Key] = &[
+    Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4,
+    Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I,
+    Key::J, Key::K, Key::L, Key::M, Key::N, Key::O, Key::P, Key::Q, Key::R,
+    Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8,
+    Key::F9, Key::F10, Key::F11, Key::F12, Key::F13, Key::F14, Key::F15,
+    Key::Down, Key::Left, Key::Right, Key::Up,
+    Key::Apostrophe, Key::Backquote, Key::Backslash, Key::Comma, Key::Equal,
+    Key::LeftBracket, Key::Minus, Key::Period, Key::RightBracket, Key::Semicolon,
+    Key::Slash, Key::Backspace, Key::Delete, Key::End, Key::Enter, Key::Escape,
+    Key::Home, Key::Insert, Key::Menu, Key::PageDown, Key::PageUp, Key::Pause,
+    Key::Space, Key::Tab, Key::NumLock, Key::CapsLock, Key::ScrollLock,
+    Key::LeftShift, Key::RightShift, Key::LeftCtrl, Key::RightCtrl,
+    Key::NumPad0, Key::NumPad1, Key::NumPad2, Key::NumPad3, Key::NumPad4,
+    Key::NumPad5, Key::NumPad6, Key::NumPad7, Key::NumPad8, Key::NumPad9,
+    Key::NumPadDot, Key::NumPadSlash, Key::NumPadAsterisk, Key::NumPadMinus,
+    Key::NumPadPlus, Key::NumPadEnter,
+    Key::LeftAlt, Key::RightAlt, Key::LeftSuper, Key::RightSuper,
+];
+
+/// Parses a key by its minifb name, ignoring case. Digits can be given
+/// without the `Key` prefix.
+pub fn parse_key(name: &str) -> Option<Key> {
+    KEYS.iter().copied().find(|key| {
+        let key_name = format!("{:?}", key);
+        key_name.eq_ignore_ascii_case(name)
+            || key_name.strip_prefix("Key").is_some_and(|digit| digit == name)
+    })
+}
+
+/// Timed input changes for headless runs. Each line of a script file is
+/// `<frame> <input> <down|up>`, e.g. `60 coin down`. Lines must be in frame
+/// order; `#` starts a comment.
+pub struct InputScript {
+    events: Vec<(u64, MachineInput, bool)>,
+    next: usize,
+    state: InputState,
+}
+
+impl InputScript {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read {}: {}", path.as_ref().display(), e))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = || format!("Line {}: expected `<frame> <input> <down|up>`", number + 1);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(error());
+            }
+
+            let frame: u64 = fields[0].parse().map_err(|_| error())?;
+            let input = MachineInput::from_name(fields[1])
+                .ok_or(format!("Line {}: unknown input {}", number + 1, fields[1]))?;
+            let pressed = match fields[2] {
+                "down" => true,
+                "up" => false,
+                _ => return Err(error()),
+            };
+
+            if events.last().is_some_and(|&(last, _, _)| frame < last) {
+                return Err(format!("Line {}: frames must be in order", number + 1));
+            }
+
+            events.push((frame, input, pressed));
+        }
+
+        Ok(Self {
+            events,
+            next: 0,
+            state: InputState::default(),
+        })
+    }
+
+    /// Applies every event up to and including `frame`, returns the inputs
+    /// the script is holding down
+    pub fn advance(&mut self, frame: u64) -> InputState {
+        while let Some(&(at, input, pressed)) = self.events.get(self.next) {
+            if at > frame {
+                break;
+            }
+            self.state.set(input, pressed);
+            self.next += 1;
+        }
+        self.state
+    }
+}
@@ -0,0 +1,55 @@
+use minifb::Key;
+
+use crate::input::{parse_key, InputScript, InputState, KeyBindings, MachineInput};
+
+#[test]
+fn test_input_names() {
+    for input in MachineInput::ALL {
+        assert_eq!(MachineInput::from_name(input.name()), Some(input));
+    }
+    assert_eq!(MachineInput::from_name("p3-fire"), None);
+}
+
+#[test]
+fn test_parse_key() {
+    assert_eq!(parse_key("space"), Some(Key::Space));
+    assert_eq!(parse_key("LeftCtrl"), Some(Key::LeftCtrl));
+    assert_eq!(parse_key("5"), Some(Key::Key5));
+    assert_eq!(parse_key("Key5"), Some(Key::Key5));
+    assert_eq!(parse_key("Hyper"), None);
+}
+
+#[test]
+fn test_bind_replaces_defaults() {
+    let mut bindings = KeyBindings::default();
+    assert_eq!(bindings.keys(MachineInput::Coin), vec![Key::C]);
+
+    bindings.bind(MachineInput::Coin, "Key5, Insert").unwrap();
+    assert_eq!(bindings.keys(MachineInput::Coin), vec![Key::Key5, Key::Insert]);
+    assert_eq!(bindings.keys(MachineInput::P1Fire), vec![Key::Space]);
+
+    assert!(bindings.bind(MachineInput::Coin, "Key5, Nope").is_err());
+    assert_eq!(bindings.keys(MachineInput::Coin), vec![Key::Key5, Key::Insert]);
+}
+
+#[test]
+fn test_input_script() {
+    let mut script = InputScript::parse(
+        "# insert a coin and start\n10 coin down\n12 coin up\n12 p1-start down # same frame\n14 p1-start up\n",
+    )
+    .unwrap();
+
+    assert_eq!(script.advance(9), InputState::default());
+    assert!(script.advance(10).is_pressed(MachineInput::Coin));
+    assert!(script.advance(11).is_pressed(MachineInput::Coin));
+
+    let state = script.advance(12);
+    assert!(!state.is_pressed(MachineInput::Coin));
+    assert!(state.is_pressed(MachineInput::P1Start));
+
+    assert_eq!(script.advance(100), InputState::default());
+
+    assert!(InputScript::parse("10 coin pressed").is_err());
+    assert!(InputScript::parse("10 p3-start down").is_err());
+    assert!(InputScript::parse("10 coin down\n5 coin up").is_err());
+}
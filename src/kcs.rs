@@ -0,0 +1,173 @@
+use std::f64::consts::TAU;
+
+#[cfg(test)]
+mod tests;
+
+// The Kansas City Standard for storing data on audio cassettes, as used by
+// the MITS 88-ACR and most other 8080 era cassette interfaces: 300 baud
+// asynchronous serial where a 0 bit is four cycles of 1200 Hz and a 1 bit
+// is eight cycles of 2400 Hz. Each byte is a 0 start bit, eight data bits
+// least significant first and two 1 stop bits. The line idles at 1.
+
+pub const BAUD: u32 = 300;
+pub const SPACE_HZ: f64 = 1200.0;
+pub const MARK_HZ: f64 = 2400.0;
+/// Bit times a byte takes, counting the start and stop bits
+pub const BITS_PER_BYTE: u32 = 11;
+/// Mark tone recorded before the data so the reader can settle
+pub const LEADER_SECONDS: f64 = 2.0;
+
+const AMPLITUDE: f64 = 0.5 * i16::MAX as f64;
+
+/// Turns bytes into a KCS audio signal
+pub struct Encoder {
+    sample_rate: u32,
+    samples: Vec<i16>,
+    /// Bit times encoded so far
+    bits: f64,
+    /// Phase of the tone in radians, kept across bits so the frequency
+    /// shifts without clicks
+    phase: f64,
+}
+
+impl Encoder {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            samples: Vec::new(),
+            bits: 0.0,
+            phase: 0.0,
+        }
+    }
+
+    /// Mark tone, for the leader and the gaps between bytes
+    pub fn idle(&mut self, seconds: f64) {
+        self.tone(true, seconds * BAUD as f64);
+    }
+
+    pub fn byte(&mut self, byte: u8) {
+        self.tone(false, 1.0);
+        for bit in 0..8 {
+            self.tone(byte & (1 << bit) != 0, 1.0);
+        }
+        self.tone(true, 2.0);
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// `bits` bit times of the tone for `mark`. Bit edges fall on the
+    /// nearest sample, so rounding doesn't accumulate over a long recording.
+    fn tone(&mut self, mark: bool, bits: f64) {
+        let frequency = if mark { MARK_HZ } else { SPACE_HZ };
+        let step = TAU * frequency / self.sample_rate as f64;
+
+        self.bits += bits;
+        let end = (self.bits * self.sample_rate as f64 / BAUD as f64).round() as usize;
+        while self.samples.len() < end {
+            self.samples.push((self.phase.sin() * AMPLITUDE) as i16);
+            self.phase = (self.phase + step) % TAU;
+        }
+    }
+}
+
+/// `data` as a complete recording: leader, the bytes and a short trailer
+pub fn encode(data: &[u8], sample_rate: u32) -> Vec<i16> {
+    let mut encoder = Encoder::new(sample_rate);
+    encoder.idle(LEADER_SECONDS);
+    for &byte in data {
+        encoder.byte(byte);
+    }
+    encoder.idle(0.1);
+    encoder.samples
+}
+
+/// Recovers the bytes from a KCS recording. Bytes with a bad stop bit are
+/// dropped, as the UART on the cassette interface would flag them.
+pub fn decode(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let tones = tones(samples, sample_rate);
+    let bit = sample_rate as f64 / BAUD as f64;
+    let mut bytes = Vec::new();
+
+    let mut i = 1;
+    while i < tones.len() {
+        // Wait for the falling edge of a start bit
+        let falling = tones[i - 1] && !tones[i];
+        if !falling {
+            i += 1;
+            continue;
+        }
+
+        // Tone in the middle of bit `n` from the start bit
+        let at = |n: f64| tones.get(i + (n * bit) as usize).copied();
+        if at(0.5) != Some(false) {
+            // A glitch rather than a start bit
+            i += 1;
+            continue;
+        }
+
+        let mut byte = 0;
+        for n in 0..8 {
+            match at(1.5 + n as f64) {
+                Some(true) => byte |= 1 << n,
+                Some(false) => {}
+                None => return bytes,
+            }
+        }
+
+        if at(9.5) == Some(true) {
+            bytes.push(byte);
+            i += (9.5 * bit) as usize;
+        } else {
+            i += 1;
+        }
+    }
+
+    bytes
+}
+
+/// Which tone each sample is part of, true for mark. Measures the time
+/// between every other zero crossing, so a full cycle, which doesn't care
+/// about a lopsided waveform. Silence counts as mark, the idle state.
+fn tones(samples: &[i16], sample_rate: u32) -> Vec<bool> {
+    // Ignore wobbles around zero smaller than this
+    let peak = samples.iter().map(|&s| (s as i32).abs()).max().unwrap_or(0);
+    let hysteresis = peak / 8;
+
+    let mut crossings = Vec::new();
+    let mut positive = None;
+    for (i, &sample) in samples.iter().enumerate() {
+        let sample = sample as i32;
+        let now = if sample > hysteresis {
+            Some(true)
+        } else if sample < -hysteresis {
+            Some(false)
+        } else {
+            positive
+        };
+        if now != positive && positive.is_some() {
+            crossings.push(i);
+        }
+        positive = now;
+    }
+
+    // Cycles longer than this are space, and much longer ones are a gap
+    let threshold = sample_rate as f64 / ((SPACE_HZ + MARK_HZ) / 2.0);
+    let longest = 2.0 * sample_rate as f64 / SPACE_HZ;
+
+    let mut tones = vec![true; samples.len()];
+    for pair in crossings.windows(3) {
+        let period = (pair[2] - pair[0]) as f64;
+        let mark = period < threshold || period > longest;
+        // The later half cycle, so edges aren't smeared earlier
+        for tone in &mut tones[pair[1]..pair[2]] {
+            *tone = mark;
+        }
+    }
+    tones
+}
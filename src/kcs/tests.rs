@@ -0,0 +1,67 @@
+use crate::kcs::{self, Encoder, BAUD, BITS_PER_BYTE, LEADER_SECONDS};
+use crate::wav::Wav;
+
+fn all_bytes() -> Vec<u8> {
+    (0..=255).collect()
+}
+
+#[test]
+fn test_encoded_length() {
+    let mut encoder = Encoder::new(44100);
+    encoder.idle(LEADER_SECONDS);
+    assert_eq!(encoder.samples().len(), 88200);
+
+    // 300 baud doesn't divide 44.1 kHz, but edges stay on the right sample
+    for _ in 0..300 {
+        encoder.byte(0x55);
+    }
+    let bits = 300 * BITS_PER_BYTE as usize;
+    assert_eq!(encoder.samples().len(), 88200 + bits * 44100 / BAUD as usize);
+}
+
+#[test]
+fn test_round_trip() {
+    for rate in [8000, 11025, 22050, 44100, 48000] {
+        let samples = kcs::encode(&all_bytes(), rate);
+        assert_eq!(kcs::decode(&samples, rate), all_bytes(), "at {} Hz", rate);
+    }
+}
+
+#[test]
+fn test_round_trip_through_wav() {
+    let data = b"10 PRINT \"HELLO\"\r20 GOTO 10\r".to_vec();
+    let wav = Wav {
+        sample_rate: 22050,
+        channels: 1,
+        samples: kcs::encode(&data, 22050),
+    };
+
+    let wav = Wav::parse(&wav.to_bytes()).unwrap();
+    assert_eq!(kcs::decode(&wav.to_mono(), wav.sample_rate), data);
+}
+
+#[test]
+fn test_decode_worn_tape() {
+    // Quieter, offset from zero, with hiss, and a gap in the middle
+    let mut samples: Vec<i16> = kcs::encode(&all_bytes()[..128], 44100);
+    samples.extend(vec![0; 44100]);
+    samples.extend(kcs::encode(&all_bytes()[128..], 44100));
+
+    let mut noise: u32 = 1;
+    let worn: Vec<i16> = samples
+        .iter()
+        .map(|&sample| {
+            noise = noise.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let hiss = (noise >> 16) as i32 % 1500 - 750;
+            (sample as i32 / 4 + 300 + hiss) as i16
+        })
+        .collect();
+
+    assert_eq!(kcs::decode(&worn, 44100), all_bytes());
+}
+
+#[test]
+fn test_decode_silence() {
+    assert!(kcs::decode(&[], 44100).is_empty());
+    assert!(kcs::decode(&[0; 44100], 44100).is_empty());
+}
@@ -1,234 +1,305 @@
 
+mod altair;
+mod config;
+mod cpm;
+mod cpmfile;
+mod cpmfs;
+mod cpmsim;
+mod disk;
 mod em8080;
-
-use crate::em8080::{Em8080, IOState};
-
-pub const SCREEN_WIDTH: usize = 224;
-pub const SCREEN_HEIGHT: usize = 256;
+mod input;
+mod kcs;
+mod mb14241;
+pub mod midway;
+mod overlay;
+mod pacing;
+mod perf;
+mod session;
+mod sound;
+mod terminal;
+#[cfg(test)]
+mod testing;
+mod video;
+mod watchdog;
+mod wav;
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::config::Settings;
+use crate::input::{InputScript, KeyBindings, MachineInput};
+use crate::midway::{games, Cabinet, GameDef, InvadersDips, MidwayBoard};
+use crate::overlay::Overlay;
+use crate::pacing::{Pacer, Speed};
+use crate::perf::{Counters, PerfOverlay};
+use crate::sound::{SampleEngine, SoundEngine, SynthEngine, WavSink};
+use crate::video::RenderMode;
 
 use minifb;
 
-#[derive(Clone, Copy)]
-#[repr(C)]
-pub union RegisterPair {
-    both: u16,
-    one: (u8, u8),
+/// Command line options. Every option can also be given in a config file
+/// loaded with `--config`, using its name without the dashes as the key.
+#[derive(Default)]
+struct Options {
+    /// Short name of the game, see `midway::games`
+    game: Option<String>,
+    /// All of the game's ROMs concatenated in ROM map order
+    rom: Option<PathBuf>,
+    /// Directory holding the game's ROMs under their usual file names
+    rom_dir: Option<PathBuf>,
+    /// Directory containing the 0.wav .. 9.wav sample set
+    samples: Option<PathBuf>,
+    /// Use the synthesized sound board instead of samples
+    synth: bool,
+    /// Master volume, 0.0 - 1.0
+    volume: Option<f32>,
+    /// `scanline` or `half-frame`
+    renderer: Option<RenderMode>,
+    /// `upright` or `cocktail`
+    cabinet: Option<Cabinet>,
+    /// Overlay preset name or overlay table file, unused by colour games
+    overlay: Option<String>,
+    /// Record the sound to this file
+    wav_out: Option<PathBuf>,
+    /// Run without a window, e.g. to render sound to a file
+    headless: bool,
+    /// Stop after this many frames
+    frames: Option<u64>,
+    /// Run as fast as possible and report the speed, for benchmarking
+    uncapped: bool,
+    /// Scripted inputs, see `InputScript`
+    input_script: Option<PathBuf>,
+    bindings: KeyBindings,
+    /// Space Invaders' DIP switches, when any of them are given
+    invaders_dips: Option<InvadersDips>,
+    /// Other DIP switch settings as `(switch, setting)`, checked against the game
+    dips: Vec<(String, String)>,
 }
 
-impl RegisterPair {
-    pub fn new() -> Self {
-        Self { both: 0 }
-    }
+impl Options {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let options: Self = config::parse_args(args)?;
 
-    pub fn both(self) -> u16 {
-        unsafe { self.both }
-    }
+        if options.headless && options.frames.is_none() {
+            return Err("--headless needs --frames".into());
+        }
 
-    pub fn both_mut(&mut self) -> &mut u16 {
-        unsafe { &mut self.both }
+        Ok(options)
     }
+}
 
-    /// Least significant byte
-    pub fn lsb(self) -> u8 {
-        unsafe { self.one.0 }
-    }
+impl Settings for Options {
+    const FLAGS: &'static [&'static str] = &["synth", "headless", "uncapped", "coin-info", "no-coin-info"];
 
-    /// Least significant byte
-    pub fn lsb_mut(&mut self) -> &mut u8 {
-        unsafe { &mut self.one.0 }
-    }
+    /// Applies one option. `value` is `None` for flags given on the command line.
+    fn set(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        let flag = || value.map_or(Ok(true), config::parse_bool);
+        let value = || value.ok_or(format!("{} needs a value", name));
+        let number = |kind: &str| {
+            let value = value()?;
+            value.parse::<u64>().map_err(|_| format!("Invalid {}: {}", kind, value))
+        };
 
-    /// Most significant byte
-    pub fn msb(self) -> u8 {
-        unsafe { self.one.1 }
+        match name {
+            "game" => self.game = Some(value()?.to_string()),
+            "rom" => self.rom = Some(value()?.into()),
+            "rom-dir" => self.rom_dir = Some(value()?.into()),
+            "dip" => {
+                let value = value()?;
+                let (dip, setting) = value.split_once('=').ok_or(format!("--dip needs NAME=VALUE, got {}", value))?;
+                self.dips.push((dip.to_string(), setting.to_string()));
+            }
+            "samples" => self.samples = Some(value()?.into()),
+            "synth" => self.synth = flag()?,
+            "volume" => {
+                let volume = value()?;
+                self.volume = Some(volume.parse().map_err(|_| format!("Invalid volume: {}", volume))?);
+            }
+            "cabinet" => {
+                let name = value()?;
+                self.cabinet = Some(Cabinet::from_name(name).ok_or(format!("Unknown cabinet: {}", name))?);
+            }
+            "overlay" => self.overlay = Some(value()?.to_string()),
+            "renderer" => {
+                let name = value()?;
+                self.renderer = Some(RenderMode::from_name(name).ok_or(format!("Unknown renderer: {}", name))?);
+            }
+            "wav-out" => self.wav_out = Some(value()?.into()),
+            "headless" => self.headless = flag()?,
+            "frames" => self.frames = Some(number("frame count")?),
+            "uncapped" => self.uncapped = flag()?,
+            "input-script" => self.input_script = Some(value()?.into()),
+            "lives" => {
+                let lives = number("number of lives")?.min(u8::MAX as u64) as u8;
+                self.invaders_dips.get_or_insert_with(InvadersDips::default).set_lives(lives)?
+            }
+            "bonus-at" => {
+                let bonus_at = number("bonus score")?.min(u16::MAX as u64) as u16;
+                self.invaders_dips.get_or_insert_with(InvadersDips::default).set_bonus_at(bonus_at)?
+            }
+            "coin-info" | "no-coin-info" => {
+                let on = flag()? == (name == "coin-info");
+                self.invaders_dips.get_or_insert_with(InvadersDips::default).coin_info = on;
+            }
+            _ => {
+                let input = name
+                    .strip_prefix("bind-")
+                    .and_then(MachineInput::from_name)
+                    .ok_or(format!("Unknown option: {}", name))?;
+                self.bindings.bind(input, value()?)?;
+            }
+        }
+
+        Ok(())
     }
 
-    /// Most significant byte
-    pub fn msb_mut(&mut self) -> &mut u8 {
-        unsafe { &mut self.one.1 }
+    fn usage() {
+        println!("Usage: emulator-8080 [--config FILE] [--game NAME] [--rom FILE | --rom-dir DIR]");
+        println!("                     [--samples DIR | --synth] [--volume V]");
+        println!("                     [--cabinet upright|cocktail] [--overlay upright|mono|FILE] [--renderer scanline|half-frame] [--wav-out FILE] [--headless] [--frames N] [--uncapped]");
+        println!("                     [--lives 3-6] [--bonus-at 1000|1500] [--coin-info | --no-coin-info] [--dip NAME=VALUE]");
+        println!("                     [--input-script FILE] [--bind-<input> KEY[,KEY...]]");
+        println!("       emulator-8080 altair --help for the Altair 8800");
+        println!("       emulator-8080 cpm --help for CP/M programs in host directories");
+        println!("       emulator-8080 cpmsim --help for CP/M disk images made for z80pack");
+        println!("       emulator-8080 cpmfs --help to copy files in and out of CP/M disk images");
+        println!("Games: {}", games::GAMES.iter().map(|game| game.name).collect::<Vec<_>>().join(", "));
     }
 }
 
-struct InvadersIO {
-    shift_register: RegisterPair,
-    shift_amount: u8,
-    port0: u8,
-    port1: u8,
-    port2: u8,
-}
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
 
-impl IOState for InvadersIO {
-    fn input(&self, _cpu: &Em8080, port: u8) -> u8 {
-        match port {
-            1 => {
-                self.port1
-            },
-            2 => self.port2,
-            3 => (self.shift_register.both() >> (8 - self.shift_amount)) as u8,
-            _ => panic!("Cannot read port: {}", port),
-        }
+    // Other machines are subcommands, the arcade games are the default
+    if args.peek().map(String::as_str) == Some("altair") {
+        args.next();
+        return altair::cli::main(args);
+    }
+    if args.peek().map(String::as_str) == Some("cpm") {
+        args.next();
+        return cpm::cli::main(args);
+    }
+    if args.peek().map(String::as_str) == Some("cpmfs") {
+        args.next();
+        return cpmfs::cli::main(args);
+    }
+    if args.peek().map(String::as_str) == Some("cpmsim") {
+        args.next();
+        return cpmsim::cli::main(args);
     }
 
-    fn output(&mut self, cpu : &Em8080, port: u8, value: u8) {
-        match port {
-            2 => self.shift_amount = value & 0b111,
-            4 => {
-                *self.shift_register.lsb_mut() = self.shift_register.msb();
-                *self.shift_register.msb_mut() = value;
+    let options = Options::parse(args).unwrap_or_else(|e| {
+        println!("{}", e);
+        Options::usage();
+        std::process::exit(1);
+    });
+
+    let game = options.game.as_deref().unwrap_or("invaders");
+    let game = games::find(game).unwrap_or_else(|| {
+        println!("Unknown game: {}", game);
+        std::process::exit(1);
+    });
+    let roms = load_roms(game, &options).unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(1);
+    });
+
+    let sound: Option<Box<dyn SoundEngine>> = if options.synth {
+        let mut engine = SynthEngine::new();
+        if let Some(volume) = options.volume {
+            engine.set_volume(volume);
+        }
+        Some(Box::new(engine))
+    } else {
+        options.samples.as_ref().and_then(|dir| match SampleEngine::load(dir) {
+            Ok(mut engine) => {
+                if let Some(volume) = options.volume {
+                    engine.set_volume(volume);
+                }
+                Some(Box::new(engine) as Box<dyn SoundEngine>)
             }
-            3 | 5 | 6 => {}
-            _ => panic!("Cannot write to port: {}", port),
+            Err(e) => {
+                println!("Sound disabled: {}", e);
+                None
+            }
+        })
+    };
+
+    let mut invaders = MidwayBoard::new(game, &roms, sound);
+    if let Some(dips) = &options.invaders_dips {
+        if let Err(e) = invaders.set_invaders_dips(dips) {
+            println!("{}", e);
+            std::process::exit(1);
         }
     }
-    
-}
-
-impl InvadersIO {
-    pub fn new() -> Self {
-        Self {
-            shift_register: RegisterPair::new(),
-            shift_amount: 0,
-            port0: 0b0111_0000,
-            port1: 0b0001_0000,
-            port2: 0b0000_0000,
-        }
-    }
-
-    fn update_input(&mut self, window: &minifb::Window) {
-        // Credit
-        Self::set_key(&mut self.port1, 0, window.is_key_down(minifb::Key::C));
-        // P2 Start
-        Self::set_key(&mut self.port1, 1, window.is_key_down(minifb::Key::W));
-        // P1 Start
-        Self::set_key(&mut self.port1, 2, window.is_key_down(minifb::Key::Q));
-        // Always 1
-        Self::set_key(&mut self.port1, 3, true);
-
-        // P1 Fire
-        Self::set_key(&mut self.port1, 4, window.is_key_down(minifb::Key::Space));
-        // P1 Left
-        Self::set_key(&mut self.port1, 5, window.is_key_down(minifb::Key::A));
-        // P1 Right
-        Self::set_key(&mut self.port1, 6, window.is_key_down(minifb::Key::D));
-
-        // P2 Fire
-        Self::set_key(&mut self.port2, 4, window.is_key_down(minifb::Key::Enter));
-        // P2 Left
-        Self::set_key(&mut self.port2, 5, window.is_key_down(minifb::Key::Left));
-        // P2 Right
-        Self::set_key(&mut self.port2, 6, window.is_key_down(minifb::Key::Right));
-    }
-
-    fn set_key(port: &mut u8, bit: u8, on: bool) {
-        if on {
-            *port |= 1 << bit
-        } else {
-            *port &= !(1 << bit)
+    for (dip, setting) in &options.dips {
+        if let Err(e) = invaders.set_dip(dip, setting) {
+            println!("{}", e);
+            std::process::exit(1);
         }
-    }    
-}
-
-pub struct SpaceInvaders {
-    cpu: Em8080,
-    io_state: InvadersIO,
-    window_buffer: [u32; 224 * 256],
-    instructions: u64,
-    cycles: u64,
-    frames: u64,
-}
-
-impl SpaceInvaders {
-    const CYCLES_PER_FRAME: u64 = 4_000_000 / 60;
-    pub const SCREEN_WIDTH: usize = 224;
-    pub const SCREEN_HEIGHT: usize = 256;
-
-    pub fn new() -> Self {
-        Self::from_rom(include_bytes!("invaders.rom"))
     }
-
-    pub fn from_rom(rom: &[u8]) -> Self {
-        Self {
-            cpu: Em8080::from_rom(rom, 0, 0),
-            io_state: InvadersIO::new(),
-            window_buffer: [0; 224 * 256],
-            instructions: 0,
-            cycles: 0,
-            frames: 0,
-        }
+    if let Some(cabinet) = options.cabinet {
+        invaders.set_cabinet(cabinet);
     }
-
-    // Proceeds one frame of the emulator
-    pub fn step(&mut self, window: &mut minifb::Window) {
-        self.half_step(window, true);
-        self.half_step(window, false);
-
-        self.frames += 1;
-
-        // Lastly, update input
-        self.io_state.update_input(window);
-
-        std::thread::sleep(std::time::Duration::from_millis(16));
+    if let Some(mode) = options.renderer {
+        invaders.set_render_mode(mode);
     }
 
-    fn half_step(&mut self, window: &mut minifb::Window, top_half: bool) {
-        let mut cycles_spent = 0;
-        while cycles_spent < Self::CYCLES_PER_FRAME / 2 {
-            let cycles = self.cpu.emulate(&mut self.io_state);
-
-            cycles_spent += cycles;
-
-            // For monitoring/debug purposes
-            self.instructions += 1;
-            self.cycles += cycles;
+    if let Some(name) = &options.overlay {
+        let (width, height) = invaders.screen_size();
+        match Overlay::preset(name, width, height, game.overlay).map_or_else(|| Overlay::load(name, width, height), Ok) {
+            Ok(overlay) => invaders.set_overlay(overlay),
+            Err(e) => println!("Could not load overlay {}: {}", name, e),
         }
-
-        // Render half of the screen
-        window.update_with_buffer(&self.screen(top_half), SCREEN_WIDTH, SCREEN_HEIGHT)
-              .unwrap_or_else(|e| println!("Failed to update window buffer: {}", e));
-
-        // Middle/end of frame interrupt
-        self.cpu.interrupt(if top_half { 1 } else { 2 });
     }
 
-    fn screen(&mut self, top_half: bool) -> &[u32] {
-        let (start_memory, start_pixel) = if top_half {
-            (0x2400, 0)
-        } else {
-            (0x3200, 0x7000)
-        };
+    if let Some(path) = &options.wav_out {
+        match WavSink::create(path) {
+            Ok(sink) => invaders.add_audio_sink(Box::new(sink)),
+            Err(e) => println!("Could not create {}: {}", path.display(), e),
+        }
+    }
 
-        // Iterate half the screen
-        for offset in 0..0xE00 {
-            let byte = self.cpu.memory[start_memory + offset];
+    let mut script = options.input_script.as_ref().map(|path| {
+        InputScript::load(path).unwrap_or_else(|e| {
+            println!("{}", e);
+            std::process::exit(1);
+        })
+    });
 
-            for bit in 0..8 {
-                let color: u32 = if byte & (1 << bit) == 0 {
-                    0x00_00_00_00
-                } else {
-                    0xff_ff_ff_ff
-                };
+    let started = Instant::now();
 
-                let x = (start_pixel + 8 * offset + bit) / Self::SCREEN_HEIGHT;
-                let y = Self::SCREEN_HEIGHT - 1 - (start_pixel + 8 * offset + bit) % Self::SCREEN_HEIGHT;
-                self.window_buffer[x + y * Self::SCREEN_WIDTH] = color;
+    if options.headless {
+        for frame in 0..options.frames.unwrap_or(0) {
+            if let Some(script) = script.as_mut() {
+                invaders.update_input(script.advance(frame));
             }
+            invaders.step();
         }
 
-        &self.window_buffer
+        if options.uncapped {
+            print_speed(&invaders, started);
+        }
+        return;
     }
-}
 
-fn main() {
+    #[cfg(feature = "audio")]
+    match sound::Playback::open() {
+        Ok(playback) => invaders.add_audio_sink(Box::new(playback)),
+        Err(e) => println!("No sound output: {}", e),
+    }
 
-    println!("Space Invaders. Keys:");
-    println!("C to add credits. Q: Start with 1 player, W: start with 2 players");
-    println!("Player 1 move A and D, fire Space");
+    println!("{} ({:.2} Hz). Keys:", game.title, invaders.frame_rate());
+    println!("{}", options.bindings.describe());
+    println!("P to pause, N to advance a frame while paused, hold Tab to fast forward");
+    println!("F2 to show performance, F3 to reset, Shift+F3 to power cycle");
 
     // Create window
+    let (width, height) = invaders.screen_size();
     let mut window = minifb::Window::new(
         "8080-emulator",
-        224,
-        256,
+        width,
+        height,
         minifb::WindowOptions {
             borderless: false,
             title: true,
@@ -241,14 +312,104 @@ fn main() {
         },
     ).expect("Could not create window");    
     
-    let mut invaders = SpaceInvaders::new();
 //    invaders.cpu.set_sp(0x4000);
    // invaders.cpu.trace = true;
 
 
-    while window.is_open() {
-        invaders.step(&mut window);
-        std::thread::sleep(std::time::Duration::from_millis(16));
+    // Frame rate is governed by the pacer
+    window.limit_update_rate(None);
+
+    let mut perf = PerfOverlay::new(invaders.clock_hz());
+    let mut pacer = Pacer::new(invaders.clock_hz());
+    if options.uncapped {
+        pacer.set_speed(Speed::Uncapped, invaders.cycles());
     }
 
+    while window.is_open() && options.frames.is_none_or(|frames| invaders.frames() < frames) {
+        if window.is_key_pressed(minifb::Key::P, minifb::KeyRepeat::No) {
+            pacer.set_paused(!pacer.is_paused(), invaders.cycles());
+        }
+        if window.is_key_pressed(minifb::Key::N, minifb::KeyRepeat::Yes) {
+            pacer.advance_frame();
+        }
+        if window.is_key_pressed(minifb::Key::F2, minifb::KeyRepeat::No) {
+            perf.toggle();
+        }
+        if window.is_key_pressed(minifb::Key::F3, minifb::KeyRepeat::No) {
+            if window.is_key_down(minifb::Key::LeftShift) || window.is_key_down(minifb::Key::RightShift) {
+                invaders.hard_reset();
+            } else {
+                invaders.soft_reset();
+            }
+        }
+        if !options.uncapped {
+            let speed = if window.is_key_down(minifb::Key::Tab) { Speed::FastForward } else { Speed::Normal };
+            pacer.set_speed(speed, invaders.cycles());
+        }
+
+        if !pacer.should_run_frame() {
+            // Keep processing window events while paused
+            window.update();
+            std::thread::sleep(Duration::from_millis(16));
+            continue;
+        }
+
+        let mut input = options.bindings.poll(&window);
+        if let Some(script) = script.as_mut() {
+            input = input.merge(script.advance(invaders.frames()));
+        }
+        invaders.update_input(input);
+
+        invaders.step();
+
+        let counters = Counters {
+            frames: invaders.frames(),
+            cycles: invaders.cycles(),
+            instructions: invaders.instructions(),
+        };
+        perf.update(counters, Instant::now());
+
+        let result = if perf.is_visible() {
+            let mut screen = invaders.frame_buffer().to_vec();
+            perf.draw(&mut screen, width);
+            window.update_with_buffer(&screen, width, height)
+        } else {
+            window.update_with_buffer(invaders.frame_buffer(), width, height)
+        };
+        result.unwrap_or_else(|e| println!("Failed to update window buffer: {}", e));
+
+        pacer.wait(invaders.cycles());
+    }
+
+    if options.uncapped {
+        print_speed(&invaders, started);
+    }
+}
+
+/// ROMs from `--rom` or `--rom-dir`, falling back to the built in Space
+/// Invaders image
+fn load_roms(game: &GameDef, options: &Options) -> Result<Vec<(u16, Vec<u8>)>, String> {
+    if let Some(path) = &options.rom {
+        let image = std::fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        return game.split_rom_image(&image);
+    }
+    if let Some(dir) = &options.rom_dir {
+        return game.load_rom_dir(dir);
+    }
+    if game.name == games::INVADERS.name {
+        return game.split_rom_image(include_bytes!("invaders.rom"));
+    }
+    Err(format!("{} needs --rom or --rom-dir", game.name))
+}
+
+fn print_speed(invaders: &MidwayBoard, started: Instant) {
+    let elapsed = started.elapsed().as_secs_f64();
+    let emulated = invaders.cycles() as f64 / invaders.clock_hz() as f64;
+    println!(
+        "{} frames in {:.2} s, {:.0} fps, {:.0}% of real time",
+        invaders.frames(),
+        elapsed,
+        invaders.frames() as f64 / elapsed,
+        100.0 * emulated / elapsed
+    );
 }
@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod tests;
+
+// The Fujitsu MB14241 barrel shifter lets the 8080 draw sprites at any pixel
+// offset without shifting every byte itself. Bytes written to the data port
+// are shifted into a 16 bit register from the top, and the result port reads
+// back 8 bits from it starting `count` bits below the top.
+
+/// Which I/O ports the shifter answers on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShifterPorts {
+    /// Write: shift amount in the low 3 bits
+    pub count: u8,
+    /// Write: next byte shifted in
+    pub data: u8,
+    /// Read: shifted result
+    pub result: u8,
+    /// Read: shifted result with its bits in reverse order, as Sea Wolf
+    /// wires up a second port for sprites facing the other way
+    pub reversed_result: Option<u8>,
+    /// Bit 3 of the shift count makes `result` read back bit-reversed, as
+    /// on Boot Hill and Gun Fight
+    pub count_selects_reverse: bool,
+}
+
+impl ShifterPorts {
+    /// Space Invaders: count on 2, data on 4, result on 3
+    pub const INVADERS: Self = Self {
+        count: 2,
+        data: 4,
+        result: 3,
+        reversed_result: None,
+        count_selects_reverse: false,
+    };
+
+    /// Boot Hill: count on 1, data on 2, result on 3
+    pub const BOOTHILL: Self = Self {
+        count: 1,
+        data: 2,
+        result: 3,
+        reversed_result: None,
+        count_selects_reverse: true,
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mb14241 {
+    ports: ShifterPorts,
+    /// Last two bytes written, the most recent in the upper half
+    data: u16,
+    count: u8,
+    reverse: bool,
+}
+
+impl Mb14241 {
+    pub fn new(ports: ShifterPorts) -> Self {
+        Self {
+            ports,
+            data: 0,
+            count: 0,
+            reverse: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.ports);
+    }
+
+    pub fn write_count(&mut self, value: u8) {
+        self.count = value & 0b111;
+        self.reverse = self.ports.count_selects_reverse && value & 0b1000 != 0;
+    }
+
+    pub fn write_data(&mut self, value: u8) {
+        self.data = (self.data >> 8) | ((value as u16) << 8);
+    }
+
+    pub fn result(&self) -> u8 {
+        (self.data >> (8 - self.count)) as u8
+    }
+
+    pub fn reversed_result(&self) -> u8 {
+        self.result().reverse_bits()
+    }
+
+    /// Handles a read of `port`, `None` when it isn't one of the shifter's
+    pub fn read(&self, port: u8) -> Option<u8> {
+        if port == self.ports.result {
+            Some(if self.reverse { self.reversed_result() } else { self.result() })
+        } else if Some(port) == self.ports.reversed_result {
+            Some(self.reversed_result())
+        } else {
+            None
+        }
+    }
+
+    /// Handles a write to `port`, returns false when it isn't one of the
+    /// shifter's
+    pub fn write(&mut self, port: u8, value: u8) -> bool {
+        if port == self.ports.count {
+            self.write_count(value);
+        } else if port == self.ports.data {
+            self.write_data(value);
+        } else {
+            return false;
+        }
+        true
+    }
+}
@@ -0,0 +1,80 @@
+use crate::mb14241::{Mb14241, ShifterPorts};
+
+// 0x3C written after 0xA5 leaves 0x3CA5 in the register
+const SHIFTED: [u8; 8] = [0x3C, 0x79, 0xF2, 0xE5, 0xCA, 0x94, 0x29, 0x52];
+const REVERSED: [u8; 8] = [0x3C, 0x9E, 0x4F, 0xA7, 0x53, 0x29, 0x94, 0x4A];
+
+fn loaded(ports: ShifterPorts) -> Mb14241 {
+    let mut shifter = Mb14241::new(ports);
+    shifter.write_data(0xFF);
+    shifter.write_data(0xA5);
+    shifter.write_data(0x3C);
+    shifter
+}
+
+#[test]
+fn test_every_shift_amount() {
+    let mut shifter = loaded(ShifterPorts::INVADERS);
+
+    for count in 0..8 {
+        shifter.write_count(count);
+        assert_eq!(shifter.result(), SHIFTED[count as usize], "count {}", count);
+        assert_eq!(shifter.reversed_result(), REVERSED[count as usize], "count {}", count);
+    }
+
+    // Only the low 3 bits of the count are used
+    shifter.write_count(0b1111_1010);
+    assert_eq!(shifter.result(), SHIFTED[2]);
+}
+
+#[test]
+fn test_ports() {
+    let mut shifter = Mb14241::new(ShifterPorts::INVADERS);
+
+    assert!(shifter.write(4, 0xF0));
+    assert!(shifter.write(4, 0x00));
+    assert!(shifter.write(2, 4));
+    assert!(!shifter.write(3, 0));
+
+    assert_eq!(shifter.read(3), Some(0x0F));
+    assert_eq!(shifter.read(2), None);
+    assert_eq!(shifter.read(4), None);
+}
+
+#[test]
+fn test_reversed_result_port() {
+    let shifter_ports = ShifterPorts {
+        count: 4,
+        data: 3,
+        result: 3,
+        reversed_result: Some(0),
+        count_selects_reverse: false,
+    };
+    let mut shifter = loaded(shifter_ports);
+
+    for count in 0..8 {
+        assert!(shifter.write(4, count));
+        assert_eq!(shifter.read(3), Some(SHIFTED[count as usize]));
+        assert_eq!(shifter.read(0), Some(REVERSED[count as usize]));
+    }
+}
+
+#[test]
+fn test_count_selects_reverse() {
+    let mut shifter = loaded(ShifterPorts {
+        count_selects_reverse: true,
+        ..ShifterPorts::INVADERS
+    });
+
+    for count in 0..8 {
+        shifter.write(2, count);
+        assert_eq!(shifter.read(3), Some(SHIFTED[count as usize]));
+        shifter.write(2, count | 0b1000);
+        assert_eq!(shifter.read(3), Some(REVERSED[count as usize]));
+    }
+
+    // Ignored unless the game wires it up
+    let mut shifter = loaded(ShifterPorts::INVADERS);
+    shifter.write(2, 0b1001);
+    assert_eq!(shifter.read(3), Some(SHIFTED[1]));
+}
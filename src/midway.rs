@@ -0,0 +1,644 @@
+use std::fs;
+use std::path::Path;
+
+use crate::em8080::{Em8080, IOState};
+use crate::input::{InputState, MachineInput};
+use crate::mb14241::{Mb14241, ShifterPorts};
+use crate::overlay::{Overlay, OverlayRect};
+use crate::sound::{self, AudioSink, SoundEngine, SoundPorts};
+use crate::video::{BeamPosition, RenderMode, VideoTiming};
+use crate::watchdog::Watchdog;
+
+pub mod games;
+#[cfg(test)]
+mod tests;
+
+// Midway's 8080 board (and Taito's licensed copies) is shared by a family of
+// games: an 8080, 7K of video RAM at 0x2400 scanned out as a 256 x 224 one
+// bit raster, and an MB14241 barrel shifter for drawing sprites at any pixel
+// offset. Each game differs in its ROM map, which I/O port does what, the
+// input and DIP switch layout and how the monitor is mounted. `GameDef`
+// describes those differences and `MidwayBoard` runs any of them.
+
+/// ROM chip loaded at `address`
+pub struct RomFile {
+    /// File name in the game's ROM set
+    pub name: &'static str,
+    pub address: u16,
+    pub size: usize,
+}
+
+/// What happens when the CPU reads a port
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadPort {
+    /// One of the input ports IN0-IN2: buttons and DIP switches
+    Input(usize),
+}
+
+/// What happens when the CPU writes a port
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WritePort {
+    /// Space Invaders style sound latch, see `SoundPorts`
+    Sound,
+    /// Any write kicks the watchdog, see `Watchdog`
+    Watchdog,
+    /// Hardware we don't emulate (e.g. a different sound board)
+    Ignored,
+}
+
+/// Ports other than the shifter's
+pub struct PortMap {
+    pub reads: &'static [(u8, ReadPort)],
+    pub writes: &'static [(u8, WritePort)],
+}
+
+/// A machine input wired to a bit of an input port
+pub struct InputBit {
+    pub input: MachineInput,
+    /// Input port index, IN0-IN2
+    pub port: usize,
+    pub bit: u8,
+    pub active_low: bool,
+}
+
+/// A bank of DIP switches on one of the input ports
+pub struct DipSwitch {
+    pub name: &'static str,
+    pub port: usize,
+    pub mask: u8,
+    /// Setting name and the bits it puts on the port
+    pub settings: &'static [(&'static str, u8)],
+    /// Index into `settings`
+    pub default: usize,
+}
+
+/// Space Invaders' DIP switches, which most of the Taito games copy
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvadersDips {
+    /// Lives per game, 3 to 6
+    pub lives: u8,
+    /// Score for the extra base, 1000 or 1500
+    pub bonus_at: u16,
+    /// Show the coin info in the attract mode
+    pub coin_info: bool,
+}
+
+impl Default for InvadersDips {
+    fn default() -> Self {
+        Self {
+            lives: 3,
+            bonus_at: 1500,
+            coin_info: true,
+        }
+    }
+}
+
+impl InvadersDips {
+    pub fn set_lives(&mut self, lives: u8) -> Result<(), String> {
+        if !(3..=6).contains(&lives) {
+            return Err(format!("Lives must be 3-6, got {}", lives));
+        }
+        self.lives = lives;
+        Ok(())
+    }
+
+    pub fn set_bonus_at(&mut self, bonus_at: u16) -> Result<(), String> {
+        if bonus_at != 1000 && bonus_at != 1500 {
+            return Err(format!("Bonus life must be at 1000 or 1500, got {}", bonus_at));
+        }
+        self.bonus_at = bonus_at;
+        Ok(())
+    }
+
+    /// The settings as `(switch, setting)` in the game's `DipSwitch` table
+    fn settings(&self) -> [(&'static str, String); 3] {
+        [
+            ("lives", self.lives.to_string()),
+            ("bonus", self.bonus_at.to_string()),
+            ("coin-info", if self.coin_info { "on" } else { "off" }.to_string()),
+        ]
+    }
+}
+
+/// Cabinet the board is installed in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cabinet {
+    /// One control panel shared by both players
+    Upright,
+    /// A table with a control panel at each end. The picture is turned
+    /// upside down while player 2 is up.
+    Cocktail,
+}
+
+impl Cabinet {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "upright" => Some(Cabinet::Upright),
+            "cocktail" => Some(Cabinet::Cocktail),
+            _ => None,
+        }
+    }
+}
+
+/// How the monitor is mounted in the cabinet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    /// Raster lines run left to right
+    None,
+    /// Turned 90 degrees counter clockwise, raster lines run bottom to top
+    CounterClockwise,
+}
+
+/// Colour RAM on Taito's colour boards. It shadows VRAM at another address,
+/// but only one byte per 8 x 8 cell is used: the low 3 bits pick the colour
+/// of the 8 pixels of VRAM at the same offset, for 8 raster lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorRam {
+    pub address: u16,
+    pub palette: [u32; 8],
+}
+
+impl ColorRam {
+    /// Colour of the byte at `offset` (0-31) of raster `line`
+    pub fn color(&self, memory: &[u8], line: usize, offset: usize) -> u32 {
+        // Lines 8n to 8n + 7 share the entry of line 8n
+        let cell = (line * 32 + offset) & 0x1F1F;
+        self.palette[(memory[self.address as usize + cell] & 0b111) as usize]
+    }
+}
+
+pub struct GameDef {
+    /// Short name, used to select the game
+    pub name: &'static str,
+    pub title: &'static str,
+    pub roms: &'static [RomFile],
+    pub ports: PortMap,
+    pub shifter: ShifterPorts,
+    /// Value of IN0-IN2 with nothing pressed and every DIP switch off
+    pub input_defaults: [u8; 3],
+    pub inputs: &'static [InputBit],
+    pub dips: &'static [DipSwitch],
+    pub rotation: Rotation,
+    /// Output port and bit that flip the screen of a cocktail cabinet
+    pub flip_screen: Option<(u8, u8)>,
+    /// Cellophane overlay of the upright cabinet
+    pub overlay: &'static [OverlayRect],
+    /// Replaces the overlay on colour boards
+    pub color_ram: Option<ColorRam>,
+}
+
+impl GameDef {
+    /// Window size after rotating the 256 x 224 raster
+    pub fn screen_size(&self) -> (usize, usize) {
+        match self.rotation {
+            Rotation::None => (MidwayBoard::RASTER_WIDTH, MidwayBoard::RASTER_LINES),
+            Rotation::CounterClockwise => (MidwayBoard::RASTER_LINES, MidwayBoard::RASTER_WIDTH),
+        }
+    }
+
+    /// Splits a single ROM image (all chips concatenated in ROM map order)
+    /// into its chips
+    pub fn split_rom_image(&self, image: &[u8]) -> Result<Vec<(u16, Vec<u8>)>, String> {
+        let expected: usize = self.roms.iter().map(|rom| rom.size).sum();
+        if image.len() != expected {
+            return Err(format!(
+                "{} ROM image must be {} bytes, got {}",
+                self.name,
+                expected,
+                image.len()
+            ));
+        }
+
+        let mut offset = 0;
+        Ok(self
+            .roms
+            .iter()
+            .map(|rom| {
+                let chip = image[offset..offset + rom.size].to_vec();
+                offset += rom.size;
+                (rom.address, chip)
+            })
+            .collect())
+    }
+
+    /// Loads each chip of the ROM set from `dir` by its file name
+    pub fn load_rom_dir<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<(u16, Vec<u8>)>, String> {
+        self.roms
+            .iter()
+            .map(|rom| {
+                let path = dir.as_ref().join(rom.name);
+                let chip = fs::read(&path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+                if chip.len() != rom.size {
+                    return Err(format!("{} must be {} bytes, got {}", path.display(), rom.size, chip.len()));
+                }
+                Ok((rom.address, chip))
+            })
+            .collect()
+    }
+
+    fn dip(&self, name: &str) -> Option<&DipSwitch> {
+        self.dips.iter().find(|dip| dip.name == name)
+    }
+}
+
+struct MidwayIO {
+    game: &'static GameDef,
+    shifter: Mb14241,
+    /// IN0-IN2 as read by the CPU
+    inputs: [u8; 3],
+    /// DIP switch bits, kept apart so input updates don't clobber them
+    dip_bits: [u8; 3],
+    sound: SoundPorts,
+    cabinet: Cabinet,
+    /// Set by the game while player 2 is up
+    flip_screen: bool,
+    /// Only for games that have one in their port map
+    watchdog: Option<Watchdog>,
+}
+
+impl IOState for MidwayIO {
+    fn input(&mut self, _cpu: &Em8080, port: u8) -> u8 {
+        if let Some(value) = self.shifter.read(port) {
+            return value;
+        }
+
+        match self.read_handler(port) {
+            Some(ReadPort::Input(index)) => self.inputs[index],
+            None => panic!("Cannot read port: {}", port),
+        }
+    }
+
+    fn output(&mut self, _cpu: &Em8080, port: u8, value: u8) {
+        if self.shifter.write(port, value) {
+            return;
+        }
+
+        if let Some((flip_port, bit)) = self.game.flip_screen {
+            if port == flip_port {
+                self.flip_screen = value & (1 << bit) != 0;
+            }
+        }
+
+        match self.write_handler(port) {
+            Some(WritePort::Sound) => self.sound.write(port, value),
+            Some(WritePort::Watchdog) => {
+                if let Some(watchdog) = self.watchdog.as_mut() {
+                    watchdog.kick();
+                }
+            }
+            Some(WritePort::Ignored) => {}
+            None => panic!("Cannot write to port: {}", port),
+        }
+    }
+}
+
+impl MidwayIO {
+    fn new(game: &'static GameDef, sound: Option<Box<dyn SoundEngine>>) -> Self {
+        let mut io = Self {
+            game,
+            shifter: Mb14241::new(game.shifter),
+            inputs: game.input_defaults,
+            dip_bits: [0; 3],
+            sound: SoundPorts::new(sound),
+            cabinet: Cabinet::Upright,
+            flip_screen: false,
+            watchdog: game
+                .ports
+                .writes
+                .iter()
+                .any(|&(_, handler)| handler == WritePort::Watchdog)
+                .then(|| Watchdog::new(Watchdog::MIDWAY_FRAMES)),
+        };
+
+        for dip in game.dips {
+            io.dip_bits[dip.port] |= dip.settings[dip.default].1;
+        }
+        io.update_input(InputState::default());
+        io
+    }
+
+    fn read_handler(&self, port: u8) -> Option<ReadPort> {
+        self.game.ports.reads.iter().find(|&&(p, _)| p == port).map(|&(_, handler)| handler)
+    }
+
+    fn write_handler(&self, port: u8) -> Option<WritePort> {
+        self.game.ports.writes.iter().find(|&&(p, _)| p == port).map(|&(_, handler)| handler)
+    }
+
+    fn set_dip(&mut self, name: &str, setting: &str) -> Result<(), String> {
+        let dip = self
+            .game
+            .dip(name)
+            .ok_or(format!("{} has no DIP switch {}", self.game.name, name))?;
+        let &(_, bits) = dip.settings.iter().find(|&&(s, _)| s == setting).ok_or(format!(
+            "DIP switch {} can be {}",
+            name,
+            dip.settings.iter().map(|&(s, _)| s).collect::<Vec<_>>().join(", ")
+        ))?;
+
+        self.dip_bits[dip.port] = (self.dip_bits[dip.port] & !dip.mask) | bits;
+        Ok(())
+    }
+
+    fn set_invaders_dips(&mut self, dips: &InvadersDips) -> Result<(), String> {
+        for (name, setting) in dips.settings() {
+            self.set_dip(name, &setting)?;
+        }
+        Ok(())
+    }
+
+    fn update_input(&mut self, input: InputState) {
+        self.inputs = self.game.input_defaults;
+        for (port, bits) in self.inputs.iter_mut().zip(self.dip_bits) {
+            *port |= bits;
+        }
+
+        for wiring in self.game.inputs {
+            let on = self.is_pressed(input, wiring.input) != wiring.active_low;
+            Self::set_key(&mut self.inputs[wiring.port], wiring.bit, on);
+        }
+    }
+
+    /// Games that also came as cocktail tables have one set of controls
+    /// in the upright, which player 2 shares. Games like Boot Hill have a
+    /// set for each player in any cabinet.
+    fn is_pressed(&self, input: InputState, which: MachineInput) -> bool {
+        let upright = self.cabinet == Cabinet::Upright && self.game.flip_screen.is_some();
+        let shared = match (upright, which) {
+            (true, MachineInput::P2Fire) => Some(MachineInput::P1Fire),
+            (true, MachineInput::P2Left) => Some(MachineInput::P1Left),
+            (true, MachineInput::P2Right) => Some(MachineInput::P1Right),
+            _ => None,
+        };
+
+        input.is_pressed(which) || shared.is_some_and(|p1| input.is_pressed(p1))
+    }
+
+    /// Puts the devices back in their power on state, DIPs and cabinet stay
+    fn reset(&mut self) {
+        self.shifter.reset();
+        self.sound.reset();
+        self.flip_screen = false;
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            watchdog.reset();
+        }
+    }
+
+    /// Whether the picture is upside down right now
+    fn flipped(&self) -> bool {
+        self.cabinet == Cabinet::Cocktail && self.flip_screen
+    }
+
+    fn set_key(port: &mut u8, bit: u8, on: bool) {
+        if on {
+            *port |= 1 << bit
+        } else {
+            *port &= !(1 << bit)
+        }
+    }
+}
+
+pub struct MidwayBoard {
+    game: &'static GameDef,
+    /// Kept for hard resets
+    roms: Vec<(u16, Vec<u8>)>,
+    cpu: Em8080,
+    io_state: MidwayIO,
+    window_buffer: Vec<u32>,
+    overlay: Overlay,
+    render_mode: RenderMode,
+    timing: VideoTiming,
+    instructions: u64,
+    cycles: u64,
+    frames: u64,
+    samples_rendered: u64,
+    audio_buffer: Vec<i16>,
+    audio_out: Vec<Box<dyn AudioSink>>,
+}
+
+impl MidwayBoard {
+    /// Pixels per raster line and visible lines, before rotation
+    pub const RASTER_WIDTH: usize = 256;
+    pub const RASTER_LINES: usize = 224;
+
+    /// `roms` are `(address, contents)` pairs as returned by
+    /// `GameDef::split_rom_image` or `GameDef::load_rom_dir`
+    pub fn new(game: &'static GameDef, roms: &[(u16, Vec<u8>)], sound: Option<Box<dyn SoundEngine>>) -> Self {
+        let (width, height) = game.screen_size();
+
+        let mut board = Self {
+            game,
+            roms: roms.to_vec(),
+            cpu: Em8080::new(),
+            io_state: MidwayIO::new(game, sound),
+            window_buffer: vec![0; width * height],
+            overlay: Overlay::new(width, height, game.overlay),
+            render_mode: RenderMode::Scanline,
+            timing: VideoTiming::midway(),
+            instructions: 0,
+            cycles: 0,
+            frames: 0,
+            samples_rendered: 0,
+            audio_buffer: Vec::new(),
+            audio_out: Vec::new(),
+        };
+        board.load_roms();
+        board
+    }
+
+    pub fn game(&self) -> &'static GameDef {
+        self.game
+    }
+
+    pub fn set_dip(&mut self, name: &str, setting: &str) -> Result<(), String> {
+        self.io_state.set_dip(name, setting)
+    }
+
+    /// Sets the switches of a game with Space Invaders' DIP switches
+    pub fn set_invaders_dips(&mut self, dips: &InvadersDips) -> Result<(), String> {
+        self.io_state.set_invaders_dips(dips)
+    }
+
+    pub fn set_cabinet(&mut self, cabinet: Cabinet) {
+        self.io_state.cabinet = cabinet;
+    }
+
+    /// Replaces the cellophane overlay used to colour the screen
+    pub fn set_overlay(&mut self, overlay: Overlay) {
+        self.overlay = overlay;
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+    }
+
+    /// Adds a destination for the rendered sound, e.g. the speakers or a file
+    pub fn add_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio_out.push(sink);
+    }
+
+    // Proceeds one frame of the emulator
+    pub fn step(&mut self) {
+        let visible_lines = self.timing.visible_lines;
+        let mut frame_done = false;
+
+        while !frame_done {
+            let cycles = self.cpu.emulate(&mut self.io_state);
+
+            // For monitoring/debug purposes
+            self.instructions += 1;
+            self.cycles += cycles;
+
+            for line in self.timing.advance(cycles) {
+                match self.render_mode {
+                    // Draw the line the beam just finished
+                    RenderMode::Scanline => {
+                        if line >= 1 && line <= visible_lines {
+                            self.render_line(line as usize - 1);
+                        }
+                    }
+                    // Each half of the screen is drawn once the beam has left it
+                    RenderMode::HalfFrame => {
+                        if line == visible_lines / 2 {
+                            self.screen(true);
+                        } else if line == visible_lines {
+                            self.screen(false);
+                        }
+                    }
+                }
+
+                // Mid screen and vblank interrupts
+                if let Some(rst) = self.timing.interrupt_at(line) {
+                    self.cpu.interrupt(rst);
+                }
+
+                frame_done |= line == 0;
+            }
+        }
+
+        self.frames += 1;
+
+        if let Some(watchdog) = self.io_state.watchdog.as_mut() {
+            if watchdog.frame() {
+                println!(
+                    "Watchdog not kicked for {} frames, resetting (frame {}, PC {:04X})",
+                    watchdog.timeout(),
+                    self.frames,
+                    self.cpu.pc()
+                );
+                self.soft_reset();
+            }
+        }
+
+        // Sound for the cycles emulated so far. Frames don't divide evenly
+        // into samples, so the count is worked out from the total.
+        let samples_due = self.cycles * sound::SAMPLE_RATE as u64 / self.timing.clock_hz;
+        self.audio_buffer.resize((samples_due - self.samples_rendered) as usize, 0);
+        self.samples_rendered = samples_due;
+
+        self.io_state.sound.render(&mut self.audio_buffer);
+        for sink in self.audio_out.iter_mut() {
+            sink.write(&self.audio_buffer);
+        }
+    }
+
+    pub fn update_input(&mut self, input: InputState) {
+        self.io_state.update_input(input);
+    }
+
+    pub fn clock_hz(&self) -> u64 {
+        self.timing.clock_hz
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        self.timing.frame_rate()
+    }
+
+    /// CPU cycles emulated since power on
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Where the emulated beam is, for renderers that race it
+    pub fn beam_position(&self) -> BeamPosition {
+        self.timing.beam()
+    }
+
+    pub fn screen_size(&self) -> (usize, usize) {
+        self.game.screen_size()
+    }
+
+    pub fn frame_buffer(&self) -> &[u32] {
+        &self.window_buffer
+    }
+
+    /// The reset button: restarts the program and the devices, RAM is kept
+    pub fn soft_reset(&mut self) {
+        self.cpu.reset();
+        self.io_state.reset();
+    }
+
+    /// Power cycle: RAM is cleared as well
+    pub fn hard_reset(&mut self) {
+        self.cpu = Em8080::new();
+        self.load_roms();
+        self.io_state.reset();
+    }
+
+    fn load_roms(&mut self) {
+        for (address, rom) in &self.roms {
+            self.cpu.load_rom(rom, *address as usize);
+        }
+    }
+
+    fn screen(&mut self, top_half: bool) {
+        let lines = if top_half { 0..0x70 } else { 0x70..0xE0 };
+
+        // Iterate half the screen
+        for line in lines {
+            self.render_line(line);
+        }
+    }
+
+    /// Converts one raster line (32 bytes of VRAM from 0x2400) to pixels
+    fn render_line(&mut self, line: usize) {
+        let start_memory = 0x2400 + line * 32;
+        let (width, height) = self.game.screen_size();
+        let flipped = self.io_state.flipped();
+
+        for offset in 0..32 {
+            let byte = self.cpu.memory[start_memory + offset];
+            let cell_color = self
+                .game
+                .color_ram
+                .map(|color_ram| color_ram.color(&self.cpu.memory, line, offset));
+
+            for bit in 0..8 {
+                let pixel = 8 * offset + bit;
+                let (x, y) = match self.game.rotation {
+                    Rotation::None => (pixel, line),
+                    // Raster lines become columns, drawn bottom up
+                    Rotation::CounterClockwise => (line, Self::RASTER_WIDTH - 1 - pixel),
+                };
+                // Turned around to face player 2
+                let (x, y) = if flipped { (width - 1 - x, height - 1 - y) } else { (x, y) };
+
+                let color: u32 = if byte & (1 << bit) == 0 {
+                    0x00_00_00_00
+                } else {
+                    cell_color.unwrap_or_else(|| self.overlay.color(x, y))
+                };
+
+                self.window_buffer[x + y * width] = color;
+            }
+        }
+    }
+}
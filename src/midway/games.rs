@@ -0,0 +1,266 @@
+use crate::input::MachineInput;
+use crate::mb14241::ShifterPorts;
+use crate::midway::{ColorRam, DipSwitch, GameDef, InputBit, PortMap, ReadPort, RomFile, Rotation, WritePort};
+use crate::overlay::INVADERS_UPRIGHT;
+
+// Port maps, input layouts and DIP switches follow MAME's mw8080bw driver.
+
+pub const GAMES: &[&GameDef] = &[&INVADERS, &INVADPT2, &LRESCUE, &BALLBOMB, &BOOTHILL];
+
+pub fn find(name: &str) -> Option<&'static GameDef> {
+    GAMES.iter().copied().find(|game| game.name == name)
+}
+
+const fn rom(name: &'static str, address: u16) -> RomFile {
+    RomFile { name, address, size: 0x800 }
+}
+
+const fn input(input: MachineInput, port: usize, bit: u8) -> InputBit {
+    InputBit { input, port, bit, active_low: false }
+}
+
+const fn input_low(input: MachineInput, port: usize, bit: u8) -> InputBit {
+    InputBit { input, port, bit, active_low: true }
+}
+
+/// Space Invaders and the Taito games built on its board: IN0-IN2 on ports
+/// 0-2, sound latches on 3 and 5, watchdog on 6. Port 5 bit 5 also flips
+/// the screen of cocktail cabinets. The shifter is on `ShifterPorts::INVADERS`.
+const INVADERS_PORTS: PortMap = PortMap {
+    reads: &[
+        (0, ReadPort::Input(0)),
+        (1, ReadPort::Input(1)),
+        (2, ReadPort::Input(2)),
+    ],
+    writes: &[
+        (3, WritePort::Sound),
+        (5, WritePort::Sound),
+        (6, WritePort::Watchdog),
+    ],
+};
+
+/// IN1 bit 3 is always 1, IN0 bits 4-6 are pulled up
+const INVADERS_INPUT_DEFAULTS: [u8; 3] = [0b0111_0000, 0b0000_1000, 0];
+
+const INVADERS_INPUTS: &[InputBit] = &[
+    input(MachineInput::Coin, 1, 0),
+    input(MachineInput::P2Start, 1, 1),
+    input(MachineInput::P1Start, 1, 2),
+    input(MachineInput::P1Fire, 1, 4),
+    input(MachineInput::P1Left, 1, 5),
+    input(MachineInput::P1Right, 1, 6),
+    input(MachineInput::Tilt, 2, 2),
+    input(MachineInput::P2Fire, 2, 4),
+    input(MachineInput::P2Left, 2, 5),
+    input(MachineInput::P2Right, 2, 6),
+];
+
+const LIVES_3_TO_6: DipSwitch = DipSwitch {
+    name: "lives",
+    port: 2,
+    mask: 0b0000_0011,
+    settings: &[("3", 0), ("4", 1), ("5", 2), ("6", 3)],
+    default: 0,
+};
+
+const COIN_INFO: DipSwitch = DipSwitch {
+    name: "coin-info",
+    port: 2,
+    mask: 0b1000_0000,
+    settings: &[("on", 0), ("off", 0b1000_0000)],
+    default: 0,
+};
+
+/// Taito's colour RAM at 0xC400, mirroring VRAM at 0x2400. The colour PROM
+/// maps bit 0 to red, bit 1 to blue and bit 2 to green.
+const TAITO_COLOR_RAM: ColorRam = ColorRam {
+    address: 0xC400,
+    palette: [
+        0x00_00_00_00,
+        0x00_ff_00_00,
+        0x00_00_00_ff,
+        0x00_ff_00_ff,
+        0x00_00_ff_00,
+        0x00_ff_ff_00,
+        0x00_00_ff_ff,
+        0x00_ff_ff_ff,
+    ],
+};
+
+pub const INVADERS: GameDef = GameDef {
+    name: "invaders",
+    title: "Space Invaders",
+    roms: &[
+        rom("invaders.h", 0x0000),
+        rom("invaders.g", 0x0800),
+        rom("invaders.f", 0x1000),
+        rom("invaders.e", 0x1800),
+    ],
+    ports: INVADERS_PORTS,
+    shifter: ShifterPorts::INVADERS,
+    input_defaults: INVADERS_INPUT_DEFAULTS,
+    inputs: INVADERS_INPUTS,
+    dips: &[
+        LIVES_3_TO_6,
+        DipSwitch {
+            name: "bonus",
+            port: 2,
+            mask: 0b0000_1000,
+            settings: &[("1500", 0), ("1000", 0b0000_1000)],
+            default: 0,
+        },
+        COIN_INFO,
+    ],
+    rotation: Rotation::CounterClockwise,
+    flip_screen: Some((5, 5)),
+    overlay: INVADERS_UPRIGHT,
+    color_ram: None,
+};
+
+/// Space Invaders Part II (Taito)
+pub const INVADPT2: GameDef = GameDef {
+    name: "invadpt2",
+    title: "Space Invaders Part II",
+    roms: &[
+        rom("pv01", 0x0000),
+        rom("pv02", 0x0800),
+        rom("pv03", 0x1000),
+        rom("pv04", 0x1800),
+        rom("pv05", 0x4000),
+    ],
+    ports: INVADERS_PORTS,
+    shifter: ShifterPorts::INVADERS,
+    input_defaults: INVADERS_INPUT_DEFAULTS,
+    inputs: INVADERS_INPUTS,
+    dips: &[
+        DipSwitch {
+            name: "lives",
+            port: 2,
+            mask: 0b0000_0001,
+            settings: &[("3", 0), ("4", 1)],
+            default: 0,
+        },
+        DipSwitch {
+            name: "preset-mode",
+            port: 2,
+            mask: 0b0000_1000,
+            settings: &[("off", 0), ("on", 0b0000_1000)],
+            default: 0,
+        },
+        COIN_INFO,
+    ],
+    rotation: Rotation::CounterClockwise,
+    flip_screen: Some((5, 5)),
+    overlay: &[],
+    color_ram: Some(TAITO_COLOR_RAM),
+};
+
+/// Lunar Rescue (Taito)
+pub const LRESCUE: GameDef = GameDef {
+    name: "lrescue",
+    title: "Lunar Rescue",
+    roms: &[
+        rom("lrescue.1", 0x0000),
+        rom("lrescue.2", 0x0800),
+        rom("lrescue.3", 0x1000),
+        rom("lrescue.4", 0x1800),
+        rom("lrescue.5", 0x4000),
+        rom("lrescue.6", 0x4800),
+    ],
+    ports: INVADERS_PORTS,
+    shifter: ShifterPorts::INVADERS,
+    input_defaults: INVADERS_INPUT_DEFAULTS,
+    inputs: INVADERS_INPUTS,
+    dips: &[LIVES_3_TO_6, COIN_INFO],
+    rotation: Rotation::CounterClockwise,
+    flip_screen: Some((5, 5)),
+    overlay: &[],
+    color_ram: Some(TAITO_COLOR_RAM),
+};
+
+/// Balloon Bomber (Taito)
+pub const BALLBOMB: GameDef = GameDef {
+    name: "ballbomb",
+    title: "Balloon Bomber",
+    roms: &[
+        rom("tn01", 0x0000),
+        rom("tn02", 0x0800),
+        rom("tn03", 0x1000),
+        rom("tn04", 0x1800),
+        rom("tn05-1", 0x4000),
+    ],
+    ports: INVADERS_PORTS,
+    shifter: ShifterPorts::INVADERS,
+    input_defaults: INVADERS_INPUT_DEFAULTS,
+    inputs: INVADERS_INPUTS,
+    dips: &[
+        LIVES_3_TO_6,
+        DipSwitch {
+            name: "bonus",
+            port: 2,
+            mask: 0b0000_1000,
+            settings: &[("1000", 0), ("2000", 0b0000_1000)],
+            default: 0,
+        },
+        COIN_INFO,
+    ],
+    rotation: Rotation::CounterClockwise,
+    flip_screen: Some((5, 5)),
+    overlay: &[],
+    color_ram: Some(TAITO_COLOR_RAM),
+};
+
+/// Boot Hill: a gunfighter each, side by side on an unrotated monitor.
+/// The shifter's count port also selects a bit reversed result.
+pub const BOOTHILL: GameDef = GameDef {
+    name: "boothill",
+    title: "Boot Hill",
+    roms: &[
+        rom("romh.cpu", 0x0000),
+        rom("romg.cpu", 0x0800),
+        rom("romf.cpu", 0x1000),
+        rom("rome.cpu", 0x1800),
+    ],
+    ports: PortMap {
+        reads: &[
+            (0, ReadPort::Input(0)),
+            (1, ReadPort::Input(1)),
+            (2, ReadPort::Input(2)),
+        ],
+        writes: &[
+            (3, WritePort::Ignored),
+            (4, WritePort::Watchdog),
+            (5, WritePort::Ignored),
+            (6, WritePort::Ignored),
+        ],
+    },
+    shifter: ShifterPorts::BOOTHILL,
+    // Bits 4-6 of IN0 and IN1 are the angle of the gun arm, which isn't
+    // emulated and stays at 0
+    input_defaults: [0, 0, 0],
+    inputs: &[
+        input_low(MachineInput::P2Up, 0, 0),
+        input_low(MachineInput::P2Down, 0, 1),
+        input_low(MachineInput::P2Left, 0, 2),
+        input_low(MachineInput::P2Right, 0, 3),
+        input_low(MachineInput::P2Fire, 0, 7),
+        input_low(MachineInput::P1Up, 1, 0),
+        input_low(MachineInput::P1Down, 1, 1),
+        input_low(MachineInput::P1Left, 1, 2),
+        input_low(MachineInput::P1Right, 1, 3),
+        input_low(MachineInput::P1Fire, 1, 7),
+        input(MachineInput::Coin, 2, 4),
+        input(MachineInput::P1Start, 2, 5),
+    ],
+    dips: &[DipSwitch {
+        name: "time",
+        port: 2,
+        mask: 0b0000_1100,
+        settings: &[("64", 0), ("74", 0b0000_0100), ("84", 0b0000_1000), ("94", 0b0000_1100)],
+        default: 0,
+    }],
+    rotation: Rotation::None,
+    flip_screen: None,
+    overlay: &[],
+    color_ram: None,
+};
@@ -0,0 +1,244 @@
+use crate::input::{InputState, MachineInput};
+use crate::midway::games::{self, BOOTHILL, INVADERS, LRESCUE};
+use crate::midway::{Cabinet, InvadersDips, MidwayBoard, MidwayIO, ReadPort, Rotation};
+use crate::overlay::WHITE;
+use crate::watchdog::Watchdog;
+
+#[test]
+fn test_games_are_consistent() {
+    for game in games::GAMES {
+        assert_eq!(games::find(game.name).map(|g| g.name), Some(game.name));
+
+        for dip in game.dips {
+            assert!(dip.default < dip.settings.len(), "{} {}", game.name, dip.name);
+            for &(setting, bits) in dip.settings {
+                assert_eq!(bits & !dip.mask, 0, "{} {}={}", game.name, dip.name, setting);
+            }
+        }
+
+        let shifter = game.shifter;
+        for (port, _) in game.ports.reads {
+            assert!(*port != shifter.result && Some(*port) != shifter.reversed_result, "{} port {}", game.name, port);
+        }
+        for (port, _) in game.ports.writes {
+            assert!(*port != shifter.count && *port != shifter.data, "{} port {}", game.name, port);
+        }
+
+        for input in game.inputs {
+            assert!(game.ports.reads.contains(&(input.port as u8, ReadPort::Input(input.port))));
+        }
+    }
+    assert!(games::find("pacman").is_none());
+}
+
+#[test]
+fn test_split_rom_image() {
+    let image: Vec<u8> = (0..6 * 0x800).map(|i| (i / 0x800) as u8).collect();
+    let roms = LRESCUE.split_rom_image(&image).unwrap();
+
+    let layout: Vec<(u16, u8)> = roms.iter().map(|(address, rom)| (*address, rom[0])).collect();
+    assert_eq!(layout, vec![(0x0000, 0), (0x0800, 1), (0x1000, 2), (0x1800, 3), (0x4000, 4), (0x4800, 5)]);
+
+    assert!(LRESCUE.split_rom_image(&image[..0x2000]).is_err());
+}
+
+#[test]
+fn test_invaders_ports() {
+    let mut io = MidwayIO::new(&INVADERS, None);
+    assert_eq!(io.inputs, [0b0111_0000, 0b0000_1000, 0]);
+
+    let mut dips = InvadersDips::default();
+    dips.set_lives(5).unwrap();
+    dips.set_bonus_at(1000).unwrap();
+    dips.coin_info = false;
+    assert!(dips.set_lives(7).is_err());
+    assert!(dips.set_bonus_at(2000).is_err());
+    io.set_invaders_dips(&dips).unwrap();
+    assert!(io.set_dip("lives", "7").is_err());
+    assert!(io.set_dip("difficulty", "hard").is_err());
+
+    let mut input = InputState::default();
+    input.set(MachineInput::Coin, true);
+    input.set(MachineInput::P2Fire, true);
+    io.update_input(input);
+
+    assert_eq!(io.inputs[1], 0b0000_1001);
+    assert_eq!(io.inputs[2], 0b1001_1010);
+
+    // DIP switches survive input updates
+    io.update_input(InputState::default());
+    assert_eq!(io.inputs[2], 0b1000_1010);
+}
+
+#[test]
+fn test_rotated_render() {
+    // LXI SP,0x2400; MVI A,1; STA 0x2400; JMP $
+    let mut image = vec![0; 0x2000];
+    image[..11].copy_from_slice(&[0x31, 0x00, 0x24, 0x3E, 0x01, 0x32, 0x00, 0x24, 0xC3, 0x08, 0x00]);
+
+    let mut board = MidwayBoard::new(&INVADERS, &INVADERS.split_rom_image(&image).unwrap(), None);
+    board.step();
+
+    assert_eq!(INVADERS.rotation, Rotation::CounterClockwise);
+    assert_eq!(board.screen_size(), (224, 256));
+
+    // First pixel of the first raster line is the bottom left corner
+    let lit: Vec<usize> = (0..224 * 256).filter(|&i| board.frame_buffer()[i] != 0).collect();
+    assert_eq!(lit, vec![255 * 224]);
+}
+
+#[test]
+fn test_boothill_ports() {
+    let mut io = MidwayIO::new(&BOOTHILL, None);
+    // Active low joysticks and triggers read 1 when left alone
+    assert_eq!(io.inputs, [0b1000_1111, 0b1000_1111, 0]);
+
+    io.set_dip("time", "84").unwrap();
+    let mut input = InputState::default();
+    input.set(MachineInput::P1Up, true);
+    input.set(MachineInput::P1Fire, true);
+    input.set(MachineInput::P2Right, true);
+    input.set(MachineInput::Coin, true);
+    io.update_input(input);
+
+    // Each player has their own controls, even upright
+    assert_eq!(io.inputs, [0b1000_0111, 0b0000_1110, 0b0001_1000]);
+}
+
+#[test]
+fn test_unrotated_render_and_reversed_shifter() {
+    // LXI SP,0x2400; MVI A,1; OUT 2; MVI A,8; OUT 1; IN 3; STA 0x2400;
+    // STA 0x2421; JMP $
+    let mut image = vec![0; 0x2000];
+    image[..23].copy_from_slice(&[
+        0x31, 0x00, 0x24, 0x3E, 0x01, 0xD3, 0x02, 0x3E, 0x08, 0xD3, 0x01, 0xDB, 0x03, 0x32, 0x00, 0x24, 0x32, 0x21,
+        0x24, 0xC3, 0x13, 0x00, 0x00,
+    ]);
+
+    let mut board = MidwayBoard::new(&BOOTHILL, &BOOTHILL.split_rom_image(&image).unwrap(), None);
+    board.step();
+
+    assert_eq!(BOOTHILL.rotation, Rotation::None);
+    assert_eq!(board.screen_size(), (256, 224));
+
+    // The shifter's 0x01 reads back as 0x80, the last pixel of byte 0 of
+    // line 0 and of byte 1 of line 1
+    let lit: Vec<(usize, usize)> = (0..256 * 224)
+        .filter(|&i| board.frame_buffer()[i] != 0)
+        .map(|i| (i % 256, i / 256))
+        .collect();
+    assert_eq!(lit, vec![(7, 0), (15, 1)]);
+}
+
+#[test]
+fn test_color_ram_cells() {
+    // LXI SP,0x2400; MVI A,0xFF; STA 0x2521; MVI A,5; STA 0xC501; JMP $
+    let mut image = vec![0; 6 * 0x800];
+    image[..16].copy_from_slice(&[
+        0x31, 0x00, 0x24, 0x3E, 0xFF, 0x32, 0x21, 0x25, 0x3E, 0x05, 0x32, 0x01, 0xC5, 0xC3, 0x0D, 0x00,
+    ]);
+
+    let mut board = MidwayBoard::new(&LRESCUE, &LRESCUE.split_rom_image(&image).unwrap(), None);
+    board.step();
+
+    // Byte 1 of line 9 takes its colour from the cell entry of line 8
+    let yellow = LRESCUE.color_ram.unwrap().palette[5];
+    assert_ne!(yellow, WHITE);
+
+    let lit: Vec<(usize, u32)> = board
+        .frame_buffer()
+        .iter()
+        .enumerate()
+        .filter(|&(_, &color)| color != 0)
+        .map(|(i, &color)| (i, color))
+        .collect();
+    let expected: Vec<(usize, u32)> = (240..248).map(|y| (9 + y * 224, yellow)).collect();
+    assert_eq!(lit, expected);
+}
+
+#[test]
+fn test_upright_shares_controls() {
+    let mut io = MidwayIO::new(&INVADERS, None);
+    let mut input = InputState::default();
+    input.set(MachineInput::P1Fire, true);
+
+    io.update_input(input);
+    assert_eq!(io.inputs[1] & 0b0001_0000, 0b0001_0000);
+    assert_eq!(io.inputs[2] & 0b0001_0000, 0b0001_0000);
+
+    io.cabinet = Cabinet::Cocktail;
+    io.update_input(input);
+    assert_eq!(io.inputs[1] & 0b0001_0000, 0b0001_0000);
+    assert_eq!(io.inputs[2] & 0b0001_0000, 0);
+}
+
+#[test]
+fn test_cocktail_flip() {
+    // LXI SP,0x2400; MVI A,0x20; OUT 5; MVI A,1; STA 0x2400; JMP $
+    let mut image = vec![0; 0x2000];
+    image[..15].copy_from_slice(&[
+        0x31, 0x00, 0x24, 0x3E, 0x20, 0xD3, 0x05, 0x3E, 0x01, 0x32, 0x00, 0x24, 0xC3, 0x0C, 0x00,
+    ]);
+    let roms = INVADERS.split_rom_image(&image).unwrap();
+    let lit = |board: &MidwayBoard| -> Vec<usize> {
+        (0..224 * 256).filter(|&i| board.frame_buffer()[i] != 0).collect()
+    };
+
+    // Upright cabinets ignore the flip bit
+    let mut board = MidwayBoard::new(&INVADERS, &roms, None);
+    board.step();
+    assert_eq!(lit(&board), vec![255 * 224]);
+
+    let mut board = MidwayBoard::new(&INVADERS, &roms, None);
+    board.set_cabinet(Cabinet::Cocktail);
+    board.step();
+    assert_eq!(lit(&board), vec![223]);
+}
+
+#[test]
+fn test_watchdog_resets_when_not_kicked() {
+    // LXI SP,0x2400; LDA 0x2000; INR A; STA 0x2000; then either JMP $ or
+    // OUT 6; JMP $-2
+    let boot = [0x31, 0x00, 0x24, 0x3A, 0x00, 0x20, 0x3C, 0x32, 0x00, 0x20];
+    let run = |main_loop: &[u8]| {
+        let mut image = vec![0; 0x2000];
+        image[..10].copy_from_slice(&boot);
+        image[10..10 + main_loop.len()].copy_from_slice(main_loop);
+
+        let mut board = MidwayBoard::new(&INVADERS, &INVADERS.split_rom_image(&image).unwrap(), None);
+        for _ in 0..Watchdog::MIDWAY_FRAMES + 1 {
+            board.step();
+        }
+        board.cpu.memory[0x2000]
+    };
+
+    // Booted twice, with RAM kept across the reset
+    assert_eq!(run(&[0xC3, 0x0A, 0x00]), 2);
+    assert_eq!(run(&[0xD3, 0x06, 0xC3, 0x0A, 0x00]), 1);
+}
+
+#[test]
+fn test_soft_and_hard_reset() {
+    // LXI SP,0x2400; LDA 0x2000; INR A; STA 0x2000; MVI A,0x20; OUT 5; JMP $
+    let mut image = vec![0; 0x2000];
+    image[..17].copy_from_slice(&[
+        0x31, 0x00, 0x24, 0x3A, 0x00, 0x20, 0x3C, 0x32, 0x00, 0x20, 0x3E, 0x20, 0xD3, 0x05, 0xC3, 0x0E, 0x00,
+    ]);
+
+    let mut board = MidwayBoard::new(&INVADERS, &INVADERS.split_rom_image(&image).unwrap(), None);
+    board.set_cabinet(Cabinet::Cocktail);
+    board.step();
+    assert!(board.io_state.flipped());
+
+    // The program runs again with RAM kept and devices cleared
+    board.soft_reset();
+    assert!(!board.io_state.flipped());
+    board.step();
+    assert_eq!(board.cpu.memory[0x2000], 2);
+
+    board.hard_reset();
+    assert_eq!(board.cpu.memory[0x2000], 0);
+    assert_eq!(board.cpu.memory[0x0003], 0x3A);
+    board.step();
+    assert_eq!(board.cpu.memory[0x2000], 1);
+}
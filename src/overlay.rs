@@ -0,0 +1,113 @@
+use std::fs;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests;
+
+// Arcade cabinets used strips of coloured cellophane on the monitor glass to
+// colour an otherwise monochrome picture. An overlay is a table of rectangles
+// in (rotated) screen coordinates; later entries are laid on top of earlier
+// ones, anything not covered stays white.
+
+pub const WHITE: u32 = 0x00_ff_ff_ff;
+pub const RED: u32 = 0x00_ff_20_20;
+pub const GREEN: u32 = 0x00_20_ff_20;
+
+/// A tinted rectangle, `x1`/`y1` exclusive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OverlayRect {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+    pub color: u32,
+}
+
+const fn rect(x0: usize, y0: usize, x1: usize, y1: usize, color: u32) -> OverlayRect {
+    OverlayRect { x0, y0, x1, y1, color }
+}
+
+/// The upright Space Invaders cabinet: red band over the UFO row, green over
+/// the shields and player, and green behind the reserve bases at the bottom.
+pub const INVADERS_UPRIGHT: &[OverlayRect] = &[
+    rect(0, 32, 224, 64, RED),
+    rect(0, 184, 224, 240, GREEN),
+    rect(16, 240, 134, 256, GREEN),
+];
+
+/// Per-pixel colour lookup built from an overlay table
+pub struct Overlay {
+    width: usize,
+    colors: Vec<u32>,
+}
+
+impl Overlay {
+    pub fn new(width: usize, height: usize, table: &[OverlayRect]) -> Self {
+        let mut colors = vec![WHITE; width * height];
+
+        for r in table {
+            for y in r.y0..r.y1.min(height) {
+                for x in r.x0..r.x1.min(width) {
+                    colors[x + y * width] = r.color;
+                }
+            }
+        }
+
+        Self { width, colors }
+    }
+
+    pub fn monochrome(width: usize, height: usize) -> Self {
+        Self::new(width, height, &[])
+    }
+
+    /// Looks up a preset by name. `upright` is the cellophane of the game
+    /// being run.
+    pub fn preset(name: &str, width: usize, height: usize, upright: &[OverlayRect]) -> Option<Self> {
+        match name {
+            "mono" | "monochrome" => Some(Self::monochrome(width, height)),
+            "upright" => Some(Self::new(width, height, upright)),
+            "invaders" => Some(Self::new(width, height, INVADERS_UPRIGHT)),
+            _ => None,
+        }
+    }
+
+    /// Reads an overlay table from a text file with one `x0 y0 x1 y1 RRGGBB`
+    /// rectangle per line. Blank lines and lines starting with `#` are skipped.
+    pub fn load<P: AsRef<Path>>(path: P, width: usize, height: usize) -> Result<Self, String> {
+        let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        Ok(Self::new(width, height, &parse_table(&text)?))
+    }
+
+    /// Colour of a lit pixel at `x`, `y`
+    pub fn color(&self, x: usize, y: usize) -> u32 {
+        self.colors[x + y * self.width]
+    }
+}
+
+fn parse_table(text: &str) -> Result<Vec<OverlayRect>, String> {
+    let mut table = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Line {}: expected `x0 y0 x1 y1 RRGGBB`", number + 1));
+        }
+
+        let coordinate = |i: usize| {
+            fields[i]
+                .parse::<usize>()
+                .map_err(|_| format!("Line {}: invalid coordinate {}", number + 1, fields[i]))
+        };
+        let color = u32::from_str_radix(fields[4].trim_start_matches('#'), 16)
+            .map_err(|_| format!("Line {}: invalid colour {}", number + 1, fields[4]))?;
+
+        table.push(rect(coordinate(0)?, coordinate(1)?, coordinate(2)?, coordinate(3)?, color & 0x00ff_ffff));
+    }
+
+    Ok(table)
+}
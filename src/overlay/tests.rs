@@ -0,0 +1,21 @@
+use crate::overlay::{parse_table, rect, Overlay, GREEN, INVADERS_UPRIGHT, RED, WHITE};
+
+#[test]
+fn test_invaders_upright() {
+    let overlay = Overlay::new(224, 256, INVADERS_UPRIGHT);
+    assert_eq!(overlay.color(100, 10), WHITE);
+    assert_eq!(overlay.color(100, 40), RED);
+    assert_eq!(overlay.color(0, 200), GREEN);
+    assert_eq!(overlay.color(10, 250), WHITE);
+    assert_eq!(overlay.color(20, 250), GREEN);
+    assert_eq!(overlay.color(200, 250), WHITE);
+}
+
+#[test]
+fn test_parse_table() {
+    let table = parse_table("# UFO band\n0 32 224 64 ff2020\n\n16 240 134 256 #20FF20\n").unwrap();
+    assert_eq!(table, vec![rect(0, 32, 224, 64, RED), rect(16, 240, 134, 256, GREEN)]);
+
+    assert!(parse_table("0 32 224 ff2020").is_err());
+    assert!(parse_table("0 32 224 64 zz").is_err());
+}
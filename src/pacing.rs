@@ -0,0 +1,141 @@
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests;
+
+/// How fast emulated time runs relative to the wall clock
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    Normal,
+    /// Runs `FAST_FORWARD` times faster than real time
+    FastForward,
+    /// As fast as the host allows, for benchmarking
+    Uncapped,
+}
+
+pub const FAST_FORWARD: f64 = 4.0;
+
+/// Keeps emulation in step with the wall clock. Deadlines are derived from
+/// the CPU cycle counter rather than summed per-frame sleeps, so time spent
+/// emulating and rendering is accounted for and errors don't accumulate.
+pub struct Pacer {
+    clock_hz: u64,
+    speed: Speed,
+    paused: bool,
+    frames_to_advance: u32,
+
+    /// Wall clock time and cycle count the current deadlines are measured from
+    origin: Instant,
+    origin_cycles: u64,
+}
+
+impl Pacer {
+    /// If the host falls further behind than this (window dragged, debugger,
+    /// slow machine) the lost time is dropped instead of being caught up
+    /// with a burst of frames.
+    const MAX_LAG: Duration = Duration::from_millis(100);
+
+    /// Sleeps are cut short by this much and the rest is spent yielding,
+    /// as OS sleeps often overshoot by a millisecond or so.
+    const SPIN: Duration = Duration::from_millis(1);
+
+    pub fn new(clock_hz: u64) -> Self {
+        Self {
+            clock_hz,
+            speed: Speed::Normal,
+            paused: false,
+            frames_to_advance: 0,
+            origin: Instant::now(),
+            origin_cycles: 0,
+        }
+    }
+
+    pub fn set_speed(&mut self, speed: Speed, cycles: u64) {
+        if speed != self.speed {
+            self.speed = speed;
+            self.resync(cycles, Instant::now());
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool, cycles: u64) {
+        if self.paused && !paused {
+            // Don't try to catch up on the time spent paused
+            self.resync(cycles, Instant::now());
+        }
+        self.paused = paused;
+        self.frames_to_advance = 0;
+    }
+
+    /// Runs a single frame while paused
+    pub fn advance_frame(&mut self) {
+        if self.paused {
+            self.frames_to_advance += 1;
+        }
+    }
+
+    /// Whether the front end should emulate a frame now
+    pub fn should_run_frame(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+        if self.frames_to_advance > 0 {
+            self.frames_to_advance -= 1;
+            return true;
+        }
+        false
+    }
+
+    /// Wall clock time at which the emulation should have reached `cycles`,
+    /// `None` when uncapped
+    pub fn deadline(&self, cycles: u64) -> Option<Instant> {
+        let multiplier = match self.speed {
+            Speed::Normal => 1.0,
+            Speed::FastForward => FAST_FORWARD,
+            Speed::Uncapped => return None,
+        };
+
+        let emulated = cycles.saturating_sub(self.origin_cycles) as f64 / self.clock_hz as f64;
+        Some(self.origin + Duration::from_secs_f64(emulated / multiplier))
+    }
+
+    /// How long to wait at `now` for the emulation to be back in real time
+    /// after running up to `cycles`. Resynchronises when too far behind.
+    pub fn delay(&mut self, cycles: u64, now: Instant) -> Duration {
+        let deadline = match self.deadline(cycles) {
+            Some(deadline) => deadline,
+            None => return Duration::ZERO,
+        };
+
+        if now > deadline + Self::MAX_LAG {
+            self.resync(cycles, now);
+            return Duration::ZERO;
+        }
+
+        deadline.saturating_duration_since(now)
+    }
+
+    /// Blocks until the wall clock has caught up with `cycles`
+    pub fn wait(&mut self, cycles: u64) {
+        let delay = self.delay(cycles, Instant::now());
+        if delay.is_zero() {
+            return;
+        }
+
+        let deadline = Instant::now() + delay;
+        if delay > Self::SPIN {
+            std::thread::sleep(delay - Self::SPIN);
+        }
+        while Instant::now() < deadline {
+            std::thread::yield_now();
+        }
+    }
+
+    fn resync(&mut self, cycles: u64, now: Instant) {
+        self.origin = now;
+        self.origin_cycles = cycles;
+    }
+}
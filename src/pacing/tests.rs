@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+use crate::pacing::{Pacer, Speed, FAST_FORWARD};
+
+const CLOCK: u64 = 2_000_000;
+
+#[test]
+fn test_deadline_follows_cycles() {
+    let pacer = Pacer::new(CLOCK);
+    let start = pacer.deadline(0).unwrap();
+
+    // One emulated second per CLOCK cycles, regardless of how it was reached
+    assert_eq!(pacer.deadline(CLOCK).unwrap() - start, Duration::from_secs(1));
+    assert_eq!(pacer.deadline(CLOCK / 4).unwrap() - start, Duration::from_millis(250));
+}
+
+#[test]
+fn test_delay_compensates_for_time_spent() {
+    let mut pacer = Pacer::new(CLOCK);
+    let start = pacer.deadline(0).unwrap();
+
+    // A 1/60 s frame that took 5 ms to emulate only needs the remainder
+    let frame = CLOCK / 60;
+    let delay = pacer.delay(frame, start + Duration::from_millis(5));
+    assert!(delay > Duration::from_millis(11) && delay < Duration::from_millis(12));
+
+    // Slightly late frames don't wait at all but keep the schedule
+    assert_eq!(pacer.delay(2 * frame, start + Duration::from_millis(40)), Duration::ZERO);
+    assert_eq!(pacer.deadline(0), Some(start));
+}
+
+#[test]
+fn test_resync_when_far_behind() {
+    let mut pacer = Pacer::new(CLOCK);
+    let start = pacer.deadline(0).unwrap();
+
+    let stalled = start + Duration::from_secs(2);
+    assert_eq!(pacer.delay(CLOCK / 60, stalled), Duration::ZERO);
+
+    // The schedule restarts from the stall instead of racing to catch up
+    assert_eq!(pacer.deadline(CLOCK / 60), Some(stalled));
+    assert_eq!(pacer.delay(CLOCK / 60 + CLOCK / 100, stalled), Duration::from_millis(10));
+}
+
+#[test]
+fn test_fast_forward_and_uncapped() {
+    let mut pacer = Pacer::new(CLOCK);
+
+    pacer.set_speed(Speed::FastForward, 0);
+    let start = pacer.deadline(0).unwrap();
+    assert_eq!(
+        pacer.deadline(CLOCK).unwrap() - start,
+        Duration::from_secs_f64(1.0 / FAST_FORWARD)
+    );
+
+    pacer.set_speed(Speed::Uncapped, 0);
+    assert_eq!(pacer.deadline(CLOCK), None);
+    assert_eq!(pacer.delay(CLOCK, Instant::now()), Duration::ZERO);
+}
+
+#[test]
+fn test_pause_and_frame_advance() {
+    let mut pacer = Pacer::new(CLOCK);
+    assert!(pacer.should_run_frame());
+
+    pacer.set_paused(true, 0);
+    assert!(!pacer.should_run_frame());
+
+    pacer.advance_frame();
+    pacer.advance_frame();
+    assert!(pacer.should_run_frame());
+    assert!(pacer.should_run_frame());
+    assert!(!pacer.should_run_frame());
+
+    pacer.set_paused(false, 1000);
+    assert!(pacer.should_run_frame());
+
+    // Frame advance does nothing while running
+    pacer.advance_frame();
+    pacer.set_paused(true, 1000);
+    assert!(!pacer.should_run_frame());
+}
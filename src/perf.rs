@@ -0,0 +1,195 @@
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests;
+
+// On-screen statistics for checking the emulator keeps up: host frame rate,
+// emulated clock speed, instructions per frame and speed relative to the
+// real machine. Drawn with a built in 3 x 5 pixel font so it works the same
+// on every game and needs no font files.
+
+/// Counters sampled from the machine once per frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub frames: u64,
+    pub cycles: u64,
+    pub instructions: u64,
+}
+
+/// Rates worked out between two samples
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub host_fps: f64,
+    pub emulated_mhz: f64,
+    pub instructions_per_frame: u64,
+    /// 100 when running exactly as fast as the real machine
+    pub percent_of_real_time: f64,
+}
+
+pub struct PerfOverlay {
+    clock_hz: u64,
+    visible: bool,
+    /// Time and counters at the start of the current interval
+    last_sample: Option<(Instant, Counters)>,
+    /// Frames presented on the host since the last sample
+    host_frames: u64,
+    stats: Stats,
+}
+
+impl PerfOverlay {
+    /// Rates are averaged over this long so the numbers stay readable
+    const INTERVAL: Duration = Duration::from_millis(500);
+
+    const TEXT_COLOR: u32 = 0x00_ff_ff_00;
+    const BACKGROUND: u32 = 0x00_00_00_00;
+
+    pub fn new(clock_hz: u64) -> Self {
+        Self {
+            clock_hz,
+            visible: false,
+            last_sample: None,
+            host_frames: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Called once per frame shown on the host
+    pub fn update(&mut self, counters: Counters, now: Instant) {
+        self.host_frames += 1;
+
+        let (since, last) = match self.last_sample {
+            Some(sample) => sample,
+            None => {
+                self.last_sample = Some((now, counters));
+                self.host_frames = 0;
+                return;
+            }
+        };
+
+        let elapsed = now.saturating_duration_since(since);
+        if elapsed < Self::INTERVAL {
+            return;
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        let cycles = counters.cycles - last.cycles;
+        let frames = counters.frames - last.frames;
+
+        self.stats = Stats {
+            host_fps: self.host_frames as f64 / seconds,
+            emulated_mhz: cycles as f64 / seconds / 1e6,
+            instructions_per_frame: (counters.instructions - last.instructions).checked_div(frames).unwrap_or(0),
+            percent_of_real_time: 100.0 * cycles as f64 / self.clock_hz as f64 / seconds,
+        };
+
+        self.last_sample = Some((now, counters));
+        self.host_frames = 0;
+    }
+
+    /// Draws the statistics in the top left corner of `buffer`
+    pub fn draw(&self, buffer: &mut [u32], width: usize) {
+        let lines = [
+            format!("FPS {:.1}", self.stats.host_fps),
+            format!("MHZ {:.3}", self.stats.emulated_mhz),
+            format!("IPF {}", self.stats.instructions_per_frame),
+            format!("SPD {:.0}%", self.stats.percent_of_real_time),
+        ];
+
+        for (row, line) in lines.iter().enumerate() {
+            draw_text(buffer, width, 1, 1 + row * LINE_HEIGHT, line, Self::TEXT_COLOR, Self::BACKGROUND);
+        }
+    }
+}
+
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+/// Glyph plus a pixel of spacing
+const CHAR_WIDTH: usize = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 1;
+
+/// 3 x 5 glyphs, one row per byte from the top, bit 2 is the left pixel.
+/// Lower case is drawn as upper case, anything missing as a space.
+const FONT: &[(char, [u8; GLYPH_HEIGHT])] = &[
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+];
+
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    let c = c.to_ascii_uppercase();
+    FONT.iter()
+        .find(|&&(glyph_char, _)| glyph_char == c)
+        .map_or([0; GLYPH_HEIGHT], |&(_, rows)| rows)
+}
+
+/// Draws `text` with its top left corner at `x`, `y` on a solid background,
+/// clipped to the buffer
+pub fn draw_text(buffer: &mut [u32], width: usize, x: usize, y: usize, text: &str, color: u32, background: u32) {
+    let height = buffer.len() / width;
+
+    for (i, c) in text.chars().enumerate() {
+        let rows = glyph(c);
+        let left = x + i * CHAR_WIDTH;
+
+        // One pixel of background around each glyph keeps it readable over
+        // the game
+        for dy in 0..LINE_HEIGHT + 1 {
+            for dx in 0..CHAR_WIDTH + 1 {
+                let (px, py) = ((left + dx).wrapping_sub(1), (y + dy).wrapping_sub(1));
+                if px >= width || py >= height {
+                    continue;
+                }
+
+                let lit = (1..=GLYPH_WIDTH).contains(&dx)
+                    && (1..=GLYPH_HEIGHT).contains(&dy)
+                    && rows[dy - 1] & (0b100 >> (dx - 1)) != 0;
+                buffer[px + py * width] = if lit { color } else { background };
+            }
+        }
+    }
+}
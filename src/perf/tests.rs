@@ -0,0 +1,54 @@
+use std::time::{Duration, Instant};
+
+use crate::perf::{draw_text, Counters, PerfOverlay};
+
+#[test]
+fn test_stats_over_interval() {
+    let mut overlay = PerfOverlay::new(2_000_000);
+    let start = Instant::now();
+
+    overlay.update(Counters::default(), start);
+    for frame in 1..=30 {
+        let counters = Counters {
+            frames: frame,
+            cycles: frame * 20_000,
+            instructions: frame * 5_000,
+        };
+        overlay.update(counters, start + Duration::from_millis(frame * 20));
+    }
+
+    // 25 frames in 0.5 s, then nothing until the next interval is up
+    let stats = overlay.stats;
+    assert!((stats.host_fps - 50.0).abs() < 1e-9);
+    assert!((stats.emulated_mhz - 1.0).abs() < 1e-9);
+    assert_eq!(stats.instructions_per_frame, 5_000);
+    assert!((stats.percent_of_real_time - 50.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_draw_text() {
+    let width = 8;
+    let mut buffer = vec![0x123456; width * 8];
+    draw_text(&mut buffer, width, 1, 1, "1", 0xffffff, 0);
+
+    let rows: Vec<String> = buffer
+        .chunks(width)
+        .map(|row| {
+            row.iter()
+                .map(|&pixel| match pixel {
+                    0xffffff => '#',
+                    0 => '.',
+                    _ => ' ',
+                })
+                .collect()
+        })
+        .collect();
+
+    // Glyph on a background box, the rest untouched
+    assert_eq!(
+        rows,
+        vec![
+            ".....   ", "..#..   ", ".##..   ", "..#..   ", "..#..   ", ".###.   ", ".....   ", "        ",
+        ]
+    );
+}
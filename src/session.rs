@@ -0,0 +1,88 @@
+use crate::pacing::{Pacer, Speed};
+use crate::terminal::Console;
+
+#[cfg(test)]
+mod tests;
+
+// The run loop of the CP/M machines: run a slice, show the terminal, see
+// whether the host console wants out, and keep pace with the wall clock.
+
+/// A machine the run loop can drive
+pub trait Machine {
+    const CLOCK_HZ: u64;
+
+    fn run(&mut self, cycles: u64);
+    fn cycles(&self) -> u64;
+
+    /// Stopped of its own accord
+    fn is_finished(&self) -> bool {
+        false
+    }
+
+    /// Waiting at a prompt, so there is nothing more to do once piped input
+    /// has run out
+    fn is_idle(&self) -> bool {
+        false
+    }
+}
+
+/// Notices a program that has gone quiet: nothing sent for a second of
+/// emulated time
+pub struct QuietTimer {
+    clock_hz: u64,
+    sent: u64,
+    since: u64,
+}
+
+impl QuietTimer {
+    pub fn new(clock_hz: u64) -> Self {
+        Self {
+            clock_hz,
+            sent: 0,
+            since: 0,
+        }
+    }
+
+    /// Whether `sent`, a count of bytes sent, hasn't changed for a second
+    /// up to `cycles`
+    pub fn is_quiet(&mut self, sent: u64, cycles: u64) -> bool {
+        if sent != self.sent {
+            (self.sent, self.since) = (sent, cycles);
+            return false;
+        }
+        cycles - self.since > self.clock_hz
+    }
+}
+
+/// Runs `machine` until it finishes, the escape key is pressed or the
+/// terminal's window is closed. With `batch` it stops as soon as it is
+/// idle; with piped input, once the input has all been read and it is
+/// idle or has gone quiet.
+pub fn run<M: Machine>(machine: &mut M, console: &mut Console, batch: bool, uncapped: bool) {
+    let mut pacer = Pacer::new(M::CLOCK_HZ);
+    if uncapped {
+        pacer.set_speed(Speed::Uncapped, 0);
+    }
+    let mut quiet = QuietTimer::new(M::CLOCK_HZ);
+
+    while !machine.is_finished() {
+        // 10 ms between checks of the console and the wall clock
+        machine.run(M::CLOCK_HZ / 100);
+
+        if !console.update() {
+            break;
+        }
+
+        let sent = console.bytes_sent();
+        let host = console.host();
+        if host.take_escape() || (batch && machine.is_idle()) {
+            break;
+        }
+        if host.input_closed() && (machine.is_idle() || quiet.is_quiet(sent, machine.cycles())) {
+            break;
+        }
+        drop(host);
+
+        pacer.wait(machine.cycles());
+    }
+}
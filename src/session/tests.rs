@@ -0,0 +1,14 @@
+use crate::session::QuietTimer;
+
+#[test]
+fn test_quiet_timer() {
+    let mut quiet = QuietTimer::new(1000);
+    assert!(!quiet.is_quiet(0, 500));
+    assert!(!quiet.is_quiet(0, 1000));
+    assert!(quiet.is_quiet(0, 1001));
+
+    // Sending anything starts the second again
+    assert!(!quiet.is_quiet(5, 1500));
+    assert!(!quiet.is_quiet(5, 2500));
+    assert!(quiet.is_quiet(5, 2501));
+}
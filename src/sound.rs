@@ -0,0 +1,206 @@
+use std::io;
+use std::path::Path;
+
+use crate::wav::WavWriter;
+
+mod samples;
+pub use samples::SampleEngine;
+
+mod synth;
+pub use synth::SynthEngine;
+
+#[cfg(feature = "audio")]
+mod playback;
+#[cfg(feature = "audio")]
+pub use playback::Playback;
+
+#[cfg(test)]
+mod tests;
+
+/// Output rate of every sound engine and audio sink
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// Sound effects triggered by the Space Invaders output ports 3 and 5
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEffect {
+    Ufo,
+    Shot,
+    PlayerDeath,
+    InvaderDeath,
+    ExtraLife,
+    Fleet1,
+    Fleet2,
+    Fleet3,
+    Fleet4,
+    UfoHit,
+}
+
+impl SoundEffect {
+    pub const ALL: [SoundEffect; 10] = [
+        SoundEffect::Ufo,
+        SoundEffect::Shot,
+        SoundEffect::PlayerDeath,
+        SoundEffect::InvaderDeath,
+        SoundEffect::ExtraLife,
+        SoundEffect::Fleet1,
+        SoundEffect::Fleet2,
+        SoundEffect::Fleet3,
+        SoundEffect::Fleet4,
+        SoundEffect::UfoHit,
+    ];
+
+    /// Output port and bit that triggers the effect
+    pub fn port_bit(self) -> (u8, u8) {
+        match self {
+            SoundEffect::Ufo => (3, 0),
+            SoundEffect::Shot => (3, 1),
+            SoundEffect::PlayerDeath => (3, 2),
+            SoundEffect::InvaderDeath => (3, 3),
+            SoundEffect::ExtraLife => (3, 4),
+            SoundEffect::Fleet1 => (5, 0),
+            SoundEffect::Fleet2 => (5, 1),
+            SoundEffect::Fleet3 => (5, 2),
+            SoundEffect::Fleet4 => (5, 3),
+            SoundEffect::UfoHit => (5, 4),
+        }
+    }
+
+    /// File name of the effect in the standard Space Invaders sample set
+    pub fn sample_name(self) -> &'static str {
+        match self {
+            SoundEffect::Ufo => "0.wav",
+            SoundEffect::Shot => "1.wav",
+            SoundEffect::PlayerDeath => "2.wav",
+            SoundEffect::InvaderDeath => "3.wav",
+            SoundEffect::Fleet1 => "4.wav",
+            SoundEffect::Fleet2 => "5.wav",
+            SoundEffect::Fleet3 => "6.wav",
+            SoundEffect::Fleet4 => "7.wav",
+            SoundEffect::UfoHit => "8.wav",
+            SoundEffect::ExtraLife => "9.wav",
+        }
+    }
+
+    /// The UFO sound keeps playing for as long as its bit is held high,
+    /// everything else is a one-shot started on the rising edge.
+    pub fn is_looping(self) -> bool {
+        self == SoundEffect::Ufo
+    }
+}
+
+/// Something that can turn sound triggers into PCM samples
+pub trait SoundEngine {
+    fn start(&mut self, effect: SoundEffect);
+    fn stop(&mut self, effect: SoundEffect);
+
+    /// Port 3 bit 5 gates the amplifier on the sound board
+    fn set_enabled(&mut self, enabled: bool);
+
+    /// Fills `out` with mono samples at `SAMPLE_RATE`
+    fn render(&mut self, out: &mut [i16]);
+}
+
+/// Latches for output ports 3 and 5. Writes are edge-detected and forwarded
+/// to the sound engine as start/stop events.
+pub struct SoundPorts {
+    port3: u8,
+    port5: u8,
+    engine: Option<Box<dyn SoundEngine>>,
+}
+
+impl SoundPorts {
+    const AMP_ENABLE: u8 = 1 << 5;
+
+    pub fn new(mut engine: Option<Box<dyn SoundEngine>>) -> Self {
+        // The latches power up cleared, amplifier included
+        if let Some(engine) = engine.as_mut() {
+            engine.set_enabled(false);
+        }
+
+        Self {
+            port3: 0,
+            port5: 0,
+            engine,
+        }
+    }
+
+    pub fn write(&mut self, port: u8, value: u8) {
+        let previous = match port {
+            3 => std::mem::replace(&mut self.port3, value),
+            5 => std::mem::replace(&mut self.port5, value),
+            _ => panic!("Port {} is not a sound port", port),
+        };
+
+        let engine = match self.engine.as_mut() {
+            Some(engine) => engine,
+            None => return,
+        };
+
+        if port == 3 && (previous ^ value) & Self::AMP_ENABLE != 0 {
+            engine.set_enabled(value & Self::AMP_ENABLE != 0);
+        }
+
+        for effect in SoundEffect::ALL {
+            let (effect_port, bit) = effect.port_bit();
+            if effect_port != port {
+                continue;
+            }
+
+            let was_on = previous & (1 << bit) != 0;
+            let is_on = value & (1 << bit) != 0;
+            if is_on && !was_on {
+                engine.start(effect);
+            } else if was_on && !is_on {
+                engine.stop(effect);
+            }
+        }
+    }
+
+    /// Clears both latches, stopping every sound and the amplifier
+    pub fn reset(&mut self) {
+        self.write(3, 0);
+        self.write(5, 0);
+    }
+
+    /// Renders the next `out.len()` samples. Silence when there's no engine.
+    pub fn render(&mut self, out: &mut [i16]) {
+        match self.engine.as_mut() {
+            Some(engine) => engine.render(out),
+            None => out.fill(0),
+        }
+    }
+}
+
+/// Destination for rendered audio
+pub trait AudioSink {
+    fn write(&mut self, samples: &[i16]);
+}
+
+/// Records the audio into a WAV file, used when running headless
+pub struct WavSink {
+    writer: WavWriter<io::BufWriter<std::fs::File>>,
+}
+
+impl WavSink {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            writer: WavWriter::create(path, SAMPLE_RATE, 1)?,
+        })
+    }
+}
+
+impl AudioSink for WavSink {
+    fn write(&mut self, samples: &[i16]) {
+        self.writer
+            .write_samples(samples)
+            .unwrap_or_else(|e| println!("Failed to write audio: {}", e));
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        self.writer
+            .finalize()
+            .unwrap_or_else(|e| println!("Failed to finish WAV file: {}", e));
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use super::{AudioSink, SAMPLE_RATE};
+
+type Queue = Arc<Mutex<VecDeque<i16>>>;
+
+/// Real-time output through the host's default audio device
+pub struct Playback {
+    queue: Queue,
+    _stream: cpal::Stream,
+    /// Device rate / `SAMPLE_RATE`, used for nearest neighbour resampling
+    step: f64,
+    phase: f64,
+    max_queued: usize,
+}
+
+impl Playback {
+    pub fn open() -> Result<Self, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| "No audio output device".to_string())?;
+        let config = device.default_output_config().map_err(|e| e.to_string())?;
+
+        let channels = config.channels() as usize;
+        let rate = config.sample_rate().0;
+        let queue: Queue = Arc::new(Mutex::new(VecDeque::new()));
+
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build::<f32>(&device, &config.into(), channels, queue.clone()),
+            cpal::SampleFormat::I16 => build::<i16>(&device, &config.into(), channels, queue.clone()),
+            cpal::SampleFormat::U16 => build::<u16>(&device, &config.into(), channels, queue.clone()),
+        }
+        .map_err(|e| e.to_string())?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            queue,
+            _stream: stream,
+            step: rate as f64 / SAMPLE_RATE as f64,
+            phase: 0.0,
+            // Never let more than a quarter second of latency build up
+            max_queued: rate as usize / 4,
+        })
+    }
+}
+
+fn build<T: cpal::Sample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    channels: usize,
+    queue: Queue,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let mut queue = queue.lock().unwrap();
+            for frame in data.chunks_mut(channels) {
+                let sample = queue.pop_front().unwrap_or(0);
+                for out in frame.iter_mut() {
+                    *out = <T as cpal::Sample>::from(&sample);
+                }
+            }
+        },
+        |e| println!("Audio stream error: {}", e),
+    )
+}
+
+impl AudioSink for Playback {
+    fn write(&mut self, samples: &[i16]) {
+        let mut queue = self.queue.lock().unwrap();
+
+        for &sample in samples {
+            self.phase += self.step;
+            while self.phase >= 1.0 {
+                queue.push_back(sample);
+                self.phase -= 1.0;
+            }
+        }
+
+        while queue.len() > self.max_queued {
+            queue.pop_front();
+        }
+    }
+}
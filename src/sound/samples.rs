@@ -0,0 +1,119 @@
+use std::io;
+use std::path::Path;
+
+use super::{SoundEffect, SoundEngine, SAMPLE_RATE};
+use crate::wav::Wav;
+
+struct Voice {
+    effect: SoundEffect,
+    data: Vec<i16>,
+    /// Playback position, `None` when the voice is idle
+    position: Option<usize>,
+    gain: f32,
+}
+
+/// Plays back the recorded sample set through a simple mixer
+pub struct SampleEngine {
+    voices: Vec<Voice>,
+    enabled: bool,
+    volume: f32,
+}
+
+impl SampleEngine {
+    /// Loads `0.wav` .. `9.wav` from `dir`. Missing samples are reported and
+    /// left silent, as the extended play sample isn't in every set.
+    pub fn load<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        if !dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Sample directory {} not found", dir.display()),
+            ));
+        }
+
+        let mut samples = Vec::new();
+        for effect in SoundEffect::ALL {
+            let path = dir.join(effect.sample_name());
+            match Wav::open(&path) {
+                Ok(wav) => samples.push((effect, wav.resampled_mono(SAMPLE_RATE))),
+                Err(e) => println!("Could not load sample {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(Self::from_samples(samples))
+    }
+
+    /// Builds an engine from already decoded mono samples at `SAMPLE_RATE`
+    pub fn from_samples(samples: Vec<(SoundEffect, Vec<i16>)>) -> Self {
+        Self {
+            voices: samples
+                .into_iter()
+                .map(|(effect, data)| Voice {
+                    effect,
+                    data,
+                    position: None,
+                    gain: 1.0,
+                })
+                .collect(),
+            enabled: true,
+            volume: 0.5,
+        }
+    }
+
+    /// Master volume, 0.0 - 1.0
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+}
+
+impl SoundEngine for SampleEngine {
+    fn start(&mut self, effect: SoundEffect) {
+        for voice in self.voices.iter_mut().filter(|v| v.effect == effect) {
+            voice.position = Some(0);
+        }
+    }
+
+    fn stop(&mut self, effect: SoundEffect) {
+        // One-shots run to completion even after their bit drops
+        if !effect.is_looping() {
+            return;
+        }
+
+        for voice in self.voices.iter_mut().filter(|v| v.effect == effect) {
+            voice.position = None;
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for sample in out.iter_mut() {
+            let mut mixed = 0.0;
+
+            for voice in self.voices.iter_mut() {
+                let position = match voice.position {
+                    Some(position) if position < voice.data.len() => position,
+                    _ => continue,
+                };
+
+                mixed += voice.data[position] as f32 * voice.gain;
+
+                voice.position = if position + 1 < voice.data.len() {
+                    Some(position + 1)
+                } else if voice.effect.is_looping() {
+                    Some(0)
+                } else {
+                    None
+                };
+            }
+
+            *sample = if self.enabled {
+                (mixed * self.volume).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+            } else {
+                0
+            };
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use super::{SoundEffect, SoundEngine, SAMPLE_RATE};
+
+// Rough model of the Space Invaders analog sound board. The UFO and the
+// player explosion come from an SN76477 complex sound generator, the other
+// effects from discrete noise/tone circuits with RC decay envelopes.
+//
+// Only basic float arithmetic and an integer LFSR are used (no sin/exp), so
+// rendering is bit-exact across platforms and can be checked by hash.
+
+const DT: f32 = 1.0 / SAMPLE_RATE as f32;
+
+/// Exponential RC decay, `time` is roughly the time to fall to ~5%
+fn decay_coefficient(time: f32) -> f32 {
+    1.0 - 3.0 * DT / time
+}
+
+/// 17-bit LFSR noise source, like the one inside the SN76477
+struct Noise {
+    lfsr: u32,
+    clock: f32,
+    phase: f32,
+    output: f32,
+}
+
+impl Noise {
+    fn new(clock: f32) -> Self {
+        Self {
+            lfsr: 0x1_ffff,
+            clock,
+            phase: 0.0,
+            output: 1.0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.lfsr = 0x1_ffff;
+        self.phase = 0.0;
+    }
+
+    fn next(&mut self) -> f32 {
+        self.phase += self.clock * DT;
+        while self.phase >= 1.0 {
+            self.phase -= 1.0;
+            let bit = (self.lfsr ^ (self.lfsr >> 3)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (bit << 16);
+            self.output = if self.lfsr & 1 != 0 { 1.0 } else { -1.0 };
+        }
+        self.output
+    }
+}
+
+/// One pole low pass filter standing in for the RC networks on the board
+struct LowPass {
+    coefficient: f32,
+    state: f32,
+}
+
+impl LowPass {
+    fn new(cutoff: f32) -> Self {
+        // 2*pi*f*dt, good enough well below the Nyquist rate
+        let x = std::f32::consts::TAU * cutoff * DT;
+        Self {
+            coefficient: x / (1.0 + x),
+            state: 0.0,
+        }
+    }
+
+    fn next(&mut self, input: f32) -> f32 {
+        self.state += self.coefficient * (input - self.state);
+        self.state
+    }
+}
+
+/// Phase accumulating oscillator with triangle and square outputs
+#[derive(Default)]
+struct Oscillator {
+    phase: f32,
+}
+
+impl Oscillator {
+    fn advance(&mut self, frequency: f32) {
+        self.phase += frequency * DT;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+    }
+
+    fn square(&self) -> f32 {
+        if self.phase < 0.5 { 1.0 } else { -1.0 }
+    }
+
+    /// 0.0 .. 1.0 .. 0.0 over one period
+    fn triangle(&self) -> f32 {
+        if self.phase < 0.5 { 2.0 * self.phase } else { 2.0 - 2.0 * self.phase }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mixer {
+    Vco,
+    Noise,
+}
+
+/// Subset of the SN76477: SLF modulating the VCO, the noise generator with
+/// its filter, the mixer select and the envelope/one-shot.
+struct Sn76477 {
+    mixer: Mixer,
+    slf_frequency: f32,
+    vco_min: f32,
+    vco_max: f32,
+    /// `None` holds the output for as long as the chip is enabled, otherwise
+    /// the one-shot fires on enable and decays over this many seconds.
+    one_shot: Option<f32>,
+
+    slf: Oscillator,
+    vco: Oscillator,
+    noise: Noise,
+    noise_filter: LowPass,
+    level: f32,
+}
+
+impl Sn76477 {
+    fn enable(&mut self) {
+        self.level = 1.0;
+        self.slf = Oscillator::default();
+        self.vco = Oscillator::default();
+        self.noise.reset();
+    }
+
+    fn disable(&mut self) {
+        if self.one_shot.is_none() {
+            self.level = 0.0;
+        }
+    }
+
+    fn next(&mut self) -> f32 {
+        if self.level <= 0.0 {
+            return 0.0;
+        }
+
+        self.slf.advance(self.slf_frequency);
+        let frequency = self.vco_min + (self.vco_max - self.vco_min) * self.slf.triangle();
+        self.vco.advance(frequency);
+
+        let output = match self.mixer {
+            Mixer::Vco => self.vco.square(),
+            Mixer::Noise => self.noise_filter.next(self.noise.next()),
+        };
+
+        if let Some(time) = self.one_shot {
+            self.level *= decay_coefficient(time);
+            if self.level < 0.001 {
+                self.level = 0.0;
+            }
+        }
+
+        output * self.level
+    }
+}
+
+/// Decaying noise burst, used for the shot and invader death circuits
+struct NoiseBurst {
+    noise: Noise,
+    filter: LowPass,
+    decay: f32,
+    level: f32,
+}
+
+impl NoiseBurst {
+    fn new(clock: f32, cutoff: f32, time: f32) -> Self {
+        Self {
+            noise: Noise::new(clock),
+            filter: LowPass::new(cutoff),
+            decay: decay_coefficient(time),
+            level: 0.0,
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.level = 1.0;
+    }
+
+    fn next(&mut self) -> f32 {
+        if self.level <= 0.0 {
+            return 0.0;
+        }
+        let output = self.filter.next(self.noise.next()) * self.level;
+        self.level *= self.decay;
+        if self.level < 0.001 {
+            self.level = 0.0;
+        }
+        output
+    }
+}
+
+/// Square tone with an RC decay, optionally alternating between two
+/// frequencies (the UFO hit warble).
+struct Tone {
+    frequencies: (f32, f32),
+    alternate: f32,
+    decay: f32,
+
+    oscillator: Oscillator,
+    switch: Oscillator,
+    filter: LowPass,
+    level: f32,
+}
+
+impl Tone {
+    fn new(frequencies: (f32, f32), alternate: f32, time: f32) -> Self {
+        Self {
+            frequencies,
+            alternate,
+            decay: decay_coefficient(time),
+            oscillator: Oscillator::default(),
+            switch: Oscillator::default(),
+            filter: LowPass::new(2_000.0),
+            level: 0.0,
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.level = 1.0;
+        self.oscillator = Oscillator::default();
+        self.switch = Oscillator::default();
+    }
+
+    fn next(&mut self) -> f32 {
+        if self.level <= 0.0 {
+            return 0.0;
+        }
+
+        self.switch.advance(self.alternate);
+        let frequency = if self.switch.square() > 0.0 {
+            self.frequencies.0
+        } else {
+            self.frequencies.1
+        };
+        self.oscillator.advance(frequency);
+
+        // A zero frequency is a gap between beeps
+        let square = if frequency > 0.0 { self.oscillator.square() } else { 0.0 };
+        let output = self.filter.next(square) * self.level;
+        self.level *= self.decay;
+        if self.level < 0.001 {
+            self.level = 0.0;
+        }
+        output
+    }
+}
+
+/// Synthesized replacement for the sample set
+pub struct SynthEngine {
+    ufo: Sn76477,
+    player_death: Sn76477,
+    shot: NoiseBurst,
+    invader_death: NoiseBurst,
+    fleet: [Tone; 4],
+    ufo_hit: Tone,
+    extra_life: Tone,
+    enabled: bool,
+    volume: f32,
+}
+
+impl SynthEngine {
+    pub fn new() -> Self {
+        Self {
+            ufo: Sn76477 {
+                mixer: Mixer::Vco,
+                slf_frequency: 6.0,
+                vco_min: 380.0,
+                vco_max: 900.0,
+                one_shot: None,
+                slf: Oscillator::default(),
+                vco: Oscillator::default(),
+                noise: Noise::new(0.0),
+                noise_filter: LowPass::new(1.0),
+                level: 0.0,
+            },
+            player_death: Sn76477 {
+                mixer: Mixer::Noise,
+                slf_frequency: 0.0,
+                vco_min: 0.0,
+                vco_max: 0.0,
+                one_shot: Some(1.2),
+                slf: Oscillator::default(),
+                vco: Oscillator::default(),
+                noise: Noise::new(8_000.0),
+                noise_filter: LowPass::new(600.0),
+                level: 0.0,
+            },
+            shot: NoiseBurst::new(20_000.0, 3_000.0, 0.3),
+            invader_death: NoiseBurst::new(12_000.0, 1_500.0, 0.35),
+            fleet: [
+                Tone::new((98.0, 98.0), 0.0, 0.15),
+                Tone::new((87.0, 87.0), 0.0, 0.15),
+                Tone::new((78.0, 78.0), 0.0, 0.15),
+                Tone::new((69.0, 69.0), 0.0, 0.15),
+            ],
+            ufo_hit: Tone::new((560.0, 740.0), 14.0, 1.0),
+            extra_life: Tone::new((1_200.0, 0.0), 8.0, 0.8),
+            enabled: true,
+            volume: 1.0,
+        }
+    }
+
+    /// Master volume, 0.0 - 1.0
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    fn next(&mut self) -> f32 {
+        let mut mixed = 0.0;
+        mixed += 0.20 * self.ufo.next();
+        mixed += 0.45 * self.player_death.next();
+        mixed += 0.30 * self.shot.next();
+        mixed += 0.40 * self.invader_death.next();
+        for fleet in self.fleet.iter_mut() {
+            mixed += 0.35 * fleet.next();
+        }
+        mixed += 0.25 * self.ufo_hit.next();
+        mixed += 0.20 * self.extra_life.next();
+        mixed
+    }
+}
+
+impl SoundEngine for SynthEngine {
+    fn start(&mut self, effect: SoundEffect) {
+        match effect {
+            SoundEffect::Ufo => self.ufo.enable(),
+            SoundEffect::Shot => self.shot.trigger(),
+            SoundEffect::PlayerDeath => self.player_death.enable(),
+            SoundEffect::InvaderDeath => self.invader_death.trigger(),
+            SoundEffect::ExtraLife => self.extra_life.trigger(),
+            SoundEffect::Fleet1 => self.fleet[0].trigger(),
+            SoundEffect::Fleet2 => self.fleet[1].trigger(),
+            SoundEffect::Fleet3 => self.fleet[2].trigger(),
+            SoundEffect::Fleet4 => self.fleet[3].trigger(),
+            SoundEffect::UfoHit => self.ufo_hit.trigger(),
+        }
+    }
+
+    fn stop(&mut self, effect: SoundEffect) {
+        match effect {
+            SoundEffect::Ufo => self.ufo.disable(),
+            SoundEffect::PlayerDeath => self.player_death.disable(),
+            _ => {}
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        for sample in out.iter_mut() {
+            // The circuits keep running while the amplifier is muted
+            let value = self.next();
+            *sample = if self.enabled {
+                (value * self.volume * 32_767.0).clamp(-32_768.0, 32_767.0) as i16
+            } else {
+                0
+            };
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::sound::{SampleEngine, SoundEffect, SoundEngine, SoundPorts, SynthEngine};
+use crate::wav::Wav;
+
+/// Records the events it receives instead of making noise
+struct RecordingEngine {
+    events: Rc<RefCell<Vec<(SoundEffect, bool)>>>,
+    enabled: Rc<RefCell<Option<bool>>>,
+}
+
+impl SoundEngine for RecordingEngine {
+    fn start(&mut self, effect: SoundEffect) {
+        self.events.borrow_mut().push((effect, true));
+    }
+
+    fn stop(&mut self, effect: SoundEffect) {
+        self.events.borrow_mut().push((effect, false));
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        *self.enabled.borrow_mut() = Some(enabled);
+    }
+
+    fn render(&mut self, out: &mut [i16]) {
+        out.fill(0);
+    }
+}
+
+#[test]
+fn test_sound_ports_edge_detection() {
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let enabled = Rc::new(RefCell::new(None));
+    let mut ports = SoundPorts::new(Some(Box::new(RecordingEngine {
+        events: events.clone(),
+        enabled: enabled.clone(),
+    })));
+
+    // UFO + amp enable
+    ports.write(3, 0b0010_0001);
+    assert_eq!(*events.borrow(), vec![(SoundEffect::Ufo, true)]);
+    assert_eq!(*enabled.borrow(), Some(true));
+
+    // Writing the same value again must not retrigger anything
+    ports.write(3, 0b0010_0001);
+    assert_eq!(events.borrow().len(), 1);
+
+    // UFO off, shot on
+    ports.write(3, 0b0010_0010);
+    assert_eq!(events.borrow()[1..], [(SoundEffect::Ufo, false), (SoundEffect::Shot, true)]);
+
+    events.borrow_mut().clear();
+    ports.write(5, 0b0001_0100);
+    assert_eq!(*events.borrow(), vec![(SoundEffect::Fleet3, true), (SoundEffect::UfoHit, true)]);
+}
+
+#[test]
+fn test_sample_engine_one_shot_and_loop() {
+    let mut engine = SampleEngine::from_samples(vec![
+        (SoundEffect::Shot, vec![1000, 2000]),
+        (SoundEffect::Ufo, vec![100, 200, 300]),
+    ]);
+    engine.set_volume(1.0);
+
+    let mut out = [0i16; 4];
+    engine.start(SoundEffect::Shot);
+    engine.render(&mut out);
+    assert_eq!(out, [1000, 2000, 0, 0]);
+
+    // Looping sound keeps going until stopped, and mixes with the one-shot
+    engine.start(SoundEffect::Ufo);
+    engine.start(SoundEffect::Shot);
+    engine.render(&mut out);
+    assert_eq!(out, [1100, 2200, 300, 100]);
+
+    engine.stop(SoundEffect::Ufo);
+    engine.render(&mut out);
+    assert_eq!(out, [0, 0, 0, 0]);
+}
+
+#[test]
+fn test_sample_engine_amp_disable_and_clipping() {
+    let mut engine = SampleEngine::from_samples(vec![
+        (SoundEffect::Shot, vec![30000; 4]),
+        (SoundEffect::PlayerDeath, vec![30000; 4]),
+    ]);
+    engine.set_volume(1.0);
+
+    let mut out = [0i16; 2];
+    engine.start(SoundEffect::Shot);
+    engine.start(SoundEffect::PlayerDeath);
+    engine.render(&mut out);
+    assert_eq!(out, [i16::MAX, i16::MAX]);
+
+    engine.set_enabled(false);
+    engine.render(&mut out);
+    assert_eq!(out, [0, 0]);
+}
+
+#[test]
+fn test_wav_round_trip() {
+    let wav = Wav {
+        sample_rate: 11025,
+        channels: 1,
+        samples: vec![0, 1, -1, i16::MAX, i16::MIN],
+    };
+
+    let decoded = Wav::parse(&wav.to_bytes()).unwrap();
+    assert_eq!(decoded.sample_rate, 11025);
+    assert_eq!(decoded.channels, 1);
+    assert_eq!(decoded.samples, wav.samples);
+
+    // Resampling to double the rate doubles the length
+    assert_eq!(decoded.resampled_mono(22050).len(), 10);
+
+    let broken = Wav {
+        sample_rate: 0,
+        ..decoded
+    };
+    assert!(Wav::parse(&broken.to_bytes()).is_err());
+}
+
+/// FNV-1a over the little endian sample bytes
+fn pcm_hash(samples: &[i16]) -> u64 {
+    samples
+        .iter()
+        .flat_map(|s| s.to_le_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+/// Drives the synth through the ports with a fixed script, one entry per
+/// 735 sample (1/60 s) frame.
+fn render_synth_script(script: &[(u8, u8)]) -> Vec<i16> {
+    let mut ports = SoundPorts::new(Some(Box::new(SynthEngine::new())));
+    let mut pcm = Vec::new();
+    let mut frame = [0i16; 735];
+
+    for &(port3, port5) in script {
+        ports.write(3, port3);
+        ports.write(5, port5);
+        ports.render(&mut frame);
+        pcm.extend_from_slice(&frame);
+    }
+
+    pcm
+}
+
+#[test]
+fn test_synth_is_deterministic() {
+    let mut script = Vec::new();
+    // UFO flying for a second, with fleet steps every 8 frames
+    for frame in 0..60u8 {
+        script.push((0b0010_0001, 1 << ((frame / 8) % 4)));
+    }
+    // Shot, invader death, UFO hit and the player explosion
+    script.extend([(0b0010_0010, 0), (0b0010_0000, 0), (0b0010_1000, 0x10)]);
+    script.extend(std::iter::repeat_n((0b0010_0100, 0), 60));
+
+    let pcm = render_synth_script(&script);
+    assert_eq!(pcm.len(), script.len() * 735);
+    assert!(pcm.iter().any(|&s| s != 0));
+
+    // Same input, same output
+    assert_eq!(pcm_hash(&pcm), pcm_hash(&render_synth_script(&script)));
+
+    // Regression check, update when the circuit models are deliberately changed
+    assert_eq!(pcm_hash(&pcm), 0xa9f2_52cf_45fa_1453);
+}
+
+#[test]
+fn test_synth_volume() {
+    let mut loud = SynthEngine::new();
+    let mut quiet = SynthEngine::new();
+    quiet.set_volume(0.25);
+    let (mut loud_pcm, mut quiet_pcm) = (vec![0; 2000], vec![0; 2000]);
+    for (engine, pcm) in [(&mut loud, &mut loud_pcm), (&mut quiet, &mut quiet_pcm)] {
+        engine.start(SoundEffect::Ufo);
+        engine.render(pcm);
+    }
+
+    assert!(loud_pcm.iter().any(|&s| s.abs() > 1000));
+    for (loud, quiet) in loud_pcm.iter().zip(&quiet_pcm) {
+        assert!((*loud as i32 / 4 - *quiet as i32).abs() <= 1);
+    }
+}
+
+#[test]
+fn test_synth_amp_disabled_is_silent() {
+    let pcm = render_synth_script(&[(0b0000_0011, 0x0F); 10]);
+    assert!(pcm.iter().all(|&s| s == 0));
+}
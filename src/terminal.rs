@@ -0,0 +1,425 @@
+use std::cell::{Ref, RefCell};
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::altair::console::HostConsole;
+use crate::altair::{SerialLine, SharedLine};
+use crate::config;
+pub use window::TerminalWindow;
+
+mod font;
+#[cfg(test)]
+mod tests;
+mod window;
+
+// Video terminals for the CP/M machines' consoles. Full screen programs
+// such as WordStar are installed for a particular terminal and drive it
+// with escape sequences, which mean nothing to the host's terminal when
+// printed as they are. Terminal interprets them into a screen of
+// characters, which is then shown on the host terminal with ANSI escapes
+// or drawn in a window of its own.
+
+pub const COLUMNS: usize = 80;
+pub const ROWS: usize = 24;
+
+/// The characters on a terminal's screen, by row
+pub type Screen = [[u8; COLUMNS]; ROWS];
+
+const ESC: u8 = 0x1B;
+
+/// Which terminal's escape sequences to understand
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// The Lear Siegler ADM-3A, with the erase and line insert and delete
+    /// escapes the Kaypro and TeleVideo terminals added to it
+    Adm3a,
+    /// The DEC VT52
+    Vt52,
+}
+
+impl Kind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "adm3a" | "adm-3a" => Some(Kind::Adm3a),
+            "vt52" => Some(Kind::Vt52),
+            _ => None,
+        }
+    }
+
+    /// What an arrow key sends
+    fn arrow(self, arrow: Arrow) -> &'static [u8] {
+        match (self, arrow) {
+            (Kind::Adm3a, Arrow::Up) => b"\x0B",
+            (Kind::Adm3a, Arrow::Down) => b"\x0A",
+            (Kind::Adm3a, Arrow::Left) => b"\x08",
+            (Kind::Adm3a, Arrow::Right) => b"\x0C",
+            (Kind::Vt52, Arrow::Up) => b"\x1BA",
+            (Kind::Vt52, Arrow::Down) => b"\x1BB",
+            (Kind::Vt52, Arrow::Left) => b"\x1BD",
+            (Kind::Vt52, Arrow::Right) => b"\x1BC",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Arrow {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Where in an escape sequence the terminal is
+#[derive(Clone, Copy)]
+enum State {
+    Ground,
+    Escape,
+    /// Cursor addressing, waiting for the row and then the column, both
+    /// offset by 32
+    Row,
+    Column(usize),
+}
+
+/// A video terminal: an 80 x 24 screen that what the machine sends is
+/// written on, and a keyboard. As a serial line it receives the keys
+/// pressed on it, followed by those from another line, usually the host
+/// console.
+pub struct Terminal {
+    kind: Kind,
+    screen: Screen,
+    row: usize,
+    column: usize,
+    state: State,
+    /// Keys pressed, and the VT52's answers to being identified
+    keys: VecDeque<u8>,
+    keyboard: Option<SharedLine>,
+    bytes_received: u64,
+}
+
+impl Terminal {
+    pub fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            screen: [[b' '; COLUMNS]; ROWS],
+            row: 0,
+            column: 0,
+            state: State::Ground,
+            keys: VecDeque::new(),
+            keyboard: None,
+            bytes_received: 0,
+        }
+    }
+
+    /// A terminal that also takes keys from `keyboard`
+    pub fn with_keyboard(kind: Kind, keyboard: SharedLine) -> Self {
+        Self {
+            keyboard: Some(keyboard),
+            ..Self::new(kind)
+        }
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    /// Row and column of the cursor
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.column)
+    }
+
+    /// Bytes the machine has sent, for noticing when output stops
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// Types `key` on the terminal's keyboard
+    pub fn press(&mut self, key: u8) {
+        self.keys.push_back(key);
+    }
+
+    /// Types what `arrow` sends on this kind of terminal
+    pub fn press_arrow(&mut self, arrow: Arrow) {
+        self.keys.extend(self.kind.arrow(arrow));
+    }
+
+    /// The screen as text, a line per row without trailing spaces
+    #[cfg(test)]
+    pub fn snapshot(&self) -> String {
+        let lines: Vec<String> = self
+            .screen
+            .iter()
+            .map(|row| String::from_utf8_lossy(row).trim_end().to_string())
+            .collect();
+        lines.join("\n")
+    }
+
+    fn write(&mut self, byte: u8) {
+        // Strip the parity bit some programs set
+        let byte = byte & 0x7F;
+
+        match std::mem::replace(&mut self.state, State::Ground) {
+            State::Ground => self.control(byte),
+            State::Escape => self.escape(byte),
+            State::Row => self.state = State::Column(byte.wrapping_sub(32) as usize),
+            State::Column(row) => {
+                self.row = row.min(ROWS - 1);
+                self.column = (byte.wrapping_sub(32) as usize).min(COLUMNS - 1);
+            }
+        }
+    }
+
+    fn control(&mut self, byte: u8) {
+        match (self.kind, byte) {
+            (_, b' '..=b'~') => self.print(byte),
+            (_, 0x08) => self.column = self.column.saturating_sub(1),
+            (_, b'\t') => self.column = ((self.column / 8 + 1) * 8).min(COLUMNS - 1),
+            (_, b'\n') => self.line_feed(),
+            (_, b'\r') => self.column = 0,
+            (_, ESC) => self.state = State::Escape,
+            (Kind::Adm3a, 0x0B) => self.row = self.row.saturating_sub(1),
+            (Kind::Adm3a, 0x0C) => self.column = (self.column + 1).min(COLUMNS - 1),
+            (Kind::Adm3a, 0x1A) => self.clear(),
+            (Kind::Adm3a, 0x1E) => (self.row, self.column) = (0, 0),
+            // The bell, and anything else the terminal ignores
+            _ => {}
+        }
+    }
+
+    fn escape(&mut self, byte: u8) {
+        match (self.kind, byte) {
+            (Kind::Adm3a, b'=') | (Kind::Vt52, b'Y') => self.state = State::Row,
+            (Kind::Adm3a, b'T' | b't') | (Kind::Vt52, b'K') => self.erase_line(),
+            (Kind::Adm3a, b'Y' | b'y') | (Kind::Vt52, b'J') => self.erase_screen(),
+            (Kind::Adm3a, b'*' | b':' | b';') => self.clear(),
+            (Kind::Adm3a, b'E') => {
+                self.screen[self.row..].rotate_right(1);
+                self.screen[self.row] = [b' '; COLUMNS];
+            }
+            (Kind::Adm3a, b'R') => {
+                self.screen[self.row..].rotate_left(1);
+                self.screen[ROWS - 1] = [b' '; COLUMNS];
+            }
+            (Kind::Vt52, b'A') => self.row = self.row.saturating_sub(1),
+            (Kind::Vt52, b'B') => self.row = (self.row + 1).min(ROWS - 1),
+            (Kind::Vt52, b'C') => self.column = (self.column + 1).min(COLUMNS - 1),
+            (Kind::Vt52, b'D') => self.column = self.column.saturating_sub(1),
+            (Kind::Vt52, b'H') => (self.row, self.column) = (0, 0),
+            (Kind::Vt52, b'I') => {
+                if self.row == 0 {
+                    self.screen.rotate_right(1);
+                    self.screen[0] = [b' '; COLUMNS];
+                } else {
+                    self.row -= 1;
+                }
+            }
+            // Identify: a VT52 without a copier
+            (Kind::Vt52, b'Z') => self.keys.extend(b"\x1B/K"),
+            _ => {}
+        }
+    }
+
+    /// The ADM-3A goes on to the next line after the last column, the
+    /// VT52 stays there
+    fn print(&mut self, byte: u8) {
+        self.screen[self.row][self.column] = byte;
+        if self.column < COLUMNS - 1 {
+            self.column += 1;
+        } else if self.kind == Kind::Adm3a {
+            self.column = 0;
+            self.line_feed();
+        }
+    }
+
+    fn line_feed(&mut self) {
+        if self.row < ROWS - 1 {
+            self.row += 1;
+        } else {
+            self.screen.rotate_left(1);
+            self.screen[ROWS - 1] = [b' '; COLUMNS];
+        }
+    }
+
+    fn clear(&mut self) {
+        self.screen = [[b' '; COLUMNS]; ROWS];
+        (self.row, self.column) = (0, 0);
+    }
+
+    fn erase_line(&mut self) {
+        self.screen[self.row][self.column..].fill(b' ');
+    }
+
+    fn erase_screen(&mut self) {
+        self.erase_line();
+        self.screen[self.row + 1..].fill([b' '; COLUMNS]);
+    }
+}
+
+impl SerialLine for Terminal {
+    fn receive(&mut self) -> Option<u8> {
+        self.keys.pop_front().or_else(|| {
+            self.keyboard
+                .as_ref()
+                .and_then(|keyboard| keyboard.borrow_mut().receive())
+        })
+    }
+
+    fn send(&mut self, byte: u8) {
+        self.write(byte);
+        self.bytes_received += 1;
+    }
+}
+
+/// Shows a terminal's screen on the host terminal with ANSI escapes,
+/// rewriting only the rows that changed since the last time
+#[derive(Default)]
+pub struct AnsiScreen {
+    shown: Option<(Box<Screen>, (usize, usize))>,
+}
+
+impl AnsiScreen {
+    /// What to write to the host terminal to bring it up to date with
+    /// `terminal`, nothing if it already is
+    pub fn update(&mut self, terminal: &Terminal) -> Vec<u8> {
+        let mut output = Vec::new();
+        let cursor = terminal.cursor();
+        if self.shown.is_none() {
+            output.extend(b"\x1B[H\x1B[2J");
+        }
+
+        let mut changed = false;
+        for (row, text) in terminal.screen().iter().enumerate() {
+            if self.shown.as_ref().is_some_and(|(shown, _)| shown[row] == *text) {
+                continue;
+            }
+            let text = String::from_utf8_lossy(text);
+            output.extend(format!("\x1B[{};1H{}\x1B[K", row + 1, text.trim_end()).as_bytes());
+            changed = true;
+        }
+        if changed || self.shown.as_ref().is_some_and(|&(_, shown)| shown != cursor) {
+            output.extend(format!("\x1B[{};{}H", cursor.0 + 1, cursor.1 + 1).as_bytes());
+        }
+
+        self.shown = Some((Box::new(*terminal.screen()), cursor));
+        output
+    }
+}
+
+/// Where the front ends show a terminal
+pub enum Display {
+    Ansi(AnsiScreen),
+    Window(Box<TerminalWindow>),
+}
+
+impl Display {
+    /// A window, or else the host terminal
+    pub fn open(window: bool) -> Result<Self, String> {
+        Ok(match window {
+            true => Display::Window(Box::new(TerminalWindow::open()?)),
+            false => Display::Ansi(AnsiScreen::default()),
+        })
+    }
+
+    /// Shows `terminal`'s screen and passes it the keys pressed in the
+    /// window. False once the window has been closed.
+    pub fn update(&mut self, terminal: &mut Terminal) -> bool {
+        match self {
+            Display::Ansi(screen) => {
+                let output = screen.update(terminal);
+                if !output.is_empty() {
+                    let mut stdout = io::stdout();
+                    let _ = stdout.write_all(&output);
+                    let _ = stdout.flush();
+                }
+                true
+            }
+            Display::Window(window) => window.update(terminal),
+        }
+    }
+}
+
+impl Drop for Display {
+    /// Leaves the host cursor below the terminal's screen
+    fn drop(&mut self) {
+        if let Display::Ansi(_) = self {
+            print!("\x1B[{};1H", ROWS + 1);
+        }
+    }
+}
+
+/// The `--terminal` and `--window` options of the CP/M front ends
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    /// Terminal to show the console on, rather than printing what it sends
+    pub kind: Option<Kind>,
+    /// Show the terminal in a window
+    pub window: bool,
+}
+
+impl Options {
+    /// Applies `terminal` or `window`. False if `name` is neither.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> Result<bool, String> {
+        match name {
+            "terminal" => {
+                let value = value.ok_or(format!("{} needs a value", name))?;
+                let kind = Kind::from_name(value);
+                self.kind = Some(kind.ok_or(format!("Unknown terminal {}, expected adm3a or vt52", value))?);
+            }
+            "window" => self.window = value.map_or(Ok(true), config::parse_bool)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// A machine's console: the host's, or a terminal shown on it or in a
+/// window that takes the host's keys as well as its own
+pub struct Console {
+    /// Dropped before the host console, which restores the host terminal
+    shown: Option<(Rc<RefCell<Terminal>>, Display)>,
+    host: Rc<RefCell<HostConsole>>,
+}
+
+impl Console {
+    pub fn open(options: &Options) -> Result<Self, String> {
+        let host = Rc::new(RefCell::new(HostConsole::open().map_err(|e| e.to_string())?));
+        // A window needs a terminal in it, an ADM-3A unless told otherwise
+        let shown = match options.kind.or(options.window.then_some(Kind::Adm3a)) {
+            Some(kind) => Some((
+                Rc::new(RefCell::new(Terminal::with_keyboard(kind, host.clone()))),
+                Display::open(options.window)?,
+            )),
+            None => None,
+        };
+        Ok(Self { shown, host })
+    }
+
+    /// The serial line for the machine's console port
+    pub fn line(&self) -> SharedLine {
+        match &self.shown {
+            Some((terminal, _)) => terminal.clone(),
+            None => self.host.clone(),
+        }
+    }
+
+    pub fn host(&self) -> Ref<'_, HostConsole> {
+        self.host.borrow()
+    }
+
+    /// Bytes the machine has sent, for noticing when output stops
+    pub fn bytes_sent(&self) -> u64 {
+        match &self.shown {
+            Some((terminal, _)) => terminal.borrow().bytes_received(),
+            None => self.host.borrow().bytes_sent(),
+        }
+    }
+
+    /// Shows the terminal, if there is one. False once its window has been
+    /// closed.
+    pub fn update(&mut self) -> bool {
+        match &mut self.shown {
+            Some((terminal, display)) => display.update(&mut terminal.borrow_mut()),
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::altair::SerialLine;
+use crate::terminal::window::{self, HEIGHT, WIDTH};
+use crate::terminal::{AnsiScreen, Arrow, Kind, Terminal, COLUMNS, ROWS};
+use crate::testing::loopback;
+
+fn send(terminal: &mut Terminal, text: &[u8]) {
+    for &byte in text {
+        terminal.send(byte);
+    }
+}
+
+/// Lines of the screen, without the blank ones at the bottom
+fn lines(terminal: &Terminal) -> Vec<String> {
+    let snapshot = terminal.snapshot();
+    let mut lines: Vec<String> = snapshot.lines().map(String::from).collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn received(terminal: &mut Terminal) -> Vec<u8> {
+    std::iter::from_fn(|| terminal.receive()).collect()
+}
+
+#[test]
+fn test_adm3a_cursor_movement() {
+    let mut terminal = Terminal::new(Kind::Adm3a);
+    send(&mut terminal, b"garbage\r\n\x1A");
+    assert_eq!(terminal.snapshot(), "\n".repeat(ROWS - 1));
+    assert_eq!(terminal.cursor(), (0, 0));
+
+    // ESC = row column, each offset by 32
+    send(&mut terminal, b"\x1B=\x22\x25HELLO");
+    assert_eq!(terminal.cursor(), (2, 10));
+    // Up, right, left, backspace and home
+    send(&mut terminal, b"\x0B\x0C\x0C*\x08\x08\x08-\x1E>");
+    assert_eq!(lines(&terminal), [">", "          - *", "     HELLO"]);
+
+    // Parity is ignored, and addresses off the screen stop at its edge
+    send(&mut terminal, b"\x1B=\x7F\x7F");
+    assert_eq!(terminal.cursor(), (ROWS - 1, COLUMNS - 1));
+    send(&mut terminal, b"\x1E\xC1");
+    assert_eq!(terminal.screen()[0][0], b'A');
+    assert_eq!(terminal.bytes_received(), 35);
+}
+
+#[test]
+fn test_adm3a_wrap_and_scroll() {
+    let mut terminal = Terminal::new(Kind::Adm3a);
+    send(&mut terminal, &[b'x'; COLUMNS + 1]);
+    assert_eq!(terminal.cursor(), (1, 1));
+    assert_eq!(lines(&terminal), ["x".repeat(COLUMNS), "x".into()]);
+
+    // Line feeds on the bottom row scroll the screen up
+    send(&mut terminal, b"\x1A");
+    for i in 0..ROWS + 2 {
+        send(&mut terminal, format!("{}\r\n", i).as_bytes());
+    }
+    let snapshot = terminal.snapshot();
+    let lines: Vec<&str> = snapshot.lines().collect();
+    assert_eq!(lines[0], "3");
+    assert_eq!(lines[ROWS - 2], "25");
+    assert_eq!(terminal.cursor(), (ROWS - 1, 0));
+
+    // So does writing in the bottom right corner
+    send(&mut terminal, b"\x1B=\x37\x6Fz");
+    assert_eq!(terminal.cursor(), (ROWS - 1, 0));
+    assert_eq!(terminal.screen()[ROWS - 2][COLUMNS - 1], b'z');
+}
+
+#[test]
+fn test_adm3a_erasing() {
+    let mut terminal = Terminal::new(Kind::Adm3a);
+    send(&mut terminal, b"ONE\r\nTWO\r\nTHREE\r\nFOUR");
+
+    // Erase to the end of the line, then of the screen
+    send(&mut terminal, b"\x1B=\x20\x21\x1BT\x1B=\x22\x22\x1BY");
+    assert_eq!(lines(&terminal), ["O", "TWO", "TH"]);
+    // Insert a line, and delete it again along with the next
+    send(&mut terminal, b"\x1B=\x21\x20\x1BENEW");
+    assert_eq!(lines(&terminal), ["O", "NEW", "TWO", "TH"]);
+    send(&mut terminal, b"\x1BR\x1BR");
+    assert_eq!(lines(&terminal), ["O", "TH"]);
+    send(&mut terminal, b"\x1B*");
+    assert!(lines(&terminal).is_empty());
+    assert_eq!(terminal.cursor(), (0, 0));
+}
+
+#[test]
+fn test_vt52() {
+    let mut terminal = Terminal::new(Kind::Vt52);
+    send(
+        &mut terminal,
+        b"\x1BY\x25\x30HERE\x1BA\x1BA\x1BD^\x1BB\x1BB\x1BB\x1BC\x1BCv\x1BHTOP",
+    );
+    assert_eq!(
+        lines(&terminal),
+        [
+            "TOP",
+            "",
+            "",
+            &format!("{:>20}", "^"),
+            "",
+            &format!("{:>20}", "HERE"),
+            &format!("{:>23}", "v")
+        ]
+    );
+
+    // The ADM-3A's controls mean nothing to it
+    send(&mut terminal, b"\x1A\x1E\x0B\x0C\x1B=");
+    assert_eq!(terminal.cursor(), (0, 3));
+
+    // Erase to the end of the line and of the screen
+    send(&mut terminal, b"\x1BY\x20\x22\x1BK\x1BY\x25\x32\x1BJ");
+    assert_eq!(
+        lines(&terminal),
+        ["TO", "", "", &format!("{:>20}", "^"), "", &format!("{:>18}", "HE")]
+    );
+    send(&mut terminal, b"\x1BH\x1BJ");
+    assert!(lines(&terminal).is_empty());
+
+    // No wrapping at the end of a line, and a reverse line feed on the top
+    // row scrolls the screen down
+    send(&mut terminal, &[b'x'; COLUMNS + 2]);
+    assert_eq!(terminal.cursor(), (0, COLUMNS - 1));
+    send(&mut terminal, b"\r\x1BIabc");
+    assert_eq!(lines(&terminal), ["abc".to_string(), "x".repeat(COLUMNS)]);
+
+    // Identify
+    send(&mut terminal, b"\x1BZ");
+    assert_eq!(received(&mut terminal), b"\x1B/K");
+}
+
+#[test]
+fn test_keys() {
+    let keyboard = loopback(b"host");
+    let mut terminal = Terminal::with_keyboard(Kind::Adm3a, keyboard.clone());
+
+    // Keys pressed on the terminal come first
+    terminal.press(b'a');
+    terminal.press_arrow(Arrow::Up);
+    terminal.press_arrow(Arrow::Right);
+    assert_eq!(received(&mut terminal), b"a\x0B\x0Chost");
+
+    let mut terminal = Terminal::new(Kind::Vt52);
+    for arrow in [Arrow::Up, Arrow::Down, Arrow::Left, Arrow::Right] {
+        terminal.press_arrow(arrow);
+    }
+    assert_eq!(received(&mut terminal), b"\x1BA\x1BB\x1BD\x1BC");
+}
+
+#[test]
+fn test_ansi_screen() {
+    let mut terminal = Terminal::new(Kind::Adm3a);
+    let mut screen = AnsiScreen::default();
+    send(&mut terminal, b"A>");
+
+    // All of it the first time, with every row cleared after its text
+    let output = String::from_utf8(screen.update(&terminal)).unwrap();
+    assert!(output.starts_with("\x1B[H\x1B[2J\x1B[1;1HA>\x1B[K\x1B[2;1H\x1B[K"));
+    assert!(output.ends_with("\x1B[24;1H\x1B[K\x1B[1;3H"));
+    assert!(screen.update(&terminal).is_empty());
+
+    // After that only what changed
+    send(&mut terminal, b"\x1B=\x25\x20x\x1B=\x20\x20");
+    assert_eq!(screen.update(&terminal), b"\x1B[6;1Hx\x1B[K\x1B[1;1H");
+    send(&mut terminal, b"\x0C");
+    assert_eq!(screen.update(&terminal), b"\x1B[1;2H");
+}
+
+#[test]
+fn test_window_drawing() {
+    let mut terminal = Terminal::new(Kind::Adm3a);
+    send(&mut terminal, b"\x1B=\x21\x22A");
+    let mut buffer = vec![0x123456; WIDTH * HEIGHT];
+    window::draw(&mut buffer, terminal.screen(), terminal.cursor());
+
+    let lit: Vec<(usize, usize)> = (0..WIDTH * HEIGHT)
+        .filter(|&i| buffer[i] != 0)
+        .map(|i| (i % WIDTH, i / WIDTH))
+        .collect();
+    // The A's 18 pixels in the cell at column 2, row 1, and the cursor's
+    // underline after it
+    assert_eq!(lit.len(), 18 + 5);
+    assert!(lit.iter().all(|&(x, y)| (14..28).contains(&x) && (10..20).contains(&y)));
+    // Its apex, the middle three pixels of the top row
+    assert!(lit.starts_with(&[(16, 11), (17, 11), (18, 11)]));
+    assert_eq!(&lit[18..], &[(22, 19), (23, 19), (24, 19), (25, 19), (26, 19)]);
+}
@@ -0,0 +1,125 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use minifb::{InputCallback, Key, KeyRepeat, Window, WindowOptions};
+
+use super::font::FONT;
+use super::{Arrow, Screen, Terminal, COLUMNS, ROWS};
+
+/// Each character cell: the 5 x 7 glyph with space around it
+const CELL_WIDTH: usize = 7;
+const CELL_HEIGHT: usize = 10;
+pub const WIDTH: usize = COLUMNS * CELL_WIDTH;
+pub const HEIGHT: usize = ROWS * CELL_HEIGHT;
+
+const FOREGROUND: u32 = 0x40FF80;
+const BACKGROUND: u32 = 0x000000;
+
+/// A terminal's screen in a window of its own, with the window's keyboard
+/// as the terminal's
+pub struct TerminalWindow {
+    window: Window,
+    buffer: Vec<u32>,
+    /// Characters typed, from the window's input callback
+    typed: Rc<RefCell<Vec<u32>>>,
+}
+
+struct Typed(Rc<RefCell<Vec<u32>>>);
+
+impl InputCallback for Typed {
+    fn add_char(&mut self, uni_char: u32) {
+        self.0.borrow_mut().push(uni_char);
+    }
+}
+
+impl TerminalWindow {
+    pub fn open() -> Result<Self, String> {
+        let mut window = Window::new(
+            "8080-emulator terminal",
+            WIDTH,
+            HEIGHT,
+            WindowOptions {
+                scale: minifb::Scale::X2,
+                ..WindowOptions::default()
+            },
+        )
+        .map_err(|e| format!("Could not create window: {}", e))?;
+        // The machine's pacing sets the rate
+        window.limit_update_rate(None);
+
+        let typed = Rc::new(RefCell::new(Vec::new()));
+        window.set_input_callback(Box::new(Typed(typed.clone())));
+        Ok(Self {
+            window,
+            buffer: vec![BACKGROUND; WIDTH * HEIGHT],
+            typed,
+        })
+    }
+
+    /// Draws the screen and passes on the keys pressed. False once the
+    /// window has been closed, or Ctrl-] pressed in it.
+    pub fn update(&mut self, terminal: &mut Terminal) -> bool {
+        draw(&mut self.buffer, terminal.screen(), terminal.cursor());
+        if let Err(e) = self.window.update_with_buffer(&self.buffer, WIDTH, HEIGHT) {
+            println!("Failed to update window buffer: {}", e);
+        }
+
+        // Printable characters come from the input callback, which gives
+        // them with the host's keyboard layout, and everything else from
+        // the keys themselves
+        let control = self.window.is_key_down(Key::LeftCtrl) || self.window.is_key_down(Key::RightCtrl);
+        for c in self.typed.borrow_mut().drain(..) {
+            if !control && (0x20..0x7F).contains(&c) {
+                terminal.press(c as u8);
+            }
+        }
+        for key in self.window.get_keys_pressed(KeyRepeat::Yes) {
+            match key {
+                Key::Enter | Key::NumPadEnter => terminal.press(b'\r'),
+                Key::Backspace => terminal.press(0x08),
+                Key::Tab => terminal.press(b'\t'),
+                Key::Escape => terminal.press(0x1B),
+                Key::Delete => terminal.press(0x7F),
+                Key::Up => terminal.press_arrow(Arrow::Up),
+                Key::Down => terminal.press_arrow(Arrow::Down),
+                Key::Left => terminal.press_arrow(Arrow::Left),
+                Key::Right => terminal.press_arrow(Arrow::Right),
+                // The host console's escape key
+                Key::RightBracket if control => return false,
+                key if control && (Key::A as usize..=Key::Z as usize).contains(&(key as usize)) => {
+                    terminal.press((key as usize - Key::A as usize) as u8 + 1)
+                }
+                _ => {}
+            }
+        }
+
+        self.window.is_open()
+    }
+}
+
+/// Draws `screen` into `buffer`, `WIDTH` pixels wide, with an underline
+/// cursor
+pub fn draw(buffer: &mut [u32], screen: &Screen, cursor: (usize, usize)) {
+    for (row, text) in screen.iter().enumerate() {
+        for (column, &c) in text.iter().enumerate() {
+            let glyph = match c {
+                b' '..=b'~' => FONT[(c - b' ') as usize],
+                _ => FONT[0],
+            };
+            let (left, top) = (column * CELL_WIDTH, row * CELL_HEIGHT);
+
+            for y in 0..CELL_HEIGHT {
+                let bits = match y {
+                    1..=7 => glyph[y - 1],
+                    9 if (row, column) == cursor => 0b11111,
+                    _ => 0,
+                };
+                let line = &mut buffer[(top + y) * WIDTH + left..][..CELL_WIDTH];
+                for (x, pixel) in line.iter_mut().enumerate() {
+                    let lit = (1..=5).contains(&x) && bits & (0b10000 >> (x - 1)) != 0;
+                    *pixel = if lit { FOREGROUND } else { BACKGROUND };
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+// Test doubles shared by the machines' tests
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::altair::SerialLine;
+
+/// Serial line fed from a buffer, recording what is sent
+#[derive(Default)]
+pub struct Loopback {
+    pub input: VecDeque<u8>,
+    pub output: Vec<u8>,
+}
+
+impl SerialLine for Loopback {
+    fn receive(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn send(&mut self, byte: u8) {
+        self.output.push(byte);
+    }
+}
+
+impl Loopback {
+    /// What has been sent since the last time, as text
+    pub fn take_output(&mut self) -> String {
+        String::from_utf8_lossy(&std::mem::take(&mut self.output)).into_owned()
+    }
+}
+
+pub fn loopback(input: &[u8]) -> Rc<RefCell<Loopback>> {
+    Rc::new(RefCell::new(Loopback {
+        input: input.iter().copied().collect(),
+        output: Vec::new(),
+    }))
+}
+
+/// Output written somewhere else that the test can still look at
+#[derive(Clone, Default)]
+pub struct SharedBuffer(pub Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod tests;
+
+/// When VRAM is converted to pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Each line as the beam finishes it, showing mid-frame VRAM changes
+    /// (tearing, flicker) the way the monitor did
+    Scanline,
+    /// Half the screen at a time, cheaper but hides mid-frame updates
+    HalfFrame,
+}
+
+impl RenderMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "scanline" => Some(RenderMode::Scanline),
+            "half-frame" => Some(RenderMode::HalfFrame),
+            _ => None,
+        }
+    }
+}
+
+/// Position of the electron beam within the frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BeamPosition {
+    pub line: u32,
+    /// Horizontal position in pixel clocks from the start of the line
+    pub dot: u32,
+}
+
+/// Raster timing of the video hardware, driven by the CPU cycle count.
+/// Tells the machine when each scanline starts, so interrupts and rendering
+/// can happen where the beam actually is.
+pub struct VideoTiming {
+    pub clock_hz: u64,
+    pub cycles_per_line: u64,
+    /// Pixel clocks per line, including horizontal blanking
+    pub dots_per_line: u32,
+    pub lines_per_frame: u32,
+    pub visible_lines: u32,
+    /// RST instructions raised at the start of a line, as `(line, rst)`
+    interrupts: Vec<(u32, u16)>,
+    /// Cycles since power on
+    cycle: u64,
+}
+
+impl VideoTiming {
+    /// Midway 8080 boards: a 19.968 MHz crystal divided by 10 for the CPU
+    /// and by 4 for the 320 x 262 raster, so each line lasts 128 CPU cycles
+    /// and a frame 33,536 (59.54 Hz). RST 1 fires at line 96, RST 2 at the
+    /// start of vertical blank.
+    pub fn midway() -> Self {
+        Self {
+            clock_hz: 1_996_800,
+            cycles_per_line: 128,
+            dots_per_line: 320,
+            lines_per_frame: 262,
+            visible_lines: 224,
+            interrupts: vec![(96, 1), (224, 2)],
+            cycle: 0,
+        }
+    }
+
+    pub fn cycles_per_frame(&self) -> u64 {
+        self.cycles_per_line * self.lines_per_frame as u64
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        self.clock_hz as f64 / self.cycles_per_frame() as f64
+    }
+
+    pub fn beam(&self) -> BeamPosition {
+        let line_cycle = self.cycle % self.cycles_per_line;
+        BeamPosition {
+            line: ((self.cycle / self.cycles_per_line) % self.lines_per_frame as u64) as u32,
+            dot: (line_cycle * self.dots_per_line as u64 / self.cycles_per_line) as u32,
+        }
+    }
+
+    /// RST number raised when `line` starts, if any
+    pub fn interrupt_at(&self, line: u32) -> Option<u16> {
+        self.interrupts
+            .iter()
+            .find(|&&(at, _)| at == line)
+            .map(|&(_, rst)| rst)
+    }
+
+    /// Moves the beam forward by `cycles` and returns the scanlines that
+    /// started along the way, in order. Line 0 starting means a new frame.
+    pub fn advance(&mut self, cycles: u64) -> impl Iterator<Item = u32> {
+        let first = self.cycle / self.cycles_per_line + 1;
+        self.cycle += cycles;
+        let last = self.cycle / self.cycles_per_line;
+
+        let lines_per_frame = self.lines_per_frame as u64;
+        (first..=last).map(move |line| (line % lines_per_frame) as u32)
+    }
+}
@@ -0,0 +1,52 @@
+use crate::video::{BeamPosition, VideoTiming};
+
+#[test]
+fn test_midway_frame() {
+    let timing = VideoTiming::midway();
+    assert_eq!(timing.cycles_per_frame(), 33_536);
+    assert!((timing.frame_rate() - 59.54).abs() < 0.01);
+}
+
+#[test]
+fn test_interrupts_at_the_right_cycle() {
+    let mut timing = VideoTiming::midway();
+    let mut raised = Vec::new();
+    let mut cycle = 0;
+
+    // Step through a frame in 4 to 18 cycle instructions
+    while cycle < timing.cycles_per_frame() + 10 {
+        let cycles = 4 + cycle % 15;
+        cycle += cycles;
+        for line in timing.advance(cycles) {
+            if let Some(rst) = timing.interrupt_at(line) {
+                raised.push((rst, cycle));
+            }
+        }
+    }
+
+    assert_eq!(raised.len(), 2);
+
+    // Raised by the first instruction that reaches the line
+    let (rst, at) = raised[0];
+    assert_eq!(rst, 1);
+    assert!((96 * 128..96 * 128 + 18).contains(&at));
+
+    let (rst, at) = raised[1];
+    assert_eq!(rst, 2);
+    assert!((224 * 128..224 * 128 + 18).contains(&at));
+}
+
+#[test]
+fn test_beam_position_and_frame_wrap() {
+    let mut timing = VideoTiming::midway();
+    assert_eq!(timing.beam(), BeamPosition { line: 0, dot: 0 });
+
+    let lines: Vec<u32> = timing.advance(128 * 10 + 64).collect();
+    assert_eq!(lines, (1..=10).collect::<Vec<_>>());
+    assert_eq!(timing.beam(), BeamPosition { line: 10, dot: 160 });
+
+    let lines: Vec<u32> = timing.advance(128 * 252).collect();
+    assert_eq!(lines.first(), Some(&11));
+    assert_eq!(lines.last(), Some(&0));
+    assert_eq!(timing.beam().line, 0);
+}
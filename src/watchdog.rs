@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod tests;
+
+// Arcade boards reset themselves if the program stops writing to the
+// watchdog port, so a crash (or a bad ROM patch) ends in a reboot rather
+// than a frozen screen. The Midway boards count vertical blanks and reset
+// the CPU after 255 of them without a kick.
+
+pub struct Watchdog {
+    timeout: u32,
+    /// Frames since the last kick
+    frames: u32,
+}
+
+impl Watchdog {
+    /// Vertical blanks the Midway boards wait before resetting
+    pub const MIDWAY_FRAMES: u32 = 255;
+
+    pub fn new(timeout: u32) -> Self {
+        Self { timeout, frames: 0 }
+    }
+
+    /// Called when the program writes the watchdog port
+    pub fn kick(&mut self) {
+        self.frames = 0;
+    }
+
+    pub fn reset(&mut self) {
+        self.frames = 0;
+    }
+
+    /// Counts a frame, returns true when the machine should be reset
+    pub fn frame(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames < self.timeout {
+            return false;
+        }
+
+        self.frames = 0;
+        true
+    }
+
+    /// Frames without a kick before a reset
+    pub fn timeout(&self) -> u32 {
+        self.timeout
+    }
+}
@@ -0,0 +1,26 @@
+use crate::watchdog::Watchdog;
+
+#[test]
+fn test_kick_postpones_reset() {
+    let mut watchdog = Watchdog::new(3);
+
+    for _ in 0..10 {
+        assert!(!watchdog.frame());
+        assert!(!watchdog.frame());
+        watchdog.kick();
+    }
+}
+
+#[test]
+fn test_expires_and_rearms() {
+    let mut watchdog = Watchdog::new(3);
+
+    assert!(!watchdog.frame());
+    assert!(!watchdog.frame());
+    assert!(watchdog.frame());
+
+    // Starts counting again after the reset
+    assert!(!watchdog.frame());
+    assert!(!watchdog.frame());
+    assert!(watchdog.frame());
+}
@@ -0,0 +1,188 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// Minimal RIFF/WAVE support: uncompressed 8-bit and 16-bit PCM only, which
+// covers the sample packs and everything we write ourselves.
+
+/// Decoded PCM data. Samples are interleaved when `channels` > 1.
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<i16>,
+}
+
+impl Wav {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        Self::parse(&bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(invalid("not a RIFF/WAVE file"));
+        }
+
+        let mut format: Option<(u16, u16, u32, u16)> = None;
+        let mut offset = 12;
+
+        while offset + 8 <= bytes.len() {
+            let id = &bytes[offset..offset + 4];
+            let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()) as usize;
+            let body = &bytes[offset + 8..(offset + 8 + size).min(bytes.len())];
+
+            match id {
+                b"fmt " => {
+                    if body.len() < 16 {
+                        return Err(invalid("truncated fmt chunk"));
+                    }
+                    let tag = u16::from_le_bytes([body[0], body[1]]);
+                    let channels = u16::from_le_bytes([body[2], body[3]]);
+                    let sample_rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+                    let bits = u16::from_le_bytes([body[14], body[15]]);
+                    format = Some((tag, channels, sample_rate, bits));
+                }
+                b"data" => {
+                    let (tag, channels, sample_rate, bits) =
+                        format.ok_or_else(|| invalid("data chunk before fmt chunk"))?;
+                    if tag != 1 {
+                        return Err(invalid("only PCM files are supported"));
+                    }
+                    if sample_rate == 0 {
+                        return Err(invalid("sample rate is 0"));
+                    }
+
+                    let samples = match bits {
+                        8 => body.iter().map(|&b| ((b as i16) - 128) << 8).collect(),
+                        16 => body
+                            .chunks_exact(2)
+                            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                            .collect(),
+                        _ => return Err(invalid("only 8 and 16 bit samples are supported")),
+                    };
+
+                    return Ok(Self { sample_rate, channels, samples });
+                }
+                _ => {}
+            }
+
+            // Chunks are padded to an even length
+            offset += 8 + size + (size & 1);
+        }
+
+        Err(invalid("no data chunk"))
+    }
+
+    /// Returns the samples mixed down to a single channel
+    pub fn to_mono(&self) -> Vec<i16> {
+        if self.channels <= 1 {
+            return self.samples.clone();
+        }
+
+        self.samples
+            .chunks(self.channels as usize)
+            .map(|frame| (frame.iter().map(|&s| s as i32).sum::<i32>() / frame.len() as i32) as i16)
+            .collect()
+    }
+
+    /// Returns mono samples resampled (linearly) to `rate`
+    pub fn resampled_mono(&self, rate: u32) -> Vec<i16> {
+        let mono = self.to_mono();
+        if self.sample_rate == rate || mono.is_empty() {
+            return mono;
+        }
+
+        let length = (mono.len() as u64 * rate as u64 / self.sample_rate as u64) as usize;
+        let step = self.sample_rate as f64 / rate as f64;
+
+        (0..length)
+            .map(|i| {
+                let position = i as f64 * step;
+                let index = position as usize;
+                let fraction = position - index as f64;
+                let a = mono[index] as f64;
+                let b = *mono.get(index + 1).unwrap_or(&mono[index]) as f64;
+                (a + (b - a) * fraction) as i16
+            })
+            .collect()
+    }
+
+    /// Encodes the samples as a 16-bit PCM file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(44 + self.samples.len() * 2);
+        write_header(&mut bytes, self.sample_rate, self.channels, self.samples.len() as u32 * 2)
+            .expect("Writing to a Vec cannot fail");
+        for sample in &self.samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        File::create(path)?.write_all(&self.to_bytes())
+    }
+}
+
+/// Streams 16-bit PCM samples into a file, patching the header sizes when
+/// finished so the length does not have to be known up front.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    data_bytes: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate, channels)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        write_header(&mut out, sample_rate, channels, 0)?;
+        Ok(Self { out, data_bytes: 0 })
+    }
+
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        for sample in samples {
+            self.out.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_bytes += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    /// Fixes up the RIFF and data chunk sizes. The file is valid (but
+    /// reports an empty data chunk) until this has been called.
+    pub fn finalize(&mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + self.data_bytes).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&self.data_bytes.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()
+    }
+}
+
+fn write_header<W: Write>(out: &mut W, sample_rate: u32, channels: u16, data_bytes: u32) -> io::Result<()> {
+    let block_align = channels * 2;
+
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_bytes).to_le_bytes())?;
+    out.write_all(b"WAVE")?;
+
+    out.write_all(b"fmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&channels.to_le_bytes())?;
+    out.write_all(&sample_rate.to_le_bytes())?;
+    out.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    out.write_all(&block_align.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+
+    out.write_all(b"data")?;
+    out.write_all(&data_bytes.to_le_bytes())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}